@@ -0,0 +1,332 @@
+//! Compiling a program into an object file. The `start` function of the starting module and every
+//! function it can call are translated with [`vm::codegen`], so they all have to be supported by
+//! it. Calls to the standard library go to the runtime, which is linked in afterwards, along with
+//! allocations, string literals and traps.
+use std::collections::HashMap;
+use anyhow::{anyhow, bail, Context, Result};
+use cranelift_codegen::ir::{self as cl, condcodes::IntCC, types::{I32, I64}, AbiParam, FuncRef, GlobalValue, InstBuilder, TrapCode};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use ir::Trap;
+use vm::codegen::{self, branch_out, spill, Allocation, Location, ValueType};
+use vm::{Function, World};
+
+/// The functions of the standard library that the runtime provides
+const NATIVES: &[&str] = &[
+    "std::io::print", "std::io::println", "std::io::eprint", "std::io::eprintln", "std::io::read_line",
+    "std::string::len", "std::string::concat", "std::string::eq", "std::string::substring",
+    "std::string::find", "std::string::parse_int", "std::string::from_u64", "std::string::from_i64",
+    "std::string::from_bool",
+    "std::env::arg_count", "std::env::arg", "std::env::var", "std::env::has_var",
+    "std::math::abs", "std::math::min", "std::math::max",
+    "std::time::now_millis", "std::time::elapsed_nanos", "std::time::sleep_millis"
+];
+
+/// The functions in the runtime that compiled code calls
+struct Runtime {
+    /// `fn(rt, size: u64) -> u64`, returning 0 if the heap is full
+    alloc: FuncId,
+    /// `fn(rt, el_size: u64, count: u64) -> u64`, returning 0 if the heap is full
+    alloc_array: FuncId,
+    /// `fn(rt, data: *const u8, len: u64) -> u64`, returning 0 if the heap is full
+    string: FuncId,
+    /// `fn(rt, el_size: u64) -> u64` makes the array of program arguments
+    args: FuncId,
+    /// `fn(rt, message: *const c_char, function: *const c_char, block: u64, instr: u64) -> !`
+    trap: FuncId
+}
+
+/// The symbols in the object file, which translated functions refer to
+struct Symbols {
+    module: ObjectModule,
+    runtime: Runtime,
+    /// the IR functions that have been declared, and those of them still to be compiled
+    functions: HashMap<ir::Path, FuncId>,
+    queue: Vec<ir::Path>,
+    natives: HashMap<ir::Path, FuncId>,
+    /// read only data, such as string literals and the names of functions
+    data: HashMap<Vec<u8>, DataId>
+}
+
+impl Symbols {
+    fn import(module: &mut ObjectModule, name: &str, params: &[cl::Type], ret: Option<cl::Type>) -> Result<FuncId> {
+        let mut sig = module.make_signature();
+        sig.params.extend(params.iter().map(|t| AbiParam::new(*t)));
+        sig.returns.extend(ret.map(AbiParam::new));
+        Ok(module.declare_function(name, Linkage::Import, &sig)?)
+    }
+
+    /// the function for a path, declaring it if it is new
+    fn function(&mut self, world: &World, path: &ir::Path) -> Result<(FuncId, Function)> {
+        let f = world.get_function(path)?.ok_or_else(|| anyhow!("function {} not found", path))?;
+        let sig = codegen::signature(&self.module);
+        let id = match &f {
+            Function::Ir { .. } => match self.functions.get(path) {
+                Some(id) => *id,
+                None => {
+                    let id = self.module.declare_function(&path.to_string(), Linkage::Local, &sig)?;
+                    self.functions.insert(path.clone(), id);
+                    self.queue.push(path.clone());
+                    id
+                }
+            },
+            Function::Native { .. } => match self.natives.get(path) {
+                Some(id) => *id,
+                None => {
+                    if !NATIVES.contains(&path.to_string().as_str()) {
+                        bail!("native function {} is not in the runtime", path);
+                    }
+                    let symbol = format!("oxlr_{}", path.0.iter().map(|s| s.0.as_str()).collect::<Vec<_>>().join("_"));
+                    let id = self.module.declare_function(&symbol, Linkage::Import, &sig)?;
+                    self.natives.insert(path.clone(), id);
+                    id
+                }
+            }
+        };
+        Ok((id, f))
+    }
+
+    /// a read only data object holding `bytes`
+    fn data(&mut self, bytes: &[u8]) -> Result<DataId> {
+        if let Some(id) = self.data.get(bytes) {
+            return Ok(*id);
+        }
+        let id = self.module.declare_anonymous_data(false, false)?;
+        let mut desc = DataDescription::new();
+        desc.define(bytes.into());
+        self.module.define_data(id, &desc)?;
+        self.data.insert(bytes.to_vec(), id);
+        Ok(id)
+    }
+
+    /// a NUL terminated string, for passing to the runtime
+    fn c_string(&mut self, s: &str) -> Result<DataId> {
+        self.data(format!("{}\0", s).as_bytes())
+    }
+}
+
+/// A compiler for programs that run on the runtime
+pub struct Compiler<'w> {
+    world: &'w World,
+    symbols: Symbols,
+    ctx: cranelift_codegen::Context,
+    fctx: FunctionBuilderContext
+}
+
+impl<'w> Compiler<'w> {
+    /// make a compiler for the machine it is running on, producing an object file called `name`
+    pub fn new(world: &'w World, name: &str) -> Result<Compiler<'w>> {
+        let mut flags = settings::builder();
+        flags.set("is_pic", "true")?;
+        flags.set("opt_level", "speed")?;
+        let isa = cranelift_native::builder().map_err(|e| anyhow!("unsupported host: {}", e))?
+            .finish(settings::Flags::new(flags))?;
+        if isa.pointer_type() != I64 {
+            bail!("compiling to native code needs 64 bit pointers");
+        }
+        let mut module = ObjectModule::new(ObjectBuilder::new(isa, name, cranelift_module::default_libcall_names())?);
+        let runtime = Runtime {
+            alloc: Symbols::import(&mut module, "oxlr_rt_alloc", &[I64, I64], Some(I64))?,
+            alloc_array: Symbols::import(&mut module, "oxlr_rt_alloc_array", &[I64, I64, I64], Some(I64))?,
+            string: Symbols::import(&mut module, "oxlr_rt_string", &[I64, I64, I64], Some(I64))?,
+            args: Symbols::import(&mut module, "oxlr_rt_args", &[I64, I64], Some(I64))?,
+            trap: Symbols::import(&mut module, "oxlr_rt_trap", &[I64, I64, I64, I64, I64], None)?
+        };
+        Ok(Compiler {
+            world,
+            ctx: module.make_context(),
+            symbols: Symbols { module, runtime, functions: HashMap::new(), queue: Vec::new(), natives: HashMap::new(), data: HashMap::new() },
+            fctx: FunctionBuilderContext::new()
+        })
+    }
+
+    /// compile the program that starts in `module`, returning the object file
+    pub fn compile(mut self, module: &ir::Path) -> Result<Vec<u8>> {
+        let mut start = module.clone();
+        start.0.push(ir::Symbol("start".into()));
+        let (start_id, f) = self.symbols.function(self.world, &start)?;
+        if f.body().is_none() {
+            bail!("{} must not be a native function", start);
+        }
+        let sig = f.signature();
+        let args_type = ir::Type::Array(Box::new(vm::native::string_type()));
+        let takes_args = match sig.args.as_slice() {
+            [] => false,
+            [(ty, _)] if *ty == args_type => true,
+            _ => bail!("{} must take no arguments or a single array of strings ({:?}), but takes {:?}", start, args_type, sig.args)
+        };
+        let returns_int = match sig.return_type {
+            ir::Type::Unit => false,
+            ir::Type::Int { .. } => true,
+            _ => bail!("{} must return nothing or an integer, but returns {:?}", start, sig.return_type)
+        };
+        while let Some(path) = self.symbols.queue.pop() {
+            self.function(&path).with_context(|| format!("compiling {}", path))?;
+        }
+        self.main(start_id, takes_args.then(|| self.world.size_of_type(&args_type)).transpose()?, returns_int)?;
+        let product = self.symbols.module.finish();
+        product.emit().map_err(|e| anyhow!("writing object file: {}", e))
+    }
+
+    fn function(&mut self, path: &ir::Path) -> Result<()> {
+        let f = self.world.get_function(path)?.ok_or_else(|| anyhow!("function {} not found", path))?;
+        let body = f.body().expect("only IR functions are compiled");
+        let id = self.symbols.functions[path];
+        self.ctx.func.signature = codegen::signature(&self.symbols.module);
+        let name = self.symbols.c_string(&path.to_string())?;
+        let mut backend = AotBackend { world: self.world, symbols: &mut self.symbols, name, funcs: HashMap::new(), globals: HashMap::new() };
+        let translated = codegen::translate(&mut self.ctx.func, &mut self.fctx, self.world, f.signature(), body, &mut backend);
+        if let Err(e) = translated {
+            self.symbols.module.clear_context(&mut self.ctx);
+            return Err(e);
+        }
+        let defined = self.symbols.module.define_function(id, &mut self.ctx);
+        self.symbols.module.clear_context(&mut self.ctx);
+        defined.map_err(|e| anyhow!("compiling to native code failed: {:?}", e))?;
+        log::debug!("compiled {}", path);
+        Ok(())
+    }
+
+    /// define `oxlr_main`, which the runtime calls to run the program. It calls `start`, passing
+    /// the program arguments in an array whose elements are `args` bytes apart if it takes them,
+    /// and returns its result as the exit code
+    fn main(&mut self, start: FuncId, args: Option<usize>, returns_int: bool) -> Result<()> {
+        let mut sig = self.symbols.module.make_signature();
+        sig.params.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I32));
+        let id = self.symbols.module.declare_function("oxlr_main", Linkage::Export, &sig)?;
+        self.ctx.func.signature = sig;
+        {
+            let mut b = FunctionBuilder::new(&mut self.ctx.func, &mut self.fctx);
+            let entry = b.create_block();
+            b.append_block_params_for_function_params(entry);
+            b.switch_to_block(entry);
+            let rt = b.block_params(entry)[0];
+            let mut values = Vec::new();
+            if let Some(el_size) = args {
+                let f = self.symbols.module.declare_func_in_func(self.symbols.runtime.args, b.func);
+                let el_size = b.ins().iconst(I64, el_size as i64);
+                let call = b.ins().call(f, &[rt, el_size]);
+                values.push(b.inst_results(call)[0]);
+            }
+            let slot = spill(&mut b, &values);
+            let addr = b.ins().stack_addr(I64, slot, 0);
+            let f = self.symbols.module.declare_func_in_func(start, b.func);
+            // traps stop the program in the runtime, so start always returns
+            b.ins().call(f, &[rt, addr]);
+            let code = if returns_int {
+                // truncated like a C exit status, as the VM does
+                let rv = b.ins().stack_load(I64, slot, 0);
+                b.ins().ireduce(I32, rv)
+            } else {
+                b.ins().iconst(I32, 0)
+            };
+            b.ins().return_(&[code]);
+            b.seal_all_blocks();
+            b.finalize();
+        }
+        let defined = self.symbols.module.define_function(id, &mut self.ctx);
+        self.symbols.module.clear_context(&mut self.ctx);
+        defined.map_err(|e| anyhow!("compiling oxlr_main failed: {:?}", e))?;
+        Ok(())
+    }
+}
+
+/// Compiles calls, allocations, string literals and traps in one function into calls to other
+/// functions in the object file and to the runtime
+struct AotBackend<'a> {
+    world: &'a World,
+    symbols: &'a mut Symbols,
+    /// the name of the function, for traps
+    name: DataId,
+    /// the functions and data imported into the function so far
+    funcs: HashMap<FuncId, FuncRef>,
+    globals: HashMap<DataId, GlobalValue>
+}
+
+impl AotBackend<'_> {
+    fn call_runtime(&mut self, b: &mut FunctionBuilder, f: FuncId, args: &[cl::Value]) -> Option<cl::Value> {
+        let f = *self.funcs.entry(f).or_insert_with(|| self.symbols.module.declare_func_in_func(f, b.func));
+        let call = b.ins().call(f, args);
+        b.inst_results(call).first().copied()
+    }
+
+    fn address(&mut self, b: &mut FunctionBuilder, data: DataId) -> cl::Value {
+        let gv = *self.globals.entry(data).or_insert_with(|| self.symbols.module.declare_data_in_func(data, b.func));
+        b.ins().symbol_value(I64, gv)
+    }
+
+    /// stop the program with `message`. Ends the current block
+    fn stop(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, message: &str) -> Result<()> {
+        let message = self.symbols.c_string(message)?;
+        let message = self.address(b, message);
+        let name = self.address(b, self.name);
+        let block = b.ins().iconst(I64, location.0 as i64);
+        let instr = b.ins().iconst(I64, location.1 as i64);
+        self.call_runtime(b, self.symbols.runtime.trap, &[context, message, name, block, instr]);
+        // the runtime exits instead of returning
+        b.ins().trap(TrapCode::unwrap_user(1));
+        Ok(())
+    }
+
+    /// stop the program if the address returned by an allocation is 0
+    fn check_alloc(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, addr: cl::Value) -> Result<()> {
+        let full = b.ins().icmp_imm(IntCC::Equal, addr, 0);
+        let mut stopped = Ok(());
+        branch_out(b, full, |b| stopped = self.stop(b, context, location, "memory exhausted"));
+        stopped
+    }
+}
+
+impl codegen::Backend for AotBackend<'_> {
+    fn call(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, callee: &ir::Path,
+        args: &[(cl::Value, ValueType)], _ret: &ValueType) -> Result<cl::Value>
+    {
+        let (id, f) = self.symbols.function(self.world, callee)?;
+        let params = f.signature().args.iter().map(|(ty, _)| ValueType::of_signature(ty)).collect::<Result<Vec<_>>>()?;
+        if !params.iter().eq(args.iter().map(|(_, t)| t)) {
+            bail!("call to {} with arguments of types {:?} does not match its signature",
+                callee, args.iter().map(|(_, t)| t).collect::<Vec<_>>());
+        }
+        let slot = spill(b, &args.iter().map(|(v, _)| *v).collect::<Vec<_>>());
+        let addr = b.ins().stack_addr(I64, slot, 0);
+        let status = self.call_runtime(b, id, &[context, addr]).expect("functions return a status");
+        // compiled functions stop the program when they trap, but native functions return
+        if let Function::Native { .. } = f {
+            let mut stopped = Ok(());
+            branch_out(b, status, |b| stopped = self.stop(b, context, location, &format!("native function {} failed", callee)));
+            stopped?;
+        }
+        Ok(b.ins().stack_load(I64, slot, 0))
+    }
+
+    fn alloc(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, alloc: Allocation) -> Result<cl::Value> {
+        let addr = match alloc {
+            Allocation::Value { size, .. } => {
+                let size = b.ins().iconst(I64, size as i64);
+                self.call_runtime(b, self.symbols.runtime.alloc, &[context, size])
+            },
+            Allocation::Array { el_size, count, .. } => {
+                let el_size = b.ins().iconst(I64, el_size as i64);
+                self.call_runtime(b, self.symbols.runtime.alloc_array, &[context, el_size, count])
+            }
+        }.expect("allocations return an address");
+        self.check_alloc(b, context, location, addr)?;
+        Ok(addr)
+    }
+
+    fn string(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, s: &str) -> Result<cl::Value> {
+        let data = self.symbols.data(s.as_bytes())?;
+        let data = self.address(b, data);
+        let len = b.ins().iconst(I64, s.len() as i64);
+        let addr = self.call_runtime(b, self.symbols.runtime.string, &[context, data, len]).expect("strings return an address");
+        self.check_alloc(b, context, location, addr)?;
+        Ok(addr)
+    }
+
+    fn trap(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, _trap: Trap, message: &'static str) -> Result<()> {
+        self.stop(b, context, location, message)
+    }
+}
@@ -0,0 +1,89 @@
+//! `oxlr-aot`: compile a module and everything its `start` function calls to native code ahead of
+//! time, producing an object file or an executable linked against the runtime in `oxlr-rt`.
+use std::path::PathBuf;
+use std::process::Command;
+use anyhow::{anyhow, bail, Context, Result};
+use vm::{World, SearchPath, stdlib};
+
+mod compile;
+
+/// the system libraries that the runtime needs, from `rustc --print native-static-libs`
+const RUNTIME_LIBS: &[&str] = &["-lgcc_s", "-lutil", "-lrt", "-lpthread", "-lm", "-ldl", "-lc"];
+
+/// What to write
+enum Emit { Object, Executable }
+
+fn main() -> Result<()> {
+    // the object backend logs every function it compiles at info level, so only show that when asked
+    env_logger::Builder::new()
+        .filter_module("cranelift_object", log::LevelFilter::Warn)
+        .parse_default_env()
+        .init();
+    let mut flags = Vec::new();
+    let mut start_mod_path = None;
+    let mut output = None;
+    // directories given with -L are searched before the default search path
+    let mut search_path = SearchPath::default();
+    let mut cmd_args = std::env::args().skip(1);
+    while let Some(a) = cmd_args.next() {
+        if a == "-L" {
+            search_path.push(cmd_args.next().expect("directory after -L"));
+        } else if let Some(dir) = a.strip_prefix("-L") {
+            search_path.push(dir);
+        } else if a == "-o" {
+            output = Some(PathBuf::from(cmd_args.next().expect("output path after -o")));
+        } else if a.starts_with("--") {
+            flags.push(a);
+        } else if start_mod_path.is_none() {
+            start_mod_path = Some(ir::Path::from(a));
+        } else {
+            bail!("unexpected argument {}", a);
+        }
+    }
+    search_path.extend(SearchPath::from_env());
+    let start_mod_path = start_mod_path.ok_or_else(|| anyhow!("missing module path"))?;
+    let mut start_mod_version = ir::VersionReq::STAR;
+    let mut emit = Emit::Executable;
+    // the runtime is built next to this tool
+    let mut runtime = std::env::current_exe()?.with_file_name("liboxlr_rt.a");
+    let mut linker = "cc".to_string();
+    for flag in flags {
+        match flag.split_once('=') {
+            Some(("--emit", "object")) => emit = Emit::Object,
+            Some(("--emit", "exe")) => emit = Emit::Executable,
+            Some(("--runtime", path)) => runtime = PathBuf::from(path),
+            Some(("--linker", path)) => linker = path.to_string(),
+            Some(("--require", req)) =>
+                start_mod_version = ir::VersionReq::parse(req).context("parse starting module version req")?,
+            _ => bail!("unknown flag {}", flag)
+        }
+    }
+    let name = start_mod_path.last().0.clone();
+    let output = output.unwrap_or_else(|| match emit {
+        Emit::Object => PathBuf::from(format!("{}.o", name)),
+        Emit::Executable => PathBuf::from(&name)
+    });
+
+    let world = World::new(search_path);
+    // the standard library is only used for its signatures, the runtime provides it when running
+    stdlib::install(&world, Vec::new())?;
+    world.load_module(&start_mod_path, &start_mod_version).context("load starting module")?;
+    let object = compile::Compiler::new(&world, &name)?.compile(&start_mod_path)?;
+
+    match emit {
+        Emit::Object => std::fs::write(&output, object).with_context(|| format!("writing {}", output.display()))?,
+        Emit::Executable => {
+            let object_path = output.with_extension("o");
+            std::fs::write(&object_path, object).with_context(|| format!("writing {}", object_path.display()))?;
+            let status = Command::new(&linker)
+                .arg(&object_path).arg(&runtime).args(RUNTIME_LIBS).arg("-o").arg(&output)
+                .status().with_context(|| format!("running linker {}", linker))?;
+            std::fs::remove_file(&object_path)?;
+            if !status.success() {
+                bail!("linking {} failed: {}", output.display(), status);
+            }
+        }
+    }
+    log::info!("{} -> {}", start_mod_path, output.display());
+    Ok(())
+}
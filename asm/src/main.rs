@@ -1,9 +1,36 @@
 use anyhow::*;
 
+fn read_module(input_path: &str) -> Result<ir::Module> {
+    let module = ir::Module::read_file(input_path)?;
+    // refuse modules with code that can't run correctly, like references to stack allocations
+    // that outlive their function
+    ir::verify::check(&module).with_context(|| format!("verifying text module {}", input_path))?;
+    Ok(module)
+}
+
+/// assemble several text modules into a single package archive
+fn assemble_package(output_path: &str, input_paths: &[String]) -> Result<()> {
+    let modules = input_paths.iter().map(|p| read_module(p)).collect::<Result<Vec<_>>>()?;
+    let mut package = ir::Package::new(modules)?;
+    let output_path = format!("{}/{}", output_path, package.file_name());
+    println!("{} -> {}", input_paths.join(", "), output_path);
+    let mut output = std::fs::File::create(output_path)?;
+    package.write(&mut output)?;
+    Ok(())
+}
+
 fn main() -> Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("--package") {
+        let output_path = std::env::args().nth(2).expect("missing output path");
+        let input_paths: Vec<String> = std::env::args().skip(3).collect();
+        if input_paths.is_empty() {
+            bail!("missing paths to text modules to include in package");
+        }
+        return assemble_package(&output_path, &input_paths);
+    }
     let input_path  = std::env::args().nth(1).expect("missing path to text module");
     let output_path = std::env::args().nth(2).expect("missing output path");
-    let module: ir::Module = ron::from_str(&std::fs::read_to_string(&input_path)?)?;
+    let module = read_module(&input_path)?;
     let output_path = format!("{}/{}#{}.om", output_path, module.path, module.version);
     println!("{} -> {}", input_path, output_path);
     let mut output = std::fs::File::create(output_path)?;
@@ -1,9 +1,8 @@
 //! This module defines the structure of the IR virtual machine code, which is in single static
 //! assignment form. Code is always found inside function bodies as [`FnBody`], organized into
 //! single [`BasicBlock`]s, each of which represents a continuous path of execution.
-use std::borrow::Cow;
-
 use serde::{Serialize, Deserialize};
+use anyhow::Result;
 use super::{Symbol, Path, Type, numbers::Integer, numbers::Float};
 
 /// A reference to a virtual machine register
@@ -45,18 +44,72 @@ pub enum UnaryOp {
     LogNot, BitNot, Neg
 }
 
+/// An error caused by running code, which stops it at the instruction that caused it. A trap
+/// unwinds like an exception thrown by [`Throw`](Instruction::Throw), with its
+/// [`code`](Trap::code) as the thrown value (an unsigned 32 bit integer), so it can be handled by
+/// an [`Invoke`](Instruction::Invoke) of any function that it passes through. The process running
+/// the code is never stopped by a trap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Trap {
+    /// An integer was divided by zero
+    DivisionByZero = 1,
+    /// The result of an integer operation does not fit in the width of its type
+    Overflow = 2,
+    /// An index was past the end of an array or tuple
+    IndexOutOfBounds = 3,
+    /// The heap has reached its maximum size
+    OutOfMemory = 4,
+    /// The data stack or the call stack is full
+    StackOverflow = 5,
+    /// An instruction was given a value of the wrong type
+    TypeMismatch = 6,
+    /// A called function does not exist
+    FunctionNotFound = 7,
+    /// The type of the first argument to an interface function has no implementation of it
+    ImplementationNotFound = 8,
+    /// A native function failed
+    NativeError = 9,
+    /// The code is malformed, for example by using a register or block that does not exist
+    InvalidCode = 10,
+    /// The instruction or operation is not supported yet
+    Unsupported = 11
+}
+
+impl Trap {
+    /// All kinds of trap
+    pub const ALL: [Trap; 11] = [
+        Trap::DivisionByZero, Trap::Overflow, Trap::IndexOutOfBounds, Trap::OutOfMemory,
+        Trap::StackOverflow, Trap::TypeMismatch, Trap::FunctionNotFound,
+        Trap::ImplementationNotFound, Trap::NativeError, Trap::InvalidCode, Trap::Unsupported
+    ];
+
+    /// The code that identifies this kind of trap when it is thrown
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// The kind of trap identified by a code, if there is one
+    pub fn from_code(code: u32) -> Option<Trap> {
+        Trap::ALL.into_iter().find(|t| t.code() == code)
+    }
+}
 
 /// A single virtual machine instruction.
 /// Destination registers are typically first in the tuple, then the source value
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Instruction {
     /// A SSA Phi node, which selects a value to store in its destination register depending on
-    /// which basic block jumped to this instruction
+    /// which basic block jumped to this instruction. Phis must come before any other instruction in
+    /// a block. The phis at the start of a block run together when execution moves into the block,
+    /// reading all of their values before writing any destination, so they can swap registers.
+    /// When a function is entered, the phis in its entry block are skipped and their registers keep
+    /// their initial values
     Phi(
         /// Destination register
         Register,
-        /// A mapping from origin blocks to values
-        std::collections::HashMap<BlockIndex, Value>
+        /// The value for each origin block, which should each appear once
+        Vec<(BlockIndex, Value)>
     ),
 
     /// Jump to a different block based on the value of `cond`
@@ -190,6 +243,47 @@ pub enum Instruction {
     /// Return from this function, yielding specified value
     Return(Value),
 
+    /// Call a function referenced by the path in place of this function, returning its result
+    /// from this function. The frame of this function is reused, so tail calls never grow the
+    /// stack. Stack allocations made by this function are released before the call, so references
+    /// to them must not be passed as arguments
+    TailCall(
+        /// Path to the function
+        Path,
+        /// Argument values
+        Vec<Value>
+    ),
+    /// Call the implementation function for the specified interface function like
+    /// [`CallImpl`](Instruction::CallImpl), as a tail call like [`TailCall`](Instruction::TailCall)
+    TailCallImpl(
+        /// Path to the function on the interface (not the implementation)
+        Path,
+        /// Argument values
+        Vec<Value>
+    ),
+
+    /// Call a function referenced by the path like [`Call`](Instruction::Call), then jump to the
+    /// `normal` block if it returns or to the `unwind` block if it throws an exception. When the
+    /// function throws, the frames of the functions between the one that threw and this one are
+    /// discarded along with their stack allocations
+    Invoke {
+        /// Destination for return value
+        dest: Register,
+        /// Path to the function
+        func: Path,
+        /// Argument values
+        args: Vec<Value>,
+        /// Index of the block to jump to if the function returns
+        normal: BlockIndex,
+        /// Index of the block to jump to if the function throws
+        unwind: BlockIndex,
+        /// Destination for the thrown value if the function throws
+        exception: Register
+    },
+    /// Throw a value as an exception, returning from each calling function until one that called
+    /// with [`Invoke`](Instruction::Invoke) is found
+    Throw(Value),
+
     /// Create a function pointer to a function at the path
     RefFunc(Register, Path),
 
@@ -267,3 +361,135 @@ pub struct FnBody {
     /// The basic blocks that are contained in the function body. [`BlockIndex`] values are indices inside this vector.
     pub blocks: Vec<BasicBlock>
 }
+
+impl Instruction {
+    /// Call `f` on each path that this instruction references, including paths inside types
+    pub fn for_each_path_mut(&mut self, f: &mut impl FnMut(&mut Path) -> Result<()>) -> Result<()> {
+        match self {
+            Instruction::Call(_, p, _) | Instruction::CallImpl(_, p, _) | Instruction::RefFunc(_, p)
+                | Instruction::TailCall(p, _) | Instruction::TailCallImpl(p, _)
+                | Instruction::Invoke { func: p, .. } => f(p),
+            Instruction::Alloc(_, t) | Instruction::AllocArray(_, t, _)
+                | Instruction::StackAlloc(_, t) | Instruction::StackAllocArray(_, t, _) => t.for_each_path_mut(f),
+            _ => Ok(())
+        }
+    }
+
+    /// Whether this instruction always leaves its block, so that instructions after it never run
+    /// and `next_block` is not used
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, Instruction::Br { .. } | Instruction::Return(_) | Instruction::TailCall(..)
+            | Instruction::TailCallImpl(..) | Instruction::Invoke { .. } | Instruction::Throw(_))
+    }
+
+    /// Call `f` on each register that this instruction writes to
+    pub fn for_each_def_mut(&mut self, f: &mut impl FnMut(&mut Register)) {
+        match self {
+            Instruction::Phi(d, _) | Instruction::BinaryOp(_, d, _, _) | Instruction::UnaryOp(_, d, _)
+                | Instruction::LoadImm(d, _) | Instruction::LoadRef(d, _) | Instruction::RefIndex(d, _, _)
+                | Instruction::RefField(d, _, _) | Instruction::LoadIndex(d, _, _) | Instruction::LoadField(d, _, _)
+                | Instruction::Call(d, _, _) | Instruction::CallImpl(d, _, _) | Instruction::RefFunc(d, _)
+                | Instruction::Alloc(d, _) | Instruction::AllocArray(d, _, _) | Instruction::StackAlloc(d, _)
+                | Instruction::StackAllocArray(d, _, _) | Instruction::CopyToStack(d, _)
+                | Instruction::CopyToHeap(d, _) => f(d),
+            Instruction::Invoke { dest, exception, .. } => {
+                f(dest);
+                f(exception);
+            },
+            Instruction::UnwrapVariant(d, inner, _, _) => {
+                f(d);
+                if let Some(inner) = inner {
+                    f(inner);
+                }
+            },
+            Instruction::Br { .. } | Instruction::StoreRef(..) | Instruction::StoreIndex(..)
+                | Instruction::StoreField(..) | Instruction::Return(_) | Instruction::TailCall(..)
+                | Instruction::TailCallImpl(..) | Instruction::Throw(_) => {}
+        }
+    }
+
+    /// Call `f` on each value that this instruction reads, including the values of a phi
+    pub fn for_each_value_mut(&mut self, f: &mut impl FnMut(&mut Value)) {
+        match self {
+            Instruction::Phi(_, vals) => vals.iter_mut().for_each(|(_, v)| f(v)),
+            Instruction::Br { cond: v, .. } | Instruction::UnaryOp(_, _, v) | Instruction::LoadImm(_, v)
+                | Instruction::StoreRef(_, v) | Instruction::RefIndex(_, _, v) | Instruction::LoadIndex(_, _, v)
+                | Instruction::StoreField(v, _, _) | Instruction::Return(v) | Instruction::Throw(v)
+                | Instruction::UnwrapVariant(_, _, v, _) | Instruction::AllocArray(_, _, v)
+                | Instruction::StackAllocArray(_, _, v) => f(v),
+            Instruction::BinaryOp(_, _, a, b) | Instruction::StoreIndex(_, a, b) => {
+                f(a);
+                f(b);
+            },
+            Instruction::Call(_, _, args) | Instruction::CallImpl(_, _, args) | Instruction::TailCall(_, args)
+                | Instruction::TailCallImpl(_, args) | Instruction::Invoke { args, .. } => args.iter_mut().for_each(f),
+            Instruction::LoadRef(..) | Instruction::RefField(..) | Instruction::LoadField(..)
+                | Instruction::RefFunc(..) | Instruction::Alloc(..) | Instruction::StackAlloc(..)
+                | Instruction::CopyToStack(..) | Instruction::CopyToHeap(..) => {}
+        }
+    }
+
+    /// Call `f` on each register that this instruction reads directly rather than through a
+    /// [`Value`], which are the references that it loads from, stores to or copies
+    pub fn for_each_ref_operand_mut(&mut self, f: &mut impl FnMut(&mut Register)) {
+        match self {
+            Instruction::LoadRef(_, r) | Instruction::StoreRef(r, _) | Instruction::RefIndex(_, r, _)
+                | Instruction::RefField(_, r, _) | Instruction::LoadIndex(_, r, _) | Instruction::StoreIndex(r, _, _)
+                | Instruction::LoadField(_, r, _) | Instruction::StoreField(_, r, _)
+                | Instruction::CopyToStack(_, r) | Instruction::CopyToHeap(_, r) => f(r),
+            _ => {}
+        }
+    }
+
+    /// Call `f` on each block that this instruction can jump to
+    pub fn for_each_target_mut(&mut self, f: &mut impl FnMut(&mut BlockIndex)) {
+        match self {
+            Instruction::Br { if_true, if_false, .. } => {
+                f(if_true);
+                f(if_false);
+            },
+            Instruction::Invoke { normal, unwind, .. } => {
+                f(normal);
+                f(unwind);
+            },
+            _ => {}
+        }
+    }
+}
+
+impl BasicBlock {
+    /// The number of instructions in this block that can run, up to and including the first
+    /// terminator
+    pub fn len_live(&self) -> usize {
+        self.instrs.iter().position(Instruction::is_terminator).map_or(self.instrs.len(), |i| i + 1)
+    }
+
+    /// Whether execution can reach the end of this block and continue at `next_block`
+    pub fn falls_through(&self) -> bool {
+        !self.instrs.iter().any(Instruction::is_terminator)
+    }
+
+    /// The blocks that execution can continue at after this block, in order and possibly repeated
+    pub fn successors(&self) -> Vec<BlockIndex> {
+        match self.instrs.iter().find(|i| i.is_terminator()) {
+            Some(Instruction::Br { if_true, if_false, .. }) => vec![*if_true, *if_false],
+            Some(Instruction::Invoke { normal, unwind, .. }) => vec![*normal, *unwind],
+            Some(_) => Vec::new(),
+            None => vec![self.next_block]
+        }
+    }
+
+    /// The number of phis at the start of this block
+    pub fn phi_count(&self) -> usize {
+        self.instrs.iter().take_while(|i| matches!(i, Instruction::Phi(..))).count()
+    }
+}
+
+impl FnBody {
+    /// Call `f` on each path referenced by the instructions in this function body
+    pub fn for_each_path_mut(&mut self, f: &mut impl FnMut(&mut Path) -> Result<()>) -> Result<()> {
+        self.blocks.iter_mut()
+            .flat_map(|b| b.instrs.iter_mut())
+            .try_for_each(|i| i.for_each_path_mut(f))
+    }
+}
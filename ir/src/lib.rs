@@ -2,16 +2,24 @@
 //! structures. Each module is represented by a [`Module`] structure, which can be (de)serialized
 //! to/from a file, for usage in other programs. The actual virtual machine code is defined in the
 //! [`code`] module, and is stored in single static assignment form.
-use std::{borrow::Cow, collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display};
 use serde::{Serialize, Deserialize};
+use anyhow::{anyhow, bail, Context, Result};
 pub use semver::{Version, VersionReq};
 
 pub mod code;
-pub use code::FnBody;
+pub use code::{FnBody, Trap};
 
 pub mod numbers;
 pub use numbers::*;
 
+pub mod package;
+pub use package::{Package, Manifest, ContentHash};
+
+pub mod opt;
+
+pub mod verify;
+
 /// A `Symbol` represents a single name in a module
 #[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone)]
 pub struct Symbol(pub String);
@@ -63,7 +71,7 @@ pub enum Type {
 }
 
 /// A user defined type definition
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub enum TypeDefinition {
     /// A single type that gets a new user specified name
     /// Provided to support variants that contain only a single value
@@ -100,9 +108,9 @@ pub struct FunctionSignature {
 }
 
 /// The module represents a contained block of function and type definitions of a specific version
-/// This is the root of the IR data structure. Modules can be nested, but this is represented only
-/// in their path field as being under the same subpath
-#[derive(Serialize, Deserialize, Debug)]
+/// This is the root of the IR data structure. Modules can be nested, either by being listed in the
+/// `submodules` of their parent or by being a separate module under the same subpath
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Module {
     pub path: Path,
     pub version: Version,
@@ -118,6 +126,16 @@ pub struct Module {
     pub functions: HashMap<Symbol, (FunctionSignature, FnBody)>,
     /// A list of module paths that this module imports, associated with the version requirements for that module that must be met
     pub imports: Vec<(Path, VersionReq)>,
+    /// The submodules contained in this module, which are always loaded together with it. The path
+    /// of each submodule must be the path of this module extended by a single symbol
+    #[serde(default)]
+    pub submodules: Vec<Module>,
+    /// Whether modules outside this module's package may inline its functions into their own code.
+    /// Inlined code does not change when a newer version of this module is loaded instead, so only
+    /// modules whose functions will keep behaving the same in every compatible version should
+    /// allow it
+    #[serde(default)]
+    pub inlinable: bool,
 }
 
 impl Path {
@@ -126,6 +144,11 @@ impl Path {
         self.0.len()
     }
 
+    /// True if this path contains no symbols
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     /// Iterator over the symbols in this path
     pub fn iter(&self) -> impl Iterator<Item=&Symbol> {
         self.0.iter()
@@ -145,9 +168,165 @@ impl Path {
     pub fn last(&self) -> &Symbol {
         self.0.last().expect("paths must have at least one element")
     }
+
+    /// Returns a new path with `sym` appended to the end of this one
+    pub fn child(&self, sym: Symbol) -> Path {
+        let mut p = self.clone();
+        p.0.push(sym);
+        p
+    }
+
+    /// True if this path begins with all the symbols in `prefix`
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// True if this path is relative to the module it appears in, which is the case if it begins
+    /// with `self` or `super`
+    pub fn is_relative(&self) -> bool {
+        matches!(self.0.first(), Some(Symbol(s)) if s == "self" || s == "super")
+    }
+
+    /// Resolve this path relative to the path of the module `base` that it appears in. A leading
+    /// `self` refers to `base` itself, and each leading `super` refers to the parent of the module
+    /// before it, so `super::super::f` inside `a::b::c` resolves to `a::f`. Absolute paths are
+    /// returned unchanged.
+    pub fn resolve(&self, base: &Path) -> Result<Path> {
+        if !self.is_relative() {
+            return Ok(self.clone());
+        }
+        let mut syms = self.0.iter().peekable();
+        let mut resolved = base.0.clone();
+        if syms.next_if(|s| s.0 == "self").is_none() {
+            while syms.next_if(|s| s.0 == "super").is_some() {
+                resolved.pop().ok_or_else(|| anyhow!("relative path {} reaches above the root module from {}", self, base))?;
+            }
+        }
+        resolved.extend(syms.cloned());
+        if resolved.is_empty() {
+            bail!("relative path {} resolves to an empty path from {}", self, base);
+        }
+        Ok(Path(resolved))
+    }
+}
+
+impl Type {
+    /// Call `f` on each path contained in this type, including inside any nested types
+    pub fn for_each_path_mut(&mut self, f: &mut impl FnMut(&mut Path) -> Result<()>) -> Result<()> {
+        match self {
+            Type::Unit | Type::Bool | Type::Int { .. } | Type::Float { .. } | Type::Var(_) => Ok(()),
+            Type::Array(t) | Type::Ref(t) => t.for_each_path_mut(f),
+            Type::Tuple(ts) => ts.iter_mut().try_for_each(|t| t.for_each_path_mut(f)),
+            Type::User(p, params) => {
+                f(p)?;
+                params.iter_mut().flatten().try_for_each(|t| t.for_each_path_mut(f))
+            },
+            Type::AbstractRef(ps) => ps.iter_mut().try_for_each(f),
+            Type::FnRef(sig) => sig.for_each_path_mut(f),
+        }
+    }
+}
+
+impl FunctionSignature {
+    /// Call `f` on each path contained in the argument and return types of this signature
+    pub fn for_each_path_mut(&mut self, f: &mut impl FnMut(&mut Path) -> Result<()>) -> Result<()> {
+        for (t, _) in self.args.iter_mut() {
+            t.for_each_path_mut(f)?;
+        }
+        self.return_type.for_each_path_mut(f)
+    }
+}
+
+impl TypeDefinition {
+    /// Call `f` on each path contained in this definition, including type parameter bounds
+    pub fn for_each_path_mut(&mut self, f: &mut impl FnMut(&mut Path) -> Result<()>) -> Result<()> {
+        match self {
+            TypeDefinition::NewType(t) => t.for_each_path_mut(f),
+            TypeDefinition::Sum { parameters, variants } => {
+                parameters.iter_mut().flat_map(|(_, ps)| ps.iter_mut()).try_for_each(&mut *f)?;
+                variants.iter_mut().try_for_each(|(_, td)| td.for_each_path_mut(f))
+            },
+            TypeDefinition::Product { parameters, fields } => {
+                parameters.iter_mut().flat_map(|(_, ps)| ps.iter_mut()).try_for_each(&mut *f)?;
+                fields.iter_mut().try_for_each(|(_, t)| t.for_each_path_mut(f))
+            },
+        }
+    }
+}
+
+impl Module {
+    /// Call `f` on each path that appears in this module, not including its own path or the
+    /// contents of its submodules
+    pub fn for_each_path_mut(&mut self, f: &mut impl FnMut(&mut Path) -> Result<()>) -> Result<()> {
+        for td in self.types.values_mut() {
+            td.for_each_path_mut(f)?;
+        }
+        for i in self.interfaces.values_mut() {
+            for sig in i.functions.values_mut() {
+                sig.for_each_path_mut(f)?;
+            }
+        }
+        self.implementations = std::mem::take(&mut self.implementations).into_iter()
+            .map(|((mut t, mut p), fns)| {
+                t.for_each_path_mut(f)?;
+                f(&mut p)?;
+                Ok(((t, p), fns))
+            })
+            .collect::<Result<_>>()?;
+        for (sig, body) in self.functions.values_mut() {
+            sig.for_each_path_mut(f)?;
+            body.for_each_path_mut(f)?;
+        }
+        for (p, _) in self.imports.iter_mut() {
+            f(p)?;
+        }
+        Ok(())
+    }
+
+    /// Read a module from a file, which is encoded like the modules the VM loads if its name ends
+    /// in `.om`, and is a text module otherwise
+    pub fn read_file(path: &str) -> Result<Module> {
+        if path.ends_with(".om") {
+            rmp_serde::from_read_ref(&std::fs::read(path).with_context(|| format!("reading {}", path))?)
+                .with_context(|| format!("decoding module {}", path))
+        } else {
+            ron::from_str(&std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?)
+                .with_context(|| format!("parsing text module {}", path))
+        }
+    }
+
+    /// Add the imports in `imports`, which must have absolute paths, that this module doesn't have
+    /// yet. Modules that are loaded along with this one, which are its submodules and the modules
+    /// it is inside of, are left out. Code copied from another module can call functions in the
+    /// modules that one imports, so they are merged into the module it is copied into
+    pub fn merge_imports_from(&mut self, imports: &[(Path, VersionReq)]) {
+        for (path, req) in imports.iter() {
+            let loaded_with = path.starts_with(&self.path) || self.path.starts_with(path);
+            let imported = self.imports.iter().any(|(p, _)| p.resolve(&self.path).is_ok_and(|p| p == *path));
+            if !loaded_with && !imported {
+                self.imports.push((path.clone(), req.clone()));
+            }
+        }
+    }
+
+    /// Resolve every relative path in this module and all of its submodules into an absolute
+    /// path, using the path of the module each appears in as the base. Submodule paths may
+    /// themselves be relative to their parent.
+    pub fn resolve_relative_paths(&mut self) -> Result<()> {
+        let base = self.path.clone();
+        self.for_each_path_mut(&mut |p| {
+            *p = p.resolve(&base)?;
+            Ok(())
+        })?;
+        for sm in self.submodules.iter_mut() {
+            sm.path = sm.path.resolve(&base)?;
+            sm.resolve_relative_paths()?;
+        }
+        Ok(())
+    }
 }
 
-impl<'a, T: 'a + AsRef<str>> From<T> for Path {
+impl<T: AsRef<str>> From<T> for Path {
     fn from(s: T) -> Self {
         Path(s.as_ref().split("::").map(|s| Symbol(s.to_string())).collect())
     }
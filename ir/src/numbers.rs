@@ -34,73 +34,94 @@ impl Integer {
         Integer { width, signed: true, data }
     }
 
+    /// Whether integers of a width are supported, which is 8, 16, 32 or 64 bits
+    pub fn valid_width(width: u8) -> bool {
+        matches!(width, 8 | 16 | 32 | 64)
+    }
+
     /// Compute the bitwise negation of the integer
     pub fn bitwise_negate(&self) -> Integer {
-        Integer {
-            data: !self.data,
-            ..*self
-        }
+        // unsigned integers are stored zero extended, so the bits above the width stay clear
+        let data = match self.width {
+            w @ 1..=63 if !self.signed => !self.data & ((1 << w) - 1),
+            _ => !self.data
+        };
+        Integer { data, ..*self }
     }
 
-    /// Compute the negation of the integer in two's complement representation
-    pub fn negate(&self) -> Integer {
-        assert!(self.signed);
-        Integer {
-            data: !self.data + 1,
-            ..*self
+    /// Compute the negation of a signed integer, or return `None` if the integer is unsigned, its
+    /// width is not supported or the result does not fit, which is only the case for the
+    /// smallest value of the width
+    pub fn checked_negate(&self) -> Option<Integer> {
+        if !self.signed {
+            return None;
         }
+        Integer::from_value(self.width, self.signed, -self.value()?)
     }
-}
 
-impl std::ops::Add for Integer {
-    type Output = Integer;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        assert!(self.width >= rhs.width);
-        assert!(self.signed == rhs.signed);
-        Integer {
-            data: self.data + rhs.data,
-            ..self
+    /// The value of the integer, read from the low `width` bits of its data, or `None` if the
+    /// width is not supported
+    pub fn value(&self) -> Option<i128> {
+        if !Integer::valid_width(self.width) {
+            return None;
         }
+        let shift = 64 - self.width as u32;
+        Some(if self.signed {
+            (((self.data << shift) as i64) >> shift) as i128
+        } else {
+            ((self.data << shift) >> shift) as i128
+        })
     }
-}
 
-impl std::ops::Sub for Integer {
-    type Output = Integer;
+    /// Create an integer of the given width and signedness holding `value`, or return `None` if
+    /// the width is not supported or the value does not fit. Signed integers are stored sign
+    /// extended to 64 bits, and unsigned ones zero extended
+    pub fn from_value(width: u8, signed: bool, value: i128) -> Option<Integer> {
+        if !Integer::valid_width(width) {
+            return None;
+        }
+        let (min, max) = if signed {
+            (-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1)
+        } else {
+            (0, (1i128 << width) - 1)
+        };
+        (min..=max).contains(&value).then_some(Integer { width, signed, data: value as u64 })
+    }
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        assert!(self.width >= rhs.width);
-        assert!(self.signed == rhs.signed);
-        Integer {
-            data: self.data - rhs.data,
-            ..self
+    /// the values of two integers of the same type, for arithmetic on them
+    fn operands(self, rhs: Integer) -> Option<(i128, i128)> {
+        if self.width != rhs.width || self.signed != rhs.signed {
+            return None;
         }
+        Some((self.value()?, rhs.value()?))
     }
-}
 
-impl std::ops::Mul for Integer {
-    type Output = Integer;
+    /// Add two integers, or return `None` if they are of different types or the result does not
+    /// fit in their type
+    pub fn checked_add(self, rhs: Integer) -> Option<Integer> {
+        let (a, b) = self.operands(rhs)?;
+        Integer::from_value(self.width, self.signed, a + b)
+    }
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        assert!(self.width >= rhs.width);
-        assert!(self.signed == rhs.signed);
-        Integer {
-            data: self.data * rhs.data,
-            ..self
-        }
+    /// Subtract two integers, or return `None` if they are of different types or the result does
+    /// not fit in their type
+    pub fn checked_sub(self, rhs: Integer) -> Option<Integer> {
+        let (a, b) = self.operands(rhs)?;
+        Integer::from_value(self.width, self.signed, a - b)
     }
-}
 
-impl std::ops::Div for Integer {
-    type Output = Integer;
+    /// Multiply two integers, or return `None` if they are of different types or the result does
+    /// not fit in their type
+    pub fn checked_mul(self, rhs: Integer) -> Option<Integer> {
+        let (a, b) = self.operands(rhs)?;
+        Integer::from_value(self.width, self.signed, a.checked_mul(b)?)
+    }
 
-    fn div(self, rhs: Self) -> Self::Output {
-        assert!(self.width >= rhs.width);
-        assert!(self.signed == rhs.signed);
-        Integer {
-            data: self.data / rhs.data,
-            ..self
-        }
+    /// Divide two integers, rounding towards zero, or return `None` if they are of different
+    /// types, `rhs` is zero or the result does not fit in their type
+    pub fn checked_div(self, rhs: Integer) -> Option<Integer> {
+        let (a, b) = self.operands(rhs)?;
+        Integer::from_value(self.width, self.signed, a.checked_div(b)?)
     }
 }
 
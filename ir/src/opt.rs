@@ -0,0 +1,174 @@
+//! Optimization passes that rewrite the code of function bodies into equivalent code that runs
+//! faster. Each pass implements [`Pass`] and makes one kind of improvement, which often opens up
+//! opportunities for the others, so a [`PassManager`] runs a pipeline of passes over a module
+//! again and again until none of them change anything.
+//!
+//! Passes must keep the behaviour of code exactly the same as the VM runs it, including when it
+//! traps: an operation that would trap is left for the VM to run, so that the trap still happens
+//! at run time. The block and instruction indices given with a trap can change, however.
+use anyhow::{anyhow, Context, Result};
+use crate::{FunctionSignature, FnBody, Module};
+
+mod cfg;
+pub use cfg::Cfg;
+
+mod fold;
+pub use fold::{ConstantFold, fold_binary, fold_unary};
+
+mod propagate;
+pub use propagate::CopyPropagation;
+
+mod dce;
+pub use dce::DeadCode;
+
+mod unreachable;
+pub use unreachable::UnreachableBlocks;
+
+mod simplify;
+pub use simplify::SimplifyCfg;
+
+mod registers;
+pub use registers::CountRegisters;
+
+mod inline;
+pub use inline::Inline;
+
+pub mod escape;
+
+mod stack;
+pub use stack::StackPromotion;
+
+mod specialize;
+pub use specialize::Specializer;
+
+/// A copy of each module in a tree of submodules with every path in it made absolute, so that code
+/// copied out of it means the same thing in any other module. The copies have no submodules
+fn resolved_modules(module: &Module) -> Result<Vec<Module>> {
+    let mut module = module.clone();
+    module.resolve_relative_paths().with_context(|| format!("resolving paths in {}", module.path))?;
+    let mut modules = Vec::new();
+    let mut unvisited = vec![module];
+    while let Some(mut m) = unvisited.pop() {
+        unvisited.append(&mut m.submodules);
+        modules.push(m);
+    }
+    Ok(modules)
+}
+
+/// A transformation of function bodies
+pub trait Pass {
+    /// The name of the pass, which selects it in [`PassManager::from_names`]
+    fn name(&self) -> &'static str;
+
+    /// Run the pass on the body of a function with the signature `sig`, returning whether it
+    /// changed anything
+    fn run(&mut self, sig: &FunctionSignature, body: &mut FnBody) -> Result<bool>;
+
+    /// Run the pass on every function in a module and its submodules, returning whether it changed
+    /// anything
+    fn run_module(&mut self, module: &mut Module) -> Result<bool> {
+        let mut changed = false;
+        for (name, (sig, body)) in module.functions.iter_mut() {
+            changed |= self.run(sig, body)
+                .with_context(|| format!("running {} pass on {}::{}", self.name(), module.path, name.0))?;
+        }
+        for sub in module.submodules.iter_mut() {
+            changed |= self.run_module(sub)?;
+        }
+        Ok(changed)
+    }
+}
+
+/// The names of the passes in the default pipeline, in the order that they run
+pub const DEFAULT_PASSES: &[&str] = &["inline", "copy-prop", "fold", "simplify-cfg", "unreachable", "dce", "stack-promote", "registers"];
+
+/// Create the pass called `name`, if there is one. Passes that need to be configured are created
+/// without any configuration, so [`Inline`] knows of no functions to inline
+pub fn pass_by_name(name: &str) -> Option<Box<dyn Pass>> {
+    Some(match name {
+        "inline" => Box::new(Inline::new()),
+        "fold" => Box::new(ConstantFold),
+        "copy-prop" => Box::new(CopyPropagation),
+        "dce" => Box::new(DeadCode),
+        "unreachable" => Box::new(UnreachableBlocks),
+        "simplify-cfg" => Box::new(SimplifyCfg),
+        "stack-promote" => Box::new(StackPromotion::new()),
+        "registers" => Box::new(CountRegisters),
+        _ => return None
+    })
+}
+
+/// Runs a pipeline of passes until they stop changing the code
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    /// the most times the whole pipeline runs, in case passes keep undoing each other's changes
+    pub max_rounds: usize
+}
+
+impl PassManager {
+    /// Create a pass manager with an empty pipeline
+    pub fn new() -> PassManager {
+        PassManager { passes: Vec::new(), max_rounds: 10 }
+    }
+
+    /// Create a pass manager that runs the passes called `names`, in order
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<PassManager> {
+        let mut pm = PassManager::new();
+        for name in names {
+            let name = name.as_ref();
+            pm.passes.push(pass_by_name(name).ok_or_else(|| anyhow!("unknown pass {}", name))?);
+        }
+        Ok(pm)
+    }
+
+    /// Add a pass to the end of the pipeline
+    pub fn add(&mut self, pass: impl Pass + 'static) -> &mut PassManager {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Replace the pass in the pipeline that has the same name as `pass`, for example to give it a
+    /// configuration. Returns whether there was such a pass
+    pub fn replace(&mut self, pass: impl Pass + 'static) -> bool {
+        match self.passes.iter_mut().find(|p| p.name() == pass.name()) {
+            Some(p) => {
+                *p = Box::new(pass);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Run the pipeline over a single function body, returning whether anything changed
+    pub fn run(&mut self, sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        self.until_unchanged(|pass| pass.run(sig, body))
+    }
+
+    /// Run the pipeline over every function in a module and its submodules, returning whether
+    /// anything changed
+    pub fn run_module(&mut self, module: &mut Module) -> Result<bool> {
+        self.until_unchanged(|pass| pass.run_module(module))
+    }
+
+    fn until_unchanged(&mut self, mut f: impl FnMut(&mut dyn Pass) -> Result<bool>) -> Result<bool> {
+        let mut changed = false;
+        for _ in 0..self.max_rounds {
+            let mut round_changed = false;
+            for pass in self.passes.iter_mut() {
+                round_changed |= f(pass.as_mut())?;
+            }
+            if !round_changed {
+                break;
+            }
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
+
+impl Default for PassManager {
+    /// The default pipeline of passes, from [`DEFAULT_PASSES`]
+    fn default() -> PassManager {
+        PassManager::from_names(DEFAULT_PASSES).expect("default passes exist")
+    }
+}
@@ -0,0 +1,115 @@
+//! The control flow graph of a function body, with the dominator tree of its reachable blocks
+use anyhow::{bail, Result};
+use crate::code::{BlockIndex, FnBody};
+
+/// Which blocks of a function body can run after which others
+pub struct Cfg {
+    /// The reachable blocks that can run immediately before each block, without repeats
+    pub preds: Vec<Vec<BlockIndex>>,
+    reachable: Vec<bool>,
+    /// the immediate dominator of each reachable block. The entry block is its own
+    idom: Vec<Option<BlockIndex>>
+}
+
+impl Cfg {
+    /// Compute the control flow graph of a body, checking that every block that reachable blocks
+    /// jump to exists
+    pub fn new(body: &FnBody) -> Result<Cfg> {
+        let n = body.blocks.len();
+        let succs: Vec<_> = body.blocks.iter().map(|b| b.successors()).collect();
+
+        // depth first search from the entry block, recording blocks in postorder
+        let mut reachable = vec![false; n];
+        let mut postorder = Vec::with_capacity(n);
+        if n > 0 {
+            let mut stack = vec![(0, 0)];
+            reachable[0] = true;
+            while let Some((b, next)) = stack.last_mut() {
+                if let Some(&s) = succs[*b].get(*next) {
+                    if s >= n {
+                        bail!("block {} continues at block {}, but there are only {} blocks", b, s, n);
+                    }
+                    *next += 1;
+                    if !reachable[s] {
+                        reachable[s] = true;
+                        stack.push((s, 0));
+                    }
+                } else {
+                    postorder.push(*b);
+                    stack.pop();
+                }
+            }
+        }
+
+        let mut preds = vec![Vec::new(); n];
+        for &b in postorder.iter().rev() {
+            for &s in succs[b].iter() {
+                if !preds[s].contains(&b) {
+                    preds[s].push(b);
+                }
+            }
+        }
+
+        // the iterative algorithm from "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy
+        let mut order = vec![usize::MAX; n];
+        for (i, &b) in postorder.iter().enumerate() {
+            order[b] = i;
+        }
+        let mut idom = vec![None; n];
+        if n > 0 {
+            idom[0] = Some(0);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in postorder.iter().rev().skip(1) {
+                let mut new_idom = None;
+                for &p in preds[b].iter().filter(|p| idom[**p].is_some()) {
+                    new_idom = Some(match new_idom {
+                        None => p,
+                        Some(mut other) => {
+                            let mut p = p;
+                            while p != other {
+                                while order[p] < order[other] {
+                                    p = idom[p].unwrap();
+                                }
+                                while order[other] < order[p] {
+                                    other = idom[other].unwrap();
+                                }
+                            }
+                            p
+                        }
+                    });
+                }
+                if new_idom.is_some() && idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(Cfg { preds, reachable, idom })
+    }
+
+    /// Whether a block can run when the function is called. Blocks that don't exist can't
+    pub fn is_reachable(&self, block: BlockIndex) -> bool {
+        self.reachable.get(block).copied().unwrap_or(false)
+    }
+
+    /// Whether every path from the entry block to `b` goes through `a`. Only reachable blocks
+    /// dominate or are dominated
+    pub fn dominates(&self, a: BlockIndex, mut b: BlockIndex) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(d) if d != b => b = d,
+                _ => return false
+            }
+        }
+    }
+}
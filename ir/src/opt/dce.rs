@@ -0,0 +1,48 @@
+//! Dead code elimination
+use std::collections::HashSet;
+use anyhow::Result;
+use crate::{FunctionSignature, FnBody};
+use crate::code::{Instruction, Value};
+use super::Pass;
+
+/// Removes instructions that assign a register which is never read, when running them can have no
+/// other effect. Only [`LoadImm`](Instruction::LoadImm) and [`Phi`](Instruction::Phi) are removed,
+/// since every other instruction that assigns a register can trap or touch memory
+pub struct DeadCode;
+
+impl Pass for DeadCode {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, _sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        let mut changed = false;
+        // removing an instruction can make the registers it read dead too
+        loop {
+            let mut read = HashSet::new();
+            for instr in body.blocks.iter_mut().flat_map(|b| b.instrs.iter_mut()) {
+                instr.for_each_value_mut(&mut |v| if let Value::Reg(r) = v {
+                    read.insert(r.0);
+                });
+                instr.for_each_ref_operand_mut(&mut |r| {
+                    read.insert(r.0);
+                });
+            }
+            let mut removed = false;
+            for block in body.blocks.iter_mut() {
+                block.instrs.retain(|instr| match instr {
+                    Instruction::LoadImm(d, _) | Instruction::Phi(d, _) if !read.contains(&d.0) => {
+                        removed = true;
+                        false
+                    },
+                    _ => true
+                });
+            }
+            if !removed {
+                break;
+            }
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,247 @@
+//! Escape analysis, which finds the ways that a reference held by a function can outlive the
+//! function's frame
+use std::collections::{HashMap, HashSet};
+use anyhow::Result;
+use crate::{FnBody, Module, Path};
+use crate::code::{BlockIndex, Instruction, Register, Value};
+
+/// A way that a reference can leave the function that holds it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Escape {
+    /// It is returned from the function
+    Returned,
+    /// It is thrown as an exception
+    Thrown,
+    /// It is stored in memory, which was allocated by this function if `into_local` is true. The
+    /// reference then also escapes wherever that memory does
+    Stored { into_local: bool },
+    /// It is passed as the argument at index `arg` to a function. `escapes` is whether the callee
+    /// lets that argument escape other than by returning it, or `None` if the callee isn't known.
+    /// Tail calls release the caller's stack allocations before the callee runs
+    Passed { callee: Path, arg: usize, tail: bool, escapes: Option<bool> },
+    /// It is used by an instruction that doesn't take references
+    Unknown
+}
+
+impl Escape {
+    /// Whether the reference can be used after the function returns
+    pub fn may_outlive(&self) -> bool {
+        !matches!(self, Escape::Passed { tail: false, escapes: Some(false), .. })
+    }
+}
+
+/// An instruction through which a reference escapes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapeSite {
+    pub block: BlockIndex,
+    pub instr: usize,
+    pub escape: Escape
+}
+
+/// Which arguments of the functions in a module let references passed in them escape, and which
+/// are returned, so the result of a call holds a reference from the argument
+#[derive(Debug, Clone, Default)]
+pub struct EscapeSummaries {
+    args: HashMap<Path, Vec<bool>>,
+    returned: HashMap<Path, Vec<bool>>
+}
+
+impl EscapeSummaries {
+    /// Summaries that know of no functions, so every call is to an unknown function
+    pub fn new() -> EscapeSummaries {
+        EscapeSummaries::default()
+    }
+
+    /// Summarize every function in a module and its submodules. Calls to functions outside of the
+    /// module are assumed to let their arguments escape
+    pub fn of_module(module: &Module) -> Result<EscapeSummaries> {
+        let mut functions = Vec::new();
+        collect_functions(module, None, &mut functions)?;
+        let mut summaries = EscapeSummaries::new();
+        for (_, path, nargs, _) in functions.iter() {
+            summaries.args.insert(path.clone(), vec![false; *nargs]);
+            summaries.returned.insert(path.clone(), vec![false; *nargs]);
+        }
+        // arguments start out neither escaping nor returned and are marked as either until
+        // nothing changes, which also settles functions that call each other
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (base, path, nargs, body) in functions.iter() {
+                for arg in 0..*nargs {
+                    if summaries.args[path][arg] && summaries.returned[path][arg] {
+                        continue;
+                    }
+                    let sites = escapes(body, &[arg as u32], base, &summaries);
+                    let escapes = sites.iter()
+                        .any(|site| !matches!(site.escape, Escape::Returned | Escape::Passed { escapes: Some(false), .. }));
+                    let returned = sites.iter().any(|site| site.escape == Escape::Returned);
+                    for (summary, found) in [(&mut summaries.args, escapes), (&mut summaries.returned, returned)] {
+                        let known = &mut summary.get_mut(path).unwrap()[arg];
+                        if found && !*known {
+                            *known = true;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(summaries)
+    }
+
+    /// Whether the function at the absolute path `func` lets its argument at index `arg` escape
+    /// other than by returning it, if the function is known
+    pub fn arg_escapes(&self, func: &Path, arg: usize) -> Option<bool> {
+        self.args.get(func).map(|args| args.get(arg).copied().unwrap_or(true))
+    }
+
+    /// Whether the function at the absolute path `func` can return a reference from its argument
+    /// at index `arg`, if the function is known
+    pub fn arg_returned(&self, func: &Path, arg: usize) -> Option<bool> {
+        self.returned.get(func).map(|args| args.get(arg).copied().unwrap_or(true))
+    }
+}
+
+/// the module path, absolute function path, number of arguments and body of each function in a
+/// module tree
+fn collect_functions<'m>(module: &'m Module, parent: Option<&Path>,
+    out: &mut Vec<(Path, Path, usize, &'m FnBody)>) -> Result<()>
+{
+    let path = match parent {
+        Some(parent) => module.path.resolve(parent)?,
+        None => module.path.clone()
+    };
+    for (name, (sig, body)) in module.functions.iter() {
+        out.push((path.clone(), path.child(name.clone()), sig.args.len(), body));
+    }
+    for sub in module.submodules.iter() {
+        collect_functions(sub, Some(&path), out)?;
+    }
+    Ok(())
+}
+
+/// The registers of a body that can hold a reference from one of the `roots` registers, or a
+/// reference into the same allocation. This doesn't depend on where the registers are written,
+/// so it includes every register that ever could
+fn aliases(body: &FnBody, roots: impl IntoIterator<Item = u32>) -> HashSet<u32> {
+    let mut derived: HashSet<u32> = roots.into_iter().collect();
+    let is_derived = |derived: &HashSet<u32>, v: &Value| matches!(v, Value::Reg(r) if derived.contains(&r.0));
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for instr in body.blocks.iter().flat_map(|b| b.instrs.iter()) {
+            let dest = match instr {
+                Instruction::LoadImm(d, v) if is_derived(&derived, v) => d,
+                Instruction::Phi(d, vals) if vals.iter().any(|(_, v)| is_derived(&derived, v)) => d,
+                Instruction::RefIndex(d, r, _) | Instruction::RefField(d, r, _) if derived.contains(&r.0) => d,
+                _ => continue
+            };
+            changed |= derived.insert(dest.0);
+        }
+    }
+    derived
+}
+
+/// the registers that allocations made by a body are put in
+fn allocations(body: &FnBody) -> impl Iterator<Item = u32> + '_ {
+    body.blocks.iter().flat_map(|b| b.instrs.iter()).filter_map(|instr| match instr {
+        Instruction::Alloc(d, _) | Instruction::AllocArray(d, _, _) | Instruction::StackAlloc(d, _)
+            | Instruction::StackAllocArray(d, _, _) | Instruction::CopyToStack(d, _)
+            | Instruction::CopyToHeap(d, _) => Some(d.0),
+        _ => None
+    })
+}
+
+/// The registers of a body that can hold a reference from one of the `roots` registers, or a
+/// reference into the same allocation. A reference stored in memory that the body allocated can
+/// be loaded back out of it, and leaves the function along with that memory, so the registers of
+/// the memory and of anything loaded from it are included too, as are the results of calls that
+/// `summaries` says can return a derived reference passed to them. Call paths are resolved against
+/// `base`, the path of the body's module. This doesn't depend on where the registers are written,
+/// so it includes every register that ever could
+pub fn derived_registers(body: &FnBody, roots: impl IntoIterator<Item = u32>, base: &Path,
+    summaries: &EscapeSummaries) -> HashSet<u32>
+{
+    let local: Vec<HashSet<u32>> = allocations(body).map(|a| aliases(body, [a])).collect();
+    let mut derived = aliases(body, roots);
+    // the registers of local allocations that a derived reference is stored in
+    let mut holders: HashSet<u32> = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let is_derived = |v: &Value| matches!(v, Value::Reg(r) if derived.contains(&r.0) || holders.contains(&r.0));
+        let mut held = HashSet::new();
+        let mut loaded = Vec::new();
+        for instr in body.blocks.iter().flat_map(|b| b.instrs.iter()) {
+            match instr {
+                Instruction::StoreRef(dest, v) | Instruction::StoreIndex(dest, _, v) | Instruction::StoreField(v, dest, _) if is_derived(v) =>
+                    held.extend(local.iter().filter(|a| a.contains(&dest.0)).flatten().copied()),
+                Instruction::LoadRef(d, r) | Instruction::LoadIndex(d, r, _) | Instruction::LoadField(d, r, _) if holders.contains(&r.0) =>
+                    loaded.push(d.0),
+                Instruction::Call(d, func, args) | Instruction::Invoke { dest: d, func, args, .. } => {
+                    let callee = func.resolve(base).unwrap_or_else(|_| func.clone());
+                    let returned = args.iter().enumerate()
+                        .any(|(arg, a)| is_derived(a) && summaries.arg_returned(&callee, arg) == Some(true));
+                    if returned {
+                        loaded.push(d.0);
+                    }
+                },
+                _ => {}
+            }
+        }
+        for r in held {
+            changed |= holders.insert(r);
+        }
+        for r in aliases(body, loaded) {
+            changed |= derived.insert(r);
+        }
+    }
+    derived.extend(holders);
+    derived
+}
+
+/// Find every instruction of a body through which a reference held in one of the `roots`
+/// registers, or a reference derived from it, can escape. Calls are looked up in `summaries`
+/// after resolving their paths against `base`, the path of the body's module
+pub fn escapes(body: &FnBody, roots: &[u32], base: &Path, summaries: &EscapeSummaries) -> Vec<EscapeSite> {
+    let derived = derived_registers(body, roots.iter().copied(), base, summaries);
+    let is_derived = |v: &Value| matches!(v, Value::Reg(r) if derived.contains(&r.0));
+    let local = aliases(body, allocations(body));
+
+    let mut sites = Vec::new();
+    for (b, block) in body.blocks.iter().enumerate() {
+        for (i, instr) in block.instrs.iter().enumerate() {
+            let mut found = |escape| sites.push(EscapeSite { block: b, instr: i, escape });
+            let stored = |dest: &Register, v: &Value| is_derived(v).then(|| Escape::Stored {
+                into_local: local.contains(&dest.0)
+            });
+            match instr {
+                Instruction::Return(v) if is_derived(v) => found(Escape::Returned),
+                Instruction::Throw(v) if is_derived(v) => found(Escape::Thrown),
+                Instruction::StoreRef(dest, v) | Instruction::StoreIndex(dest, _, v) | Instruction::StoreField(v, dest, _) => {
+                    if let Some(escape) = stored(dest, v) {
+                        found(escape);
+                    }
+                },
+                Instruction::Call(_, func, args) | Instruction::Invoke { func, args, .. } | Instruction::TailCall(func, args) => {
+                    let tail = matches!(instr, Instruction::TailCall(..));
+                    let callee = func.resolve(base).unwrap_or_else(|_| func.clone());
+                    for (arg, _) in args.iter().enumerate().filter(|(_, a)| is_derived(a)) {
+                        let escapes = summaries.arg_escapes(&callee, arg);
+                        found(Escape::Passed { callee: callee.clone(), arg, tail, escapes });
+                    }
+                },
+                // the implementation that runs is only known at run time
+                Instruction::CallImpl(_, func, args) | Instruction::TailCallImpl(func, args) => {
+                    let tail = matches!(instr, Instruction::TailCallImpl(..));
+                    for (arg, _) in args.iter().enumerate().filter(|(_, a)| is_derived(a)) {
+                        found(Escape::Passed { callee: func.clone(), arg, tail, escapes: None });
+                    }
+                },
+                Instruction::UnwrapVariant(_, _, v, _) if is_derived(v) => found(Escape::Unknown),
+                _ => {}
+            }
+        }
+    }
+    sites
+}
@@ -0,0 +1,78 @@
+//! Constant folding, which computes operations on literal values ahead of time
+use anyhow::Result;
+use crate::{FunctionSignature, FnBody};
+use crate::code::{BinOp, Instruction, UnaryOp, Value};
+use crate::numbers::Integer;
+use super::Pass;
+
+/// Replaces each [`BinaryOp`](Instruction::BinaryOp) and [`UnaryOp`](Instruction::UnaryOp) whose
+/// operands are all literals with a [`LoadImm`](Instruction::LoadImm) of its result
+pub struct ConstantFold;
+
+impl Pass for ConstantFold {
+    fn name(&self) -> &'static str {
+        "fold"
+    }
+
+    fn run(&mut self, _sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        let mut changed = false;
+        for instr in body.blocks.iter_mut().flat_map(|b| b.instrs.iter_mut()) {
+            let folded = match instr {
+                Instruction::BinaryOp(op, dest, a, b) => fold_binary(op, a, b).map(|v| (dest.clone(), v)),
+                Instruction::UnaryOp(op, dest, a) => fold_unary(op, a).map(|v| (dest.clone(), v)),
+                _ => None
+            };
+            if let Some((dest, v)) = folded {
+                *instr = Instruction::LoadImm(dest, v);
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// The result of a binary operation on two literals, computed the same way as the VM. Gives `None`
+/// if an operand is not a unit, bool or integer literal, or if the VM would trap
+pub fn fold_binary(op: &BinOp, a: &Value, b: &Value) -> Option<Value> {
+    use Value::*;
+    if let (LiteralInt(a), LiteralInt(b)) = (a, b) {
+        if a.signed != b.signed || a.width != b.width || !Integer::valid_width(a.width) {
+            return None;
+        }
+    }
+    Some(match (op, a, b) {
+        (BinOp::Add, LiteralInt(a), LiteralInt(b)) => LiteralInt(a.checked_add(*b)?),
+        (BinOp::Sub, LiteralInt(a), LiteralInt(b)) => LiteralInt(a.checked_sub(*b)?),
+        (BinOp::Mul, LiteralInt(a), LiteralInt(b)) => LiteralInt(a.checked_mul(*b)?),
+        (BinOp::Div, LiteralInt(a), LiteralInt(b)) => LiteralInt(a.checked_div(*b)?),
+        (BinOp::Eq, a, b) => LiteralBool(literal_eq(a, b)?),
+        (BinOp::NEq, a, b) => LiteralBool(!literal_eq(a, b)?),
+        _ => return None
+    })
+}
+
+/// The result of a unary operation on a literal, computed the same way as the VM. Gives `None` if
+/// the VM would trap
+pub fn fold_unary(op: &UnaryOp, a: &Value) -> Option<Value> {
+    Some(match (op, a) {
+        (UnaryOp::LogNot, Value::LiteralBool(v)) => Value::LiteralBool(!v),
+        (UnaryOp::BitNot, Value::LiteralInt(v)) if Integer::valid_width(v.width) => Value::LiteralInt(v.bitwise_negate()),
+        (UnaryOp::Neg, Value::LiteralInt(v)) => Value::LiteralInt(v.checked_negate()?),
+        _ => return None
+    })
+}
+
+/// whether two literals are equal as values in the VM, if they are literals that can be compared
+fn literal_eq(a: &Value, b: &Value) -> Option<bool> {
+    use Value::*;
+    let scalar = |v: &Value| matches!(v, LiteralUnit | LiteralBool(_) | LiteralInt(_));
+    if !scalar(a) || !scalar(b) {
+        return None;
+    }
+    Some(match (a, b) {
+        (LiteralUnit, LiteralUnit) => true,
+        (LiteralBool(a), LiteralBool(b)) => a == b,
+        (LiteralInt(a), LiteralInt(b)) => a == b,
+        _ => false
+    })
+}
@@ -0,0 +1,256 @@
+//! Inlining of calls to small functions, including functions from other modules
+use std::collections::HashMap;
+use anyhow::{Context, Result};
+use crate::{FunctionSignature, FnBody, Module, Path, VersionReq};
+use crate::code::{BasicBlock, BlockIndex, Instruction, Register, Value};
+use super::{resolved_modules, Pass};
+use super::registers::used_registers;
+
+/// Replaces each [`Call`](Instruction::Call) of a known function with a copy of the function's
+/// body. The copy uses new registers after the caller's, its blocks are added after the caller's
+/// and each return jumps to a new block holding the rest of the calling block, with a phi for the
+/// returned value. Tail calls in the copy become calls whose result is returned the same way.
+///
+/// A function is only inlined from a module that is loaded together with the caller, which is the
+/// caller's own module, its submodules or another module in the same package (added with
+/// [`add_module`](Inline::add_module)), or from a module outside of it that is marked
+/// [`inlinable`](Module::inlinable) (added with [`add_dependency`](Inline::add_dependency)),
+/// since inlined code stays the same when a newer version of the callee's module is loaded.
+/// Functions that make stack allocations are never inlined, as their allocations would last until
+/// the caller returns, and neither are functions that call themselves.
+pub struct Inline {
+    callees: HashMap<Path, Callee>,
+    /// the imports of each module that functions can be inlined from
+    imports: HashMap<Path, Vec<(Path, VersionReq)>>,
+    /// the most instructions a function can have to be inlined
+    pub max_callee_size: usize,
+    /// the most instructions a function can grow to by having calls inlined into it
+    pub max_caller_size: usize,
+    /// the module and function that calls are currently being inlined into, if known
+    caller: Option<(Path, Path)>,
+    /// the modules that functions were inlined from into the current module
+    inlined_from: Vec<Path>
+}
+
+/// a function that can be inlined
+struct Callee {
+    module: Path,
+    sig: FunctionSignature,
+    body: FnBody
+}
+
+impl Inline {
+    /// Create an inlining pass that knows of no functions to inline yet
+    pub fn new() -> Inline {
+        Inline {
+            callees: HashMap::new(),
+            imports: HashMap::new(),
+            max_callee_size: 32,
+            max_caller_size: 1024,
+            caller: None,
+            inlined_from: Vec::new()
+        }
+    }
+
+    /// Allow the functions in a module and its submodules to be inlined into modules that are in
+    /// the same package, or that are the module itself
+    pub fn add_module(&mut self, module: &Module) -> Result<()> {
+        for m in resolved_modules(module)? {
+            for (name, (sig, body)) in m.functions {
+                let path = m.path.child(name);
+                let size: usize = body.blocks.iter().map(|b| b.instrs.len()).sum();
+                let uses_stack = body.blocks.iter().flat_map(|b| b.instrs.iter()).any(|i| matches!(i,
+                    Instruction::StackAlloc(..) | Instruction::StackAllocArray(..) | Instruction::CopyToStack(..)));
+                // inlining a recursive function would only make a copy of the call to inline again
+                let recursive = body.blocks.iter().flat_map(|b| b.instrs.iter()).any(|i| matches!(i,
+                    Instruction::Call(_, p, _) | Instruction::TailCall(p, _) | Instruction::Invoke { func: p, .. } if *p == path));
+                if size <= self.max_callee_size && !uses_stack && !recursive && !body.blocks.is_empty() {
+                    self.callees.insert(path, Callee { module: m.path.clone(), sig, body });
+                }
+            }
+            self.imports.insert(m.path, m.imports);
+        }
+        Ok(())
+    }
+
+    /// Allow the functions in a module from outside the package being optimized to be inlined,
+    /// if it is marked inlinable. Returns whether it was
+    pub fn add_dependency(&mut self, module: &Module) -> Result<bool> {
+        if !module.inlinable {
+            return Ok(false);
+        }
+        self.add_module(module)?;
+        Ok(true)
+    }
+
+    /// inline calls in the blocks of `body` that existed before, and the blocks they split into
+    fn inline_calls(&mut self, nargs: usize, body: &mut FnBody) -> Result<bool> {
+        let mut size: usize = body.blocks.iter().map(|b| b.instrs.len()).sum();
+        let mut changed = false;
+        let mut worklist: Vec<BlockIndex> = (0..body.blocks.len()).rev().collect();
+        while let Some(b) = worklist.pop() {
+            let mut found = None;
+            for (i, instr) in body.blocks[b].instrs.iter().enumerate() {
+                if instr.is_terminator() {
+                    break;
+                }
+                let Instruction::Call(_, path, args) = instr else { continue };
+                let path = match &self.caller {
+                    Some((module, _)) => path.resolve(module)?,
+                    None => path.clone()
+                };
+                if self.caller.as_ref().is_some_and(|(_, f)| *f == path) {
+                    continue;
+                }
+                if let Some(callee) = self.callees.get(&path) {
+                    let callee_size: usize = callee.body.blocks.iter().map(|b| b.instrs.len()).sum();
+                    if callee.sig.args.len() == args.len() && size + callee_size <= self.max_caller_size {
+                        found = Some((i, path, callee_size));
+                        break;
+                    }
+                }
+            }
+            let Some((i, path, callee_size)) = found else { continue };
+            let callee = &self.callees[&path];
+            if !self.inlined_from.contains(&callee.module) {
+                self.inlined_from.push(callee.module.clone());
+            }
+            let rest = splice(body, nargs, b, i, callee);
+            size += callee_size;
+            changed = true;
+            worklist.push(rest);
+        }
+        Ok(changed)
+    }
+}
+
+impl Default for Inline {
+    fn default() -> Inline {
+        Inline::new()
+    }
+}
+
+/// replace the call at index `i` in block `b` of a body with `nargs` arguments with a copy of the
+/// callee, returning the index of the block that holds the instructions after the call
+fn splice(body: &mut FnBody, nargs: usize, b: BlockIndex, i: usize, callee: &Callee) -> BlockIndex {
+    let Instruction::Call(dest, _, args) = body.blocks[b].instrs[i].clone() else { unreachable!("splicing a call") };
+    let base = body.max_registers.max(used_registers(nargs, body));
+    let nargs = callee.sig.args.len();
+    let mut callee_body = callee.body.clone();
+    let mut next_reg = base + callee_body.max_registers.max(used_registers(nargs, &mut callee_body));
+    let entry = body.blocks.len();
+    let rest = entry + callee_body.blocks.len();
+
+    // the calling block moves the arguments into the callee's registers and jumps to its entry
+    let block = &mut body.blocks[b];
+    let after = block.instrs.split_off(i + 1);
+    block.instrs.pop();
+    let old_next = std::mem::replace(&mut block.next_block, entry);
+    for (j, a) in args.into_iter().enumerate() {
+        block.instrs.push(Instruction::LoadImm(Register(base + j as u32), a));
+    }
+    // phis in the entry block are skipped when a function is called, so their registers keep
+    // their initial values, which are the arguments or unit
+    for instr in callee_body.blocks[0].instrs.iter() {
+        if let Instruction::Phi(d, _) = instr {
+            if d.0 as usize >= nargs {
+                block.instrs.push(Instruction::LoadImm(Register(base + d.0), Value::LiteralUnit));
+            }
+        }
+    }
+    // the blocks that came after the calling block now come after the rest of it
+    let mut rest_block = BasicBlock { instrs: after, next_block: old_next };
+    for s in rest_block.successors().into_iter().filter(|s| *s < entry) {
+        for instr in body.blocks[s].instrs.iter_mut() {
+            if let Instruction::Phi(_, vals) = instr {
+                vals.iter_mut().filter(|(p, _)| *p == b).for_each(|(p, _)| *p = rest);
+            }
+        }
+    }
+
+    let mut returns = Vec::new();
+    for (cb, mut block) in callee_body.blocks.into_iter().enumerate() {
+        block.instrs.truncate(block.len_live());
+        for instr in block.instrs.iter_mut() {
+            instr.for_each_def_mut(&mut |r| r.0 += base);
+            instr.for_each_ref_operand_mut(&mut |r| r.0 += base);
+            instr.for_each_value_mut(&mut |v| if let Value::Reg(r) = v {
+                r.0 += base;
+            });
+            instr.for_each_target_mut(&mut |t| *t += entry);
+            if let Instruction::Phi(d, vals) = instr {
+                vals.iter_mut().for_each(|(p, _)| *p += entry);
+                if cb == 0 {
+                    vals.push((b, Value::Reg(d.clone())));
+                }
+            }
+        }
+        block.next_block += entry;
+        let returned = match block.instrs.pop() {
+            Some(Instruction::Return(v)) => Some(v),
+            Some(Instruction::TailCall(p, a)) => {
+                block.instrs.push(Instruction::Call(Register(next_reg), p, a));
+                next_reg += 1;
+                Some(Value::Reg(Register(next_reg - 1)))
+            },
+            Some(Instruction::TailCallImpl(p, a)) => {
+                block.instrs.push(Instruction::CallImpl(Register(next_reg), p, a));
+                next_reg += 1;
+                Some(Value::Reg(Register(next_reg - 1)))
+            },
+            Some(instr) => {
+                block.instrs.push(instr);
+                None
+            },
+            None => None
+        };
+        if let Some(v) = returned {
+            block.next_block = rest;
+            returns.push((entry + cb, v));
+        }
+        body.blocks.push(block);
+    }
+
+    rest_block.instrs.insert(0, Instruction::Phi(dest, returns));
+    body.blocks.push(rest_block);
+    body.max_registers = next_reg;
+    rest
+}
+
+impl Pass for Inline {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn run(&mut self, sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        if self.callees.is_empty() {
+            return Ok(false);
+        }
+        self.inline_calls(sig.args.len(), body)
+    }
+
+    fn run_module(&mut self, module: &mut Module) -> Result<bool> {
+        if self.callees.is_empty() {
+            return Ok(false);
+        }
+        // calls are matched by their absolute paths
+        module.resolve_relative_paths().with_context(|| format!("resolving paths in {}", module.path))?;
+        let mut changed = false;
+        let outer = std::mem::take(&mut self.inlined_from);
+        for (name, (sig, body)) in module.functions.iter_mut() {
+            self.caller = Some((module.path.clone(), module.path.child(name.clone())));
+            changed |= self.run(sig, body)
+                .with_context(|| format!("running inline pass on {}::{}", module.path, name.0))?;
+        }
+        self.caller = None;
+        // the inlined code may call functions in modules that only the callee's module imported
+        let inlined_from = std::mem::replace(&mut self.inlined_from, outer);
+        for from in inlined_from {
+            module.merge_imports_from(&self.imports[&from]);
+        }
+        for sub in module.submodules.iter_mut() {
+            changed |= self.run_module(sub)?;
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,127 @@
+//! Copy and constant propagation through [`LoadImm`](Instruction::LoadImm)
+use std::collections::HashMap;
+use anyhow::Result;
+use crate::{FunctionSignature, FnBody};
+use crate::code::{BlockIndex, Instruction, Value};
+use super::{Cfg, Pass};
+
+/// Replaces reads of a register that is only ever assigned by a single
+/// [`LoadImm`](Instruction::LoadImm) with the value it loads, wherever that value is known to
+/// still be the same. A literal replaces every read that the load dominates, and another register
+/// does so as long as that register is also assigned once, before the load. The loads themselves
+/// are left for [`DeadCode`](super::DeadCode) to remove
+pub struct CopyPropagation;
+
+/// where a register is assigned
+#[derive(Clone, Copy)]
+enum Def {
+    /// on entry to the function, as an argument
+    Entry,
+    /// by an instruction, at an index in a block
+    At(BlockIndex, usize)
+}
+
+impl Def {
+    /// whether every path to an instruction at `index` in `block` runs this definition first
+    fn dominates(self, cfg: &Cfg, block: BlockIndex, index: usize) -> bool {
+        match self {
+            Def::Entry => true,
+            Def::At(b, i) if b == block => i < index,
+            Def::At(b, _) => cfg.dominates(b, block)
+        }
+    }
+}
+
+impl Pass for CopyPropagation {
+    fn name(&self) -> &'static str {
+        "copy-prop"
+    }
+
+    fn run(&mut self, sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        let cfg = Cfg::new(body)?;
+        let mut defs: HashMap<u32, Vec<Def>> = HashMap::new();
+        for r in 0..sig.args.len() as u32 {
+            defs.entry(r).or_default().push(Def::Entry);
+        }
+        for (b, block) in body.blocks.iter_mut().enumerate() {
+            for (i, instr) in block.instrs.iter_mut().enumerate() {
+                instr.for_each_def_mut(&mut |r| defs.entry(r.0).or_default().push(Def::At(b, i)));
+            }
+        }
+        let single_def = |r: u32| match defs.get(&r).map(Vec::as_slice) {
+            Some([d]) => Some(*d),
+            _ => None
+        };
+
+        // the registers that can be replaced, with where they are assigned and their value
+        let mut copies = HashMap::new();
+        for (b, block) in body.blocks.iter().enumerate().filter(|(b, _)| cfg.is_reachable(*b)) {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                let Instruction::LoadImm(r, v) = instr else { continue };
+                if single_def(r.0).is_none() {
+                    continue;
+                }
+                let known = match v {
+                    Value::Reg(s) => s.0 != r.0 && single_def(s.0).is_some_and(|d| d.dominates(&cfg, b, i)),
+                    Value::LiteralFloat(_) | Value::LiteralString(_) => false,
+                    _ => true
+                };
+                if known {
+                    copies.insert(r.0, (Def::At(b, i), v.clone()));
+                }
+            }
+        }
+        // follow chains of copies to their source, which dominates every copy along the way
+        let resolve = |mut v: Value| {
+            for _ in 0..copies.len() {
+                match &v {
+                    Value::Reg(r) => match copies.get(&r.0) {
+                        Some((_, next)) => v = next.clone(),
+                        None => break
+                    },
+                    _ => break
+                }
+            }
+            v
+        };
+
+        let mut changed = false;
+        for (b, block) in body.blocks.iter_mut().enumerate().filter(|(b, _)| cfg.is_reachable(*b)) {
+            for (i, instr) in block.instrs.iter_mut().enumerate() {
+                if let Instruction::Phi(_, vals) = instr {
+                    // a phi reads its value for a block at the end of that block
+                    for (pred, v) in vals.iter_mut().filter(|(p, _)| cfg.is_reachable(*p)) {
+                        if let Value::Reg(r) = v {
+                            if let Some((def, new)) = copies.get(&r.0) {
+                                if def.dominates(&cfg, *pred, usize::MAX) {
+                                    *v = resolve(new.clone());
+                                    changed = true;
+                                }
+                            }
+                        }
+                    }
+                    continue;
+                }
+                instr.for_each_value_mut(&mut |v| {
+                    if let Value::Reg(r) = v {
+                        if let Some((def, new)) = copies.get(&r.0) {
+                            if def.dominates(&cfg, b, i) {
+                                *v = resolve(new.clone());
+                                changed = true;
+                            }
+                        }
+                    }
+                });
+                instr.for_each_ref_operand_mut(&mut |r| {
+                    if let Some((def, new)) = copies.get(&r.0) {
+                        if let (true, Value::Reg(s)) = (def.dominates(&cfg, b, i), resolve(new.clone())) {
+                            *r = s;
+                            changed = true;
+                        }
+                    }
+                });
+            }
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,37 @@
+//! Recomputing the number of registers a function body uses
+use anyhow::Result;
+use crate::{FunctionSignature, FnBody};
+use crate::code::Value;
+use super::Pass;
+
+/// Sets [`max_registers`](FnBody::max_registers) to the number of registers that the body
+/// actually uses, which is one more than the highest register it names and at least enough to
+/// hold the arguments
+pub struct CountRegisters;
+
+impl Pass for CountRegisters {
+    fn name(&self) -> &'static str {
+        "registers"
+    }
+
+    fn run(&mut self, sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        let count = used_registers(sig.args.len(), body);
+        let changed = body.max_registers != count;
+        body.max_registers = count;
+        Ok(changed)
+    }
+}
+
+/// the number of registers that a body with `args` arguments uses
+pub(super) fn used_registers(args: usize, body: &mut FnBody) -> u32 {
+    let mut count = args as u32;
+    for instr in body.blocks.iter_mut().flat_map(|b| b.instrs.iter_mut()) {
+        let mut count_reg = |r: u32| count = count.max(r + 1);
+        instr.for_each_def_mut(&mut |r| count_reg(r.0));
+        instr.for_each_ref_operand_mut(&mut |r| count_reg(r.0));
+        instr.for_each_value_mut(&mut |v| if let Value::Reg(r) = v {
+            count_reg(r.0)
+        });
+    }
+    count
+}
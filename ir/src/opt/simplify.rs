@@ -0,0 +1,81 @@
+//! Simplification of the control flow graph
+use std::collections::HashSet;
+use anyhow::Result;
+use crate::{FunctionSignature, FnBody};
+use crate::code::{BasicBlock, BlockIndex, Instruction, Value};
+use super::{Cfg, Pass};
+
+/// Turns each [`Br`](Instruction::Br) on a literal into falling through to the block it always
+/// goes to, and merges each block into the block that falls through to it when that is the only
+/// block that can run before it. Blocks that are left behind are removed by
+/// [`UnreachableBlocks`](super::UnreachableBlocks)
+pub struct SimplifyCfg;
+
+impl Pass for SimplifyCfg {
+    fn name(&self) -> &'static str {
+        "simplify-cfg"
+    }
+
+    fn run(&mut self, _sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        let mut changed = false;
+        for block in body.blocks.iter_mut() {
+            let Some(i) = block.instrs.iter().position(Instruction::is_terminator) else { continue };
+            if let Instruction::Br { cond: Value::LiteralBool(c), if_true, if_false } = block.instrs[i] {
+                block.next_block = if c { if_true } else { if_false };
+                block.instrs.truncate(i);
+                changed = true;
+            }
+        }
+
+        let mut cfg = Cfg::new(body)?;
+        let mut a = 0;
+        while a < body.blocks.len() {
+            let b = body.blocks[a].next_block;
+            let mergeable = cfg.is_reachable(a) && body.blocks[a].falls_through()
+                && b != 0 && b != a && cfg.preds[b] == [a];
+            if mergeable {
+                if let Some(instrs) = merged_instrs(&body.blocks[b], a) {
+                    let next = body.blocks[b].next_block;
+                    body.blocks[b].instrs.clear();
+                    let block = &mut body.blocks[a];
+                    block.instrs.extend(instrs);
+                    block.next_block = next;
+                    // the blocks that came after `b` now come after `a`
+                    for s in body.blocks[a].successors() {
+                        for instr in body.blocks[s].instrs.iter_mut() {
+                            if let Instruction::Phi(_, vals) = instr {
+                                vals.iter_mut().filter(|(p, _)| *p == b).for_each(|(p, _)| *p = a);
+                            }
+                        }
+                    }
+                    changed = true;
+                    cfg = Cfg::new(body)?;
+                    // `a` may now fall through to another block that can be merged
+                    continue;
+                }
+            }
+            a += 1;
+        }
+        Ok(changed)
+    }
+}
+
+/// the instructions of `block` to run at the end of `pred`, which is the only block that can run
+/// before it, with its phis turned into loads of their values for `pred`. Gives `None` if the
+/// phis read each other, since they would no longer run together
+fn merged_instrs(block: &BasicBlock, pred: BlockIndex) -> Option<Vec<Instruction>> {
+    let dests: HashSet<u32> = block.instrs.iter().filter_map(|i| match i {
+        Instruction::Phi(d, _) => Some(d.0),
+        _ => None
+    }).collect();
+    block.instrs.iter().map(|instr| match instr {
+        Instruction::Phi(d, vals) => {
+            let (_, v) = vals.iter().find(|(p, _)| *p == pred)?;
+            match v {
+                Value::Reg(r) if r.0 != d.0 && dests.contains(&r.0) => None,
+                v => Some(Instruction::LoadImm(d.clone(), v.clone()))
+            }
+        },
+        instr => Some(instr.clone())
+    }).collect()
+}
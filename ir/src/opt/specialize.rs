@@ -0,0 +1,557 @@
+//! Partial evaluation, which specializes functions for arguments that are known ahead of time
+use std::collections::{HashMap, HashSet};
+use anyhow::{anyhow, bail, Context, Result};
+use crate::{FunctionSignature, FnBody, Module, Path, Symbol, VersionReq};
+use crate::numbers::Integer;
+use crate::code::{BasicBlock, BlockIndex, Instruction, Register, Value};
+use super::{fold_binary, fold_unary, resolved_modules, PassManager};
+use super::registers::used_registers;
+
+/// the passes that tidy up specialized code, which is made without merging blocks or removing
+/// copies
+const CLEANUP_PASSES: &[&str] = &["copy-prop", "fold", "simplify-cfg", "unreachable", "dce", "registers"];
+
+/// Creates specialized copies of functions for some of their arguments, by running their code
+/// with the known arguments and keeping only the instructions that depend on the others. The
+/// specialized copy takes the remaining arguments in the same order.
+///
+/// Each block is copied once for every combination of register values known when it starts, so a
+/// branch on a known value becomes a jump and a loop with a known number of iterations is
+/// unrolled. Phis select their values for the block that was actually jumped from, and become
+/// phis again in the copy only for the registers that aren't known. Operations on known values are
+/// computed unless they would trap, and calls to known functions with some known arguments call a
+/// specialization of the callee instead, or are replaced by the result if the specialization
+/// always returns the same value without doing anything else.
+///
+/// Specializing always finishes: once a block has [`max_variants`](Specializer::max_variants)
+/// copies, a new copy only keeps the registers known to have the same value in all of them, and no
+/// more than [`max_specializations`](Specializer::max_specializations) specializations are made of
+/// each function, nested at most [`max_depth`](Specializer::max_depth) deep. Calls past these
+/// limits call the original function.
+///
+/// Functions are only known if they are in a module added with
+/// [`add_module`](Specializer::add_module), or in the module that the specialization is added to.
+/// Like inlining, this copies their code, so it stays the same if a newer version of their module
+/// is loaded.
+pub struct Specializer {
+    functions: HashMap<Path, Function>,
+    /// the imports of each module that functions are known from
+    imports: HashMap<Path, Vec<(Path, VersionReq)>>,
+    /// the most copies of a block with different known values before they are merged
+    pub max_variants: usize,
+    /// the most specializations that can be in progress at once, each inside a call in another
+    pub max_depth: usize,
+    /// the most specializations of a single function
+    pub max_specializations: usize,
+    /// the specializations made of each function, for each combination of known arguments
+    specs: HashMap<Path, Vec<(Env, Spec)>>,
+    depth: usize,
+    /// the module that specialized functions are added to
+    target: Path,
+    /// the names of functions in the target module, including the specializations
+    taken: HashSet<Symbol>,
+    finished: HashMap<Symbol, (FunctionSignature, FnBody)>,
+    /// the modules that specialized code was copied from
+    used: Vec<Path>
+}
+
+#[derive(Clone)]
+struct Function {
+    module: Path,
+    sig: FunctionSignature,
+    body: FnBody
+}
+
+/// a specialization of a function, or the value it always returns
+#[derive(Clone)]
+enum Spec {
+    Residual(Path),
+    Constant(Known)
+}
+
+/// what a call becomes in specialized code
+enum Called {
+    Constant(Known),
+    Residual(Path, Vec<Value>)
+}
+
+/// A value that is known while specializing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Known {
+    Unit,
+    Bool(bool),
+    Int(Integer)
+}
+
+impl Known {
+    /// the known value of a literal, if copying it doesn't change what the code does
+    fn from_value(v: &Value) -> Option<Known> {
+        match v {
+            Value::LiteralUnit => Some(Known::Unit),
+            Value::LiteralBool(b) => Some(Known::Bool(*b)),
+            Value::LiteralInt(i) => Some(Known::Int(*i)),
+            _ => None
+        }
+    }
+
+    fn to_value(self) -> Value {
+        match self {
+            Known::Unit => Value::LiteralUnit,
+            Known::Bool(b) => Value::LiteralBool(b),
+            Known::Int(i) => Value::LiteralInt(i)
+        }
+    }
+}
+
+/// the value of each register if it is known, or `None` if it is only known at run time
+type Env = Vec<Option<Known>>;
+
+impl Specializer {
+    /// Create a specializer that knows of no functions outside the module being specialized
+    pub fn new() -> Specializer {
+        Specializer {
+            functions: HashMap::new(),
+            imports: HashMap::new(),
+            max_variants: 16,
+            max_depth: 64,
+            max_specializations: 64,
+            specs: HashMap::new(),
+            depth: 0,
+            target: Path(Vec::new()),
+            taken: HashSet::new(),
+            finished: HashMap::new(),
+            used: Vec::new()
+        }
+    }
+
+    /// Allow calls to the functions in a module and its submodules to be specialized
+    pub fn add_module(&mut self, module: &Module) -> Result<()> {
+        for m in resolved_modules(module)? {
+            for (name, (sig, body)) in m.functions {
+                self.functions.insert(m.path.child(name), Function { module: m.path.clone(), sig, body });
+            }
+            self.imports.insert(m.path, m.imports);
+        }
+        Ok(())
+    }
+
+    /// The signature of a known function, with absolute paths
+    pub fn signature(&self, func: &Path) -> Option<&FunctionSignature> {
+        self.functions.get(func).map(|f| &f.sig)
+    }
+
+    /// Specialize the function at the absolute path `func` for the arguments in `args` that are
+    /// known, which must be unit, bool or integer literals, while `None` marks an argument that is
+    /// only known at run time. The specialization is added to `module` as `name`, or a new name
+    /// made from the function's name, along with the specializations of the functions it calls.
+    /// Returns the name of the specialization
+    pub fn specialize(&mut self, module: &mut Module, func: &Path, args: &[Option<Value>], name: Option<Symbol>) -> Result<Symbol> {
+        module.resolve_relative_paths().with_context(|| format!("resolving paths in {}", module.path))?;
+        self.add_module(module)?;
+        let f = self.functions.get(func).ok_or_else(|| anyhow!("{} is not a known function", func))?;
+        if f.sig.args.len() != args.len() {
+            bail!("{} takes {} arguments, but {} were given", func, f.sig.args.len(), args.len());
+        }
+        let known = args.iter().map(|a| match a {
+            Some(v) => Known::from_value(v).map(Some)
+                .ok_or_else(|| anyhow!("only unit, bool and integer arguments can be known, not {:?}", v)),
+            None => Ok(None)
+        }).collect::<Result<Env>>()?;
+
+        self.target = module.path.clone();
+        self.taken = module.functions.keys().cloned().collect();
+        let name = match name {
+            Some(name) if !self.taken.insert(name.clone()) => bail!("{} already has a function called {}", module.path, name.0),
+            Some(name) => name,
+            None => self.fresh_name(func.last())
+        };
+        let result = self.add_spec(func, &known, name.clone());
+
+        // add the specialization and every specialization that it calls
+        let mut worklist = vec![name.clone()];
+        while let Some(n) = worklist.pop() {
+            let Some((sig, body)) = self.finished.remove(&n) else { continue };
+            for instr in body.blocks.iter().flat_map(|b| b.instrs.iter()) {
+                if let Instruction::Call(_, p, _) | Instruction::TailCall(p, _) | Instruction::Invoke { func: p, .. } = instr {
+                    if p.0.len() == self.target.0.len() + 1 && p.starts_with(&self.target) {
+                        worklist.push(p.last().clone());
+                    }
+                }
+            }
+            module.functions.insert(n, (sig, body));
+        }
+        // specialized code may call functions in modules that only the original's module imported
+        for from in std::mem::take(&mut self.used) {
+            module.merge_imports_from(&self.imports[&from]);
+        }
+        self.specs.clear();
+        self.finished.clear();
+        self.taken.clear();
+        result?;
+        Ok(name)
+    }
+
+    /// a name for a specialization of a function that isn't used yet
+    fn fresh_name(&mut self, base: &Symbol) -> Symbol {
+        (1..).map(|n| Symbol(format!("{}_{}", base.0, n)))
+            .find(|name| self.taken.insert(name.clone()))
+            .expect("there is always a free name")
+    }
+
+    /// specialize a function as `name`, remembering it for calls with the same known arguments
+    fn add_spec(&mut self, func: &Path, known: &Env, name: Symbol) -> Result<Spec> {
+        let path = self.target.child(name.clone());
+        // recursive calls with the same arguments call the specialization being made
+        self.specs.entry(func.clone()).or_default().push((known.clone(), Spec::Residual(path.clone())));
+        self.depth += 1;
+        let result = self.specialize_fn(func, known);
+        self.depth -= 1;
+        let (sig, body) = result.with_context(|| format!("specializing {}", func))?;
+        let spec = match constant(&body) {
+            Some(x) => Spec::Constant(x),
+            None => Spec::Residual(path)
+        };
+        if let Some((_, s)) = self.specs.get_mut(func).and_then(|s| s.iter_mut().find(|(a, _)| a == known)) {
+            *s = spec.clone();
+        }
+        self.finished.insert(name, (sig, body));
+        Ok(spec)
+    }
+
+    /// make the signature and body of a specialization of a function
+    fn specialize_fn(&mut self, func: &Path, known: &Env) -> Result<(FunctionSignature, FnBody)> {
+        let f = self.functions[func].clone();
+        if !self.used.contains(&f.module) {
+            self.used.push(f.module.clone());
+        }
+        let mut src = f.body;
+        if src.blocks.is_empty() {
+            bail!("{} has no code", func);
+        }
+        let nregs = src.max_registers.max(used_registers(known.len(), &mut src)) as usize;
+        let sig = FunctionSignature {
+            args: f.sig.args.iter().zip(known.iter()).filter(|(_, a)| a.is_none()).map(|(a, _)| a.clone()).collect(),
+            return_type: f.sig.return_type.clone()
+        };
+
+        // the unknown arguments come first in the specialized registers, so every other register
+        // moves up by their number, and a new entry block moves the arguments into place. Every
+        // register that isn't an argument starts out as unit
+        let k = sig.args.len() as u32;
+        let mut res = Residual {
+            k,
+            max_variants: self.max_variants,
+            blocks: Vec::new(),
+            variants: Vec::new(),
+            by_block: vec![Vec::new(); src.blocks.len()],
+            worklist: Vec::new()
+        };
+        let mut entry = BasicBlock { instrs: Vec::new(), next_block: 0 };
+        let mut env: Env = vec![Some(Known::Unit); nregs];
+        let mut next_arg = 0;
+        for (i, a) in known.iter().enumerate() {
+            env[i] = *a;
+            if a.is_none() {
+                entry.instrs.push(Instruction::LoadImm(Register(i as u32 + k), Value::Reg(Register(next_arg))));
+                next_arg += 1;
+            }
+        }
+        res.blocks.push(entry);
+        res.blocks[0].next_block = res.enter(&src, 0, None, 0, env)?;
+        while let Some(v) = res.worklist.pop() {
+            self.run_variant(&src, &mut res, v)?;
+        }
+
+        let mut body = FnBody { max_registers: nregs as u32 + k, blocks: res.finish(&src)? };
+        PassManager::from_names(CLEANUP_PASSES)?.run(&sig, &mut body)?;
+        Ok((sig, body))
+    }
+
+    /// fill in the specialized block for a variant of a block
+    fn run_variant(&mut self, src: &FnBody, res: &mut Residual, v: usize) -> Result<()> {
+        let (block, mut env, rb) = {
+            let var = &res.variants[v];
+            (var.block, var.env.clone(), var.residual)
+        };
+        let k = res.k;
+        let reg = |r: &Register| Register(r.0 + k);
+        let mut out = Vec::new();
+        let mut next = None;
+        let instrs = &src.blocks[block].instrs;
+        let mut ended = false;
+        for instr in instrs[src.blocks[block].phi_count()..].iter() {
+            ended = instr.is_terminator();
+            match instr {
+                Instruction::Phi(..) => bail!("block {} has a phi after other instructions", block),
+                Instruction::Br { cond, if_true, if_false } => match operand(&env, k, cond) {
+                    (Some(Known::Bool(c)), _) => {
+                        let target = if c { *if_true } else { *if_false };
+                        next = Some(res.enter(src, target, Some(block), rb, env.clone())?);
+                    },
+                    (_, cond) => {
+                        let if_true = res.enter(src, *if_true, Some(block), rb, env.clone())?;
+                        let if_false = res.enter(src, *if_false, Some(block), rb, env.clone())?;
+                        out.push(Instruction::Br { cond, if_true, if_false });
+                    }
+                },
+                Instruction::BinaryOp(op, d, a, b) => {
+                    let ((ka, a), (kb, b)) = (operand(&env, k, a), operand(&env, k, b));
+                    let folded = match (ka, kb) {
+                        (Some(x), Some(y)) => fold_binary(op, &x.to_value(), &y.to_value()).as_ref().and_then(Known::from_value),
+                        _ => None
+                    };
+                    if folded.is_none() {
+                        out.push(Instruction::BinaryOp(op.clone(), reg(d), a, b));
+                    }
+                    env[d.0 as usize] = folded;
+                },
+                Instruction::UnaryOp(op, d, a) => {
+                    let (ka, a) = operand(&env, k, a);
+                    let folded = ka.and_then(|x| fold_unary(op, &x.to_value())).as_ref().and_then(Known::from_value);
+                    if folded.is_none() {
+                        out.push(Instruction::UnaryOp(op.clone(), reg(d), a));
+                    }
+                    env[d.0 as usize] = folded;
+                },
+                Instruction::LoadImm(d, v) => {
+                    let (x, v) = operand(&env, k, v);
+                    if x.is_none() {
+                        out.push(Instruction::LoadImm(reg(d), v));
+                    }
+                    env[d.0 as usize] = x;
+                },
+                Instruction::Call(d, func, args) => match self.call(func, args, &env, k)? {
+                    Called::Constant(x) => env[d.0 as usize] = Some(x),
+                    Called::Residual(func, args) => {
+                        out.push(Instruction::Call(reg(d), func, args));
+                        env[d.0 as usize] = None;
+                    }
+                },
+                Instruction::TailCall(func, args) => out.push(match self.call(func, args, &env, k)? {
+                    Called::Constant(x) => Instruction::Return(x.to_value()),
+                    Called::Residual(func, args) => Instruction::TailCall(func, args)
+                }),
+                Instruction::Invoke { dest, func, args, normal, unwind, exception } => {
+                    let args = args.iter().map(|a| operand(&env, k, a).1).collect();
+                    let mut normal_env = env.clone();
+                    normal_env[dest.0 as usize] = None;
+                    let mut unwind_env = env.clone();
+                    unwind_env[exception.0 as usize] = None;
+                    out.push(Instruction::Invoke {
+                        dest: reg(dest),
+                        func: func.clone(),
+                        args,
+                        normal: res.enter(src, *normal, Some(block), rb, normal_env)?,
+                        unwind: res.enter(src, *unwind, Some(block), rb, unwind_env)?,
+                        exception: reg(exception)
+                    });
+                },
+                _ => {
+                    // everything else runs at run time, with known values filled in
+                    let mut instr = instr.clone();
+                    instr.for_each_ref_operand_mut(&mut |r| {
+                        // registers read as references can't be replaced by literals
+                        if let Some(x) = env[r.0 as usize].take() {
+                            out.push(Instruction::LoadImm(reg(r), x.to_value()));
+                        }
+                        r.0 += k;
+                    });
+                    instr.for_each_value_mut(&mut |v| *v = operand(&env, k, v).1);
+                    instr.for_each_def_mut(&mut |r| {
+                        env[r.0 as usize] = None;
+                        r.0 += k;
+                    });
+                    out.push(instr);
+                }
+            }
+            if ended {
+                break;
+            }
+        }
+        if !ended && next.is_none() {
+            next = Some(res.enter(src, src.blocks[block].next_block, Some(block), rb, env)?);
+        }
+        let out_block = &mut res.blocks[rb];
+        out_block.instrs = out;
+        if let Some(next) = next {
+            out_block.next_block = next;
+        }
+        Ok(())
+    }
+
+    /// what a call to `func` becomes, given what is known about its arguments
+    fn call(&mut self, func: &Path, args: &[Value], env: &Env, k: u32) -> Result<Called> {
+        let ops: Vec<_> = args.iter().map(|a| operand(env, k, a)).collect();
+        let original = Called::Residual(func.clone(), ops.iter().map(|(_, v)| v.clone()).collect());
+        let Some(f) = self.functions.get(func) else { return Ok(original) };
+        let known: Env = ops.iter().map(|(x, _)| *x).collect();
+        if f.sig.args.len() != args.len() || known.iter().all(Option::is_none) {
+            return Ok(original);
+        }
+        let specs = self.specs.get(func);
+        let spec = match specs.and_then(|s| s.iter().find(|(a, _)| *a == known)) {
+            Some((_, spec)) => spec.clone(),
+            None if self.depth >= self.max_depth || specs.map_or(0, Vec::len) >= self.max_specializations => return Ok(original),
+            None => {
+                let name = self.fresh_name(func.last());
+                self.add_spec(func, &known, name)?
+            }
+        };
+        Ok(match spec {
+            Spec::Constant(x) => Called::Constant(x),
+            Spec::Residual(path) => Called::Residual(path, ops.into_iter().filter(|(x, _)| x.is_none()).map(|(_, v)| v).collect())
+        })
+    }
+}
+
+impl Default for Specializer {
+    fn default() -> Specializer {
+        Specializer::new()
+    }
+}
+
+/// a copy of a source block for the register values known when it starts
+struct Variant {
+    block: BlockIndex,
+    env: Env,
+    /// the index of the copy in the specialized body
+    residual: BlockIndex,
+    preds: Vec<Edge>
+}
+
+/// a jump into a variant from a specialized block
+struct Edge {
+    from: BlockIndex,
+    /// the source block that the jump comes from, or `None` for the function's entry
+    src_pred: Option<BlockIndex>,
+    /// the known register values at the end of the block that jumps
+    env: Env
+}
+
+/// the specialized body of a function as it is made
+struct Residual {
+    /// the number of unknown arguments, which every other register moves up by
+    k: u32,
+    max_variants: usize,
+    blocks: Vec<BasicBlock>,
+    variants: Vec<Variant>,
+    /// the variants of each source block
+    by_block: Vec<Vec<usize>>,
+    /// variants whose blocks haven't been filled in yet
+    worklist: Vec<usize>
+}
+
+impl Residual {
+    /// the specialized block to jump to from block `from` to run source `block`, given the known
+    /// register values before the block's phis
+    fn enter(&mut self, src: &FnBody, block: BlockIndex, src_pred: Option<BlockIndex>, from: BlockIndex, env: Env) -> Result<BlockIndex> {
+        let Some(target) = src.blocks.get(block) else {
+            bail!("block {} continues at block {}, but there are only {} blocks", src_pred.unwrap_or(0), block, src.blocks.len())
+        };
+        // phis are skipped when the function is entered
+        let mut entry = env.clone();
+        if let Some(p) = src_pred {
+            for instr in target.instrs.iter() {
+                let Instruction::Phi(d, vals) = instr else { break };
+                entry[d.0 as usize] = operand(&env, self.k, phi_value(vals, p, block)?).0;
+            }
+        }
+
+        let variants = self.by_block[block].clone();
+        let find = |entry: &Env| variants.iter().copied().find(|&v| self.variants[v].env == *entry);
+        let v = match find(&entry) {
+            Some(v) => v,
+            None => {
+                // too many copies, so only the registers known to be the same in all of them stay
+                // known, which makes fewer variants each time and so ends any loop
+                if variants.len() >= self.max_variants {
+                    for &v in variants.iter() {
+                        for (x, y) in entry.iter_mut().zip(self.variants[v].env.iter()) {
+                            if *x != *y {
+                                *x = None;
+                            }
+                        }
+                    }
+                }
+                match find(&entry) {
+                    Some(v) => v,
+                    None => {
+                        self.blocks.push(BasicBlock { instrs: Vec::new(), next_block: 0 });
+                        self.variants.push(Variant { block, env: entry, residual: self.blocks.len() - 1, preds: Vec::new() });
+                        self.by_block[block].push(self.variants.len() - 1);
+                        self.worklist.push(self.variants.len() - 1);
+                        self.variants.len() - 1
+                    }
+                }
+            }
+        };
+
+        let var = &mut self.variants[v];
+        match var.preds.iter().find(|e| e.from == from) {
+            Some(e) if e.env != env => bail!("block {} jumps to block {} twice with different values", src_pred.unwrap_or(0), block),
+            Some(_) => {},
+            None => var.preds.push(Edge { from, src_pred, env })
+        }
+        Ok(var.residual)
+    }
+
+    /// the specialized blocks, with phis for the registers that aren't known at the start of a
+    /// variant but either are known or are set by a phi in a block that jumps to it
+    fn finish(mut self, src: &FnBody) -> Result<Vec<BasicBlock>> {
+        for var in self.variants.iter() {
+            let mut phis = Vec::new();
+            for r in (0..var.env.len()).filter(|r| var.env[*r].is_none()) {
+                let vals = var.preds.iter()
+                    .map(|e| Ok((e.from, edge_value(src, var.block, e, r, self.k)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                if vals.iter().any(|(_, v)| !matches!(v, Value::Reg(s) if s.0 == r as u32 + self.k)) {
+                    phis.push(Instruction::Phi(Register(r as u32 + self.k), vals));
+                }
+            }
+            self.blocks[var.residual].instrs.splice(0..0, phis);
+        }
+        Ok(self.blocks)
+    }
+}
+
+/// the value that register `r` has after jumping along an edge into a copy of `block`
+fn edge_value(src: &FnBody, block: BlockIndex, edge: &Edge, r: usize, k: u32) -> Result<Value> {
+    if let Some(p) = edge.src_pred {
+        for instr in src.blocks[block].instrs.iter() {
+            match instr {
+                Instruction::Phi(d, vals) if d.0 as usize == r => return Ok(operand(&edge.env, k, phi_value(vals, p, block)?).1),
+                Instruction::Phi(..) => {},
+                _ => break
+            }
+        }
+    }
+    Ok(match edge.env[r] {
+        Some(x) => x.to_value(),
+        None => Value::Reg(Register(r as u32 + k))
+    })
+}
+
+fn phi_value(vals: &[(BlockIndex, Value)], pred: BlockIndex, block: BlockIndex) -> Result<&Value> {
+    vals.iter().find(|(p, _)| *p == pred).map(|(_, v)| v)
+        .ok_or_else(|| anyhow!("phi in block {} has no value for block {}", block, pred))
+}
+
+/// what is known about a value, and the value to use for it in specialized code
+fn operand(env: &Env, k: u32, v: &Value) -> (Option<Known>, Value) {
+    match v {
+        Value::Reg(r) => match env[r.0 as usize] {
+            Some(x) => (Some(x), x.to_value()),
+            None => (None, Value::Reg(Register(r.0 + k)))
+        },
+        v => (Known::from_value(v), v.clone())
+    }
+}
+
+/// the value a specialized function always returns, if that is all it does
+fn constant(body: &FnBody) -> Option<Known> {
+    let block = body.blocks.first()?;
+    match &block.instrs[..block.len_live()] {
+        [Instruction::Return(v)] => Known::from_value(v),
+        _ => None
+    }
+}
@@ -0,0 +1,118 @@
+//! Moving heap allocations that never outlive their function onto the stack
+use anyhow::{Context, Result};
+use crate::{FunctionSignature, FnBody, Module, Path};
+use crate::code::{BlockIndex, Instruction, Value};
+use super::Pass;
+use super::escape::{escapes, EscapeSummaries};
+
+/// Replaces each [`Alloc`](Instruction::Alloc) or [`AllocArray`](Instruction::AllocArray) whose
+/// reference never escapes the function with a [`StackAlloc`](Instruction::StackAlloc) or
+/// [`StackAllocArray`](Instruction::StackAllocArray), which is freed when the function returns
+/// instead of being left for the garbage collector.
+///
+/// A reference escapes if it, or a reference into the same allocation, is returned, thrown, stored
+/// in memory or passed to a function that might keep it. When run over a module, calls to the
+/// module's own functions are followed to find out which of their arguments escape, otherwise
+/// every call is assumed to keep its arguments. Allocations in loops are left alone, since each
+/// time around the loop would make a new stack allocation that lasts until the function returns,
+/// and so are arrays unless they have a small constant length.
+pub struct StackPromotion {
+    /// the most elements an array can have to be moved onto the stack
+    pub max_array_len: u64,
+    summaries: EscapeSummaries,
+    /// the path of the module of the function being optimized
+    base: Path
+}
+
+impl StackPromotion {
+    /// Create a stack promotion pass
+    pub fn new() -> StackPromotion {
+        StackPromotion { max_array_len: 256, summaries: EscapeSummaries::new(), base: Path(Vec::new()) }
+    }
+
+    fn promote(&self, body: &mut FnBody) -> bool {
+        let mut promote = Vec::new();
+        for (b, block) in body.blocks.iter().enumerate() {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                let dest = match instr {
+                    Instruction::Alloc(d, _) => d,
+                    Instruction::AllocArray(d, _, Value::LiteralInt(n))
+                        if !n.signed && n.data <= self.max_array_len => d,
+                    _ => continue
+                };
+                let stays = escapes(body, &[dest.0], &self.base, &self.summaries).iter()
+                    .all(|site| !site.escape.may_outlive());
+                if stays && !in_loop(body, b) {
+                    promote.push((b, i));
+                }
+            }
+        }
+        for &(b, i) in promote.iter() {
+            let instr = &mut body.blocks[b].instrs[i];
+            *instr = match instr.clone() {
+                Instruction::Alloc(d, ty) => Instruction::StackAlloc(d, ty),
+                Instruction::AllocArray(d, ty, n) => Instruction::StackAllocArray(d, ty, n),
+                _ => unreachable!("promoting an allocation")
+            };
+        }
+        !promote.is_empty()
+    }
+
+    /// promote allocations in the functions of a module and its submodules
+    fn promote_in(&mut self, module: &mut Module, parent: Option<&Path>) -> Result<bool> {
+        self.base = match parent {
+            Some(parent) => module.path.resolve(parent)?,
+            None => module.path.clone()
+        };
+        let mut changed = false;
+        for (_, body) in module.functions.values_mut() {
+            changed |= self.promote(body);
+        }
+        let path = self.base.clone();
+        for sub in module.submodules.iter_mut() {
+            changed |= self.promote_in(sub, Some(&path))?;
+        }
+        Ok(changed)
+    }
+}
+
+impl Default for StackPromotion {
+    fn default() -> StackPromotion {
+        StackPromotion::new()
+    }
+}
+
+/// whether block `b` can run again after it runs
+fn in_loop(body: &FnBody, b: BlockIndex) -> bool {
+    let mut seen = vec![false; body.blocks.len()];
+    let mut stack = body.blocks[b].successors();
+    while let Some(s) = stack.pop() {
+        if s == b {
+            return true;
+        }
+        if s < seen.len() && !seen[s] {
+            seen[s] = true;
+            stack.extend(body.blocks[s].successors());
+        }
+    }
+    false
+}
+
+impl Pass for StackPromotion {
+    fn name(&self) -> &'static str {
+        "stack-promote"
+    }
+
+    fn run(&mut self, _sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        Ok(self.promote(body))
+    }
+
+    fn run_module(&mut self, module: &mut Module) -> Result<bool> {
+        self.summaries = EscapeSummaries::of_module(module)
+            .with_context(|| format!("finding escaping arguments in {}", module.path))?;
+        let changed = self.promote_in(module, None);
+        self.summaries = EscapeSummaries::new();
+        self.base = Path(Vec::new());
+        changed
+    }
+}
@@ -0,0 +1,60 @@
+//! Removal of code that can never run
+use anyhow::Result;
+use crate::{FunctionSignature, FnBody};
+use crate::code::Instruction;
+use super::{Cfg, Pass};
+
+/// Removes the instructions after the first terminator of each block and the blocks that can't be
+/// reached from the entry block, renumbering the blocks that are left. Phi values for blocks that
+/// can't jump to the phi's block are removed too
+pub struct UnreachableBlocks;
+
+impl Pass for UnreachableBlocks {
+    fn name(&self) -> &'static str {
+        "unreachable"
+    }
+
+    fn run(&mut self, _sig: &FunctionSignature, body: &mut FnBody) -> Result<bool> {
+        let mut changed = false;
+        for block in body.blocks.iter_mut() {
+            let live = block.len_live();
+            if live < block.instrs.len() {
+                block.instrs.truncate(live);
+                changed = true;
+            }
+        }
+
+        let cfg = Cfg::new(body)?;
+        let mut new_index = vec![0; body.blocks.len()];
+        let mut count = 0;
+        for (b, index) in new_index.iter_mut().enumerate() {
+            if cfg.is_reachable(b) {
+                *index = count;
+                count += 1;
+            }
+        }
+        for (b, block) in body.blocks.iter_mut().enumerate().filter(|(b, _)| cfg.is_reachable(*b)) {
+            let preds = &cfg.preds[b];
+            for instr in block.instrs.iter_mut() {
+                if let Instruction::Phi(_, vals) = instr {
+                    let len = vals.len();
+                    vals.retain(|(p, _)| preds.contains(p));
+                    changed |= vals.len() != len;
+                    vals.iter_mut().for_each(|(p, _)| *p = new_index[*p]);
+                }
+                instr.for_each_target_mut(&mut |t| *t = new_index[*t]);
+            }
+            // a block that never reaches its end may name a block that was removed
+            block.next_block = if block.falls_through() { new_index[block.next_block] } else { 0 };
+        }
+        if count < body.blocks.len() {
+            let mut b = 0;
+            body.blocks.retain(|_| {
+                b += 1;
+                cfg.is_reachable(b - 1)
+            });
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
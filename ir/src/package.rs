@@ -0,0 +1,173 @@
+//! Packages bundle a set of related [`Module`]s into a single archive file, so that a library and
+//! its submodules can be distributed as one artifact. Each package has a [`Manifest`] describing
+//! its name, version and external dependencies, along with a hash of its contents that is checked
+//! whenever the package is read.
+use std::fmt::Display;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use anyhow::{anyhow, bail, Result};
+use super::{Module, Path, Version, VersionReq};
+
+/// The file extension used for package archives
+pub const PACKAGE_EXTENSION: &str = "opk";
+
+/// A SHA-256 hash identifying the exact contents of a module or package file
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub struct ContentHash(pub [u8; 32]);
+
+/// Describes a package without requiring its modules to be decoded
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    /// The path of the root module of the package. Every module in the package is either this
+    /// module or nested underneath it
+    pub name: Path,
+    pub version: Version,
+    /// Modules outside of this package that are imported by modules inside it, along with the
+    /// version requirements that must be met for each
+    pub dependencies: Vec<(Path, VersionReq)>,
+    /// The hash of the encoded module contents of the package
+    pub hash: ContentHash
+}
+
+/// A set of modules rooted at a single module path, along with a manifest that describes them
+#[derive(Debug)]
+pub struct Package {
+    pub manifest: Manifest,
+    pub modules: Vec<Module>
+}
+
+/// The on-disk representation of a package. The modules are kept as an encoded blob so that the
+/// hash covers exactly the bytes in the file, independent of how they get decoded
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    manifest: Manifest,
+    #[serde(with = "serde_bytes")]
+    contents: Vec<u8>
+}
+
+impl ContentHash {
+    /// Compute the hash of some data
+    pub fn of(data: &[u8]) -> ContentHash {
+        ContentHash(Sha256::digest(data).into())
+    }
+}
+
+impl Display for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for ContentHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() != 64 || !s.is_ascii() {
+            bail!("content hash must be 64 hex digits, got {:?}", s);
+        }
+        let mut hash = [0; 32];
+        for (i, b) in hash.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[i*2..i*2+2], 16)?;
+        }
+        Ok(ContentHash(hash))
+    }
+}
+
+impl Package {
+    /// Bundle a set of modules into a package. The root module is the one whose path is a prefix
+    /// of every other module's path, and it gives the package its name and version. Dependencies
+    /// are computed from the imports that are not satisfied by modules inside the package.
+    pub fn new(mut modules: Vec<Module>) -> Result<Package> {
+        for m in modules.iter_mut() {
+            m.resolve_relative_paths()?;
+        }
+        let root = modules.iter()
+            .min_by_key(|m| m.path.len())
+            .ok_or_else(|| anyhow!("a package must contain at least one module"))?;
+        let mut contained = Vec::new();
+        for m in modules.iter() {
+            collect_module_paths(m, &mut contained);
+        }
+        if let Some(p) = contained.iter().find(|p| !p.starts_with(&root.path)) {
+            bail!("module {} is not contained in the package root {}", p, root.path);
+        }
+        let mut dependencies = Vec::new();
+        for m in modules.iter() {
+            collect_external_imports(m, &contained, &mut dependencies);
+        }
+        let manifest = Manifest {
+            name: root.path.clone(),
+            version: root.version.clone(),
+            dependencies,
+            hash: ContentHash::of(&rmp_serde::encode::to_vec_named(&modules)?)
+        };
+        Ok(Package { manifest, modules })
+    }
+
+    /// Read a package archive, checking that its contents match the hash in its manifest
+    pub fn read(r: impl std::io::Read) -> Result<Package> {
+        let archive: Archive = rmp_serde::from_read(r)?;
+        let hash = ContentHash::of(&archive.contents);
+        if hash != archive.manifest.hash {
+            bail!("package {} v{} is corrupt: contents hash to {}, but the manifest expects {}",
+                archive.manifest.name, archive.manifest.version, hash, archive.manifest.hash);
+        }
+        Ok(Package {
+            modules: rmp_serde::from_read_ref(&archive.contents)?,
+            manifest: archive.manifest
+        })
+    }
+
+    /// Read only the manifest of a package archive, without checking or decoding its modules
+    pub fn read_manifest(r: impl std::io::Read) -> Result<Manifest> {
+        let archive: Archive = rmp_serde::from_read(r)?;
+        Ok(archive.manifest)
+    }
+
+    /// Write this package as an archive. The manifest hash is recomputed from the modules as
+    /// they are written
+    pub fn write(&mut self, w: &mut impl std::io::Write) -> Result<()> {
+        let contents = rmp_serde::encode::to_vec_named(&self.modules)?;
+        self.manifest.hash = ContentHash::of(&contents);
+        rmp_serde::encode::write_named(w, &Archive { manifest: self.manifest.clone(), contents })?;
+        Ok(())
+    }
+
+    /// Find a module contained anywhere in this package, including nested submodules
+    pub fn find_module(&self, path: &Path) -> Option<&Module> {
+        fn find<'m>(m: &'m Module, path: &Path) -> Option<&'m Module> {
+            if m.path == *path {
+                Some(m)
+            } else {
+                m.submodules.iter().find_map(|sm| find(sm, path))
+            }
+        }
+        self.modules.iter().find_map(|m| find(m, path))
+    }
+
+    /// The file name that this package is stored under in a module search directory
+    pub fn file_name(&self) -> String {
+        format!("{}#{}.{}", self.manifest.name, self.manifest.version, PACKAGE_EXTENSION)
+    }
+}
+
+fn collect_module_paths(m: &Module, out: &mut Vec<Path>) {
+    out.push(m.path.clone());
+    for sm in m.submodules.iter() {
+        collect_module_paths(sm, out);
+    }
+}
+
+fn collect_external_imports(m: &Module, contained: &[Path], out: &mut Vec<(Path, VersionReq)>) {
+    for (p, v) in m.imports.iter() {
+        if !contained.contains(p) && !out.iter().any(|(op, ov)| op == p && ov == v) {
+            out.push((p.clone(), v.clone()));
+        }
+    }
+    for sm in m.submodules.iter() {
+        collect_external_imports(sm, contained, out);
+    }
+}
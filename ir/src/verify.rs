@@ -0,0 +1,107 @@
+//! Checks that a module's code is valid beyond what its types can express. For now this finds
+//! integer literals of unsupported widths, and references to stack allocations that outlive the
+//! function that made them, which would point at memory that other functions reuse once the
+//! function returns.
+use std::fmt::Display;
+use anyhow::{bail, Result};
+use crate::{Module, Path};
+use crate::code::{BlockIndex, Instruction, Value};
+use crate::numbers::Integer;
+use crate::opt::escape::{escapes, Escape, EscapeSummaries};
+
+/// A problem found in the code of a function
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    /// The absolute path of the function
+    pub function: Path,
+    pub block: BlockIndex,
+    pub instr: usize,
+    pub message: String
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} block {} instruction {}: {}", self.function, self.block, self.instr, self.message)
+    }
+}
+
+/// Check every function in a module and its submodules, returning the problems found.
+///
+/// An integer literal is an error unless it is 8, 16, 32 or 64 bits wide.
+///
+/// A reference made by [`StackAlloc`](Instruction::StackAlloc),
+/// [`StackAllocArray`](Instruction::StackAllocArray) or [`CopyToStack`](Instruction::CopyToStack)
+/// is an error if it, or a reference into the same allocation, is returned, thrown, stored in
+/// memory that the function didn't allocate, passed to a tail call or passed to a function of the
+/// module that lets it escape. Storing it in memory that the function did allocate is an error if
+/// that memory then escapes in one of those ways, and so is the result of a call that can return
+/// it. Passing it to functions in other modules is allowed, even when the callee might keep it,
+/// since that can't be known.
+pub fn verify(module: &Module) -> Result<Vec<VerifyError>> {
+    let summaries = EscapeSummaries::of_module(module)?;
+    let mut errors = Vec::new();
+    verify_in(module, None, &summaries, &mut errors)?;
+    Ok(errors)
+}
+
+/// Fail if [`verify`] finds problems in a module, with an error that lists each of them
+pub fn check(module: &Module) -> Result<()> {
+    let errors = verify(module)?;
+    if !errors.is_empty() {
+        let list: String = errors.iter().map(|e| format!("\n{}", e)).collect();
+        bail!("{} has {} invalid instruction(s):{}", module.path, errors.len(), list);
+    }
+    Ok(())
+}
+
+fn verify_in(module: &Module, parent: Option<&Path>, summaries: &EscapeSummaries, errors: &mut Vec<VerifyError>) -> Result<()> {
+    let path = match parent {
+        Some(parent) => module.path.resolve(parent)?,
+        None => module.path.clone()
+    };
+    for (name, (_, body)) in module.functions.iter() {
+        let function = path.child(name.clone());
+        for (b, block) in body.blocks.iter().enumerate() {
+            for (i, instr) in block.instrs.iter().enumerate() {
+                let mut widths = Vec::new();
+                instr.clone().for_each_value_mut(&mut |v| match v {
+                    Value::LiteralInt(n) if !Integer::valid_width(n.width) => widths.push(n.width),
+                    _ => {}
+                });
+                for width in widths {
+                    errors.push(VerifyError {
+                        function: function.clone(),
+                        block: b,
+                        instr: i,
+                        message: format!("integer literal has unsupported width {}", width)
+                    });
+                }
+                let dest = match instr {
+                    Instruction::StackAlloc(d, _) | Instruction::StackAllocArray(d, _, _) | Instruction::CopyToStack(d, _) => d,
+                    _ => continue
+                };
+                for site in escapes(body, &[dest.0], &path, summaries) {
+                    let how = match &site.escape {
+                        Escape::Returned => "returned".to_string(),
+                        Escape::Thrown => "thrown".to_string(),
+                        Escape::Stored { into_local: false } => "stored in memory that outlives the function".to_string(),
+                        Escape::Passed { callee, tail: true, .. } => format!("passed to a tail call of {}", callee),
+                        Escape::Passed { callee, escapes: Some(true), .. } => format!("passed to {}, which lets it escape", callee),
+                        _ => continue
+                    };
+                    errors.push(VerifyError {
+                        function: function.clone(),
+                        block: site.block,
+                        instr: site.instr,
+                        message: format!("reference to the stack allocation in register {} (made at block {} instruction {}) is {}",
+                            dest.0, b, i, how)
+                    });
+                }
+            }
+        }
+    }
+    for sub in module.submodules.iter() {
+        verify_in(sub, Some(&path), summaries, errors)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! `oxlr-opt`: run optimization passes from [`ir::opt`] over a module or package, writing the
+//! optimized module or package to a directory like `asm` does.
+//!
+//! Usage: `oxlr-opt [-L <dir>] [--passes=<pass>,...] [--print] <module .s/.om or package .opk> <output directory>`
+//!
+//! Functions are inlined from the modules being optimized, which are the module and its
+//! submodules or every module in the package, and from the modules they import that are marked
+//! inlinable, which are found in the module search path like the VM does. Modules are checked
+//! with [`ir::verify`] before and after they are optimized.
+use anyhow::{anyhow, bail, Context, Result};
+use ir::opt::{Inline, PassManager, DEFAULT_PASSES};
+use vm::SearchPath;
+use vm::resolve::{ResolveOptions, Resolver};
+
+/// every module in a tree of submodules, whose paths must already be resolved
+fn flatten<'m>(module: &'m ir::Module, out: &mut Vec<&'m ir::Module>) {
+    out.push(module);
+    for sub in module.submodules.iter() {
+        flatten(sub, out);
+    }
+}
+
+/// the inlining pass for the modules being optimized, which can also inline from the inlinable
+/// modules that they import
+fn inline_pass(modules: &[ir::Module], search_path: SearchPath) -> Result<Inline> {
+    let mut inline = Inline::new();
+    let mut unit = Vec::new();
+    for m in modules {
+        inline.add_module(m)?;
+        flatten(m, &mut unit);
+    }
+    let mut resolver = Resolver::new(search_path, ResolveOptions::default());
+    let mut deps: Vec<ir::Module> = Vec::new();
+    for m in unit.iter() {
+        for (path, req) in m.imports.iter() {
+            if unit.iter().any(|m| m.path == *path) || deps.iter().any(|m| m.path == *path) {
+                continue;
+            }
+            let loaded = unit.iter().copied().chain(deps.iter()).map(|m| (&m.path, m));
+            match resolver.resolve(loaded, path, req) {
+                Ok(resolution) => deps.extend(resolution.modules.into_iter().map(|r| r.module)),
+                // natives like the standard library have no code to inline
+                Err(e) => log::debug!("not inlining from {}: {}", path, e)
+            }
+        }
+    }
+    for dep in deps.iter() {
+        if inline.add_dependency(dep)? {
+            log::debug!("inlining from {} v{}", dep.path, dep.version);
+        }
+    }
+    Ok(inline)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let mut passes = None;
+    let mut print = false;
+    let mut paths = Vec::new();
+    let mut search_path = SearchPath::default();
+    let mut cmd_args = std::env::args().skip(1);
+    while let Some(a) = cmd_args.next() {
+        if a == "-L" {
+            search_path.push(cmd_args.next().expect("directory after -L"));
+        } else if let Some(dir) = a.strip_prefix("-L") {
+            search_path.push(dir);
+        } else if let Some(names) = a.strip_prefix("--passes=") {
+            passes = Some(names.split(',').filter(|n| !n.is_empty()).map(str::to_string).collect::<Vec<_>>());
+        } else if a == "--print" {
+            print = true;
+        } else if a.starts_with("--") {
+            bail!("unknown flag {}", a);
+        } else {
+            paths.push(a);
+        }
+    }
+    search_path.extend(SearchPath::from_env());
+    let [input_path, output_path] = paths.as_slice() else {
+        bail!("usage: oxlr-opt [-L <dir>] [--passes=<pass>,...] [--print] <module .s/.om or package .opk> <output directory>");
+    };
+    let passes = passes.unwrap_or_else(|| DEFAULT_PASSES.iter().map(|p| p.to_string()).collect());
+    let mut pm = PassManager::from_names(&passes)?;
+
+    let package = input_path.ends_with(&format!(".{}", ir::package::PACKAGE_EXTENSION));
+    let mut modules = if package {
+        ir::Package::read(std::fs::File::open(input_path)?)
+            .with_context(|| format!("reading package {}", input_path))?.modules
+    } else {
+        vec![ir::Module::read_file(input_path)?]
+    };
+    for m in modules.iter_mut() {
+        m.resolve_relative_paths()?;
+        ir::verify::check(m).with_context(|| format!("verifying {}", input_path))?;
+    }
+    if passes.iter().any(|p| p == "inline") {
+        pm.replace(inline_pass(&modules, search_path)?);
+    }
+    for m in modules.iter_mut() {
+        let changed = pm.run_module(m).with_context(|| format!("optimizing {}", m.path))?;
+        ir::verify::check(m).with_context(|| format!("verifying optimized {}", m.path))?;
+        log::debug!("{} {}", m.path, if changed { "was optimized" } else { "did not change" });
+        if print {
+            println!("{}", ron::ser::to_string_pretty(&*m, Default::default())
+                .map_err(|e| anyhow!("printing module: {}", e))?);
+        }
+    }
+
+    if package {
+        let mut package = ir::Package::new(modules)?;
+        let output_path = format!("{}/{}", output_path, package.file_name());
+        println!("{} -> {}", input_path, output_path);
+        package.write(&mut std::fs::File::create(output_path)?)?;
+    } else {
+        let module = &modules[0];
+        let output_path = format!("{}/{}#{}.om", output_path, module.path, module.version);
+        println!("{} -> {}", input_path, output_path);
+        let mut output = std::fs::File::create(output_path)?;
+        rmp_serde::encode::write_named(&mut output, module)?;
+    }
+    Ok(())
+}
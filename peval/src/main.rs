@@ -0,0 +1,117 @@
+//! `oxlr-peval`: specialize a function for some of its arguments with [`ir::opt::Specializer`],
+//! writing a new version of its module that has the specialized function added.
+//!
+//! Usage: `oxlr-peval [-L <dir>] [--known=<module>,...] [--as=<name>] [--version=<version>] [--print] <module .s/.om> <function> [<argument>=<value>...] <output directory>`
+//!
+//! Arguments that are not given are only known at run time, and become the arguments of the
+//! specialized function. Calls are specialized into the module itself and its submodules, and into
+//! the imported modules listed with `--known`, which are found in the module search path like the
+//! VM does. The new version is the next minor version unless `--version` is given.
+use anyhow::{anyhow, bail, Context, Result};
+use ir::{Path, Symbol, Type};
+use ir::code::Value;
+use ir::opt::Specializer;
+use vm::SearchPath;
+use vm::resolve::{ResolveOptions, Resolver};
+
+const USAGE: &str = "usage: oxlr-peval [-L <dir>] [--known=<module>,...] [--as=<name>] [--version=<version>] [--print] <module .s/.om> <function> [<argument>=<value>...] <output directory>";
+
+/// the literal for an argument value given on the command line
+fn parse_value(ty: &Type, s: &str) -> Result<Value> {
+    Ok(match ty {
+        Type::Unit if s == "()" => Value::LiteralUnit,
+        Type::Bool => Value::LiteralBool(s.parse()?),
+        Type::Int { signed: false, width } => {
+            let data: u64 = s.parse()?;
+            if *width < 64 && data >> width != 0 {
+                bail!("{} does not fit in {} bits", data, width);
+            }
+            Value::LiteralInt(ir::Integer::unsigned(*width, data))
+        },
+        Type::Int { signed: true, width } => {
+            let data: i64 = s.parse()?;
+            if *width < 64 && (data >> (width - 1) != 0 && data >> (width - 1) != -1) {
+                bail!("{} does not fit in {} bits", data, width);
+            }
+            Value::LiteralInt(ir::Integer::signed(*width, data as u64))
+        },
+        t => bail!("{} is not a value of type {:?}", s, t)
+    })
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let mut known_modules = Vec::new();
+    let mut name = None;
+    let mut version = None;
+    let mut print = false;
+    let mut paths = Vec::new();
+    let mut search_path = SearchPath::default();
+    let mut cmd_args = std::env::args().skip(1);
+    while let Some(a) = cmd_args.next() {
+        if a == "-L" {
+            search_path.push(cmd_args.next().expect("directory after -L"));
+        } else if let Some(dir) = a.strip_prefix("-L") {
+            search_path.push(dir);
+        } else if let Some(paths) = a.strip_prefix("--known=") {
+            known_modules.extend(paths.split(',').filter(|p| !p.is_empty()).map(Path::from));
+        } else if let Some(n) = a.strip_prefix("--as=") {
+            name = Some(Symbol(n.to_string()));
+        } else if let Some(v) = a.strip_prefix("--version=") {
+            version = Some(ir::Version::parse(v).with_context(|| format!("parsing version {}", v))?);
+        } else if a == "--print" {
+            print = true;
+        } else if a.starts_with("--") {
+            bail!("unknown flag {}", a);
+        } else {
+            paths.push(a);
+        }
+    }
+    search_path.extend(SearchPath::from_env());
+    let [input_path, function, assignments @ .., output_path] = paths.as_slice() else {
+        bail!(USAGE);
+    };
+
+    let mut module = ir::Module::read_file(input_path)?;
+    module.resolve_relative_paths()?;
+    ir::verify::check(&module).with_context(|| format!("verifying {}", input_path))?;
+    let mut spec = Specializer::new();
+    spec.add_module(&module)?;
+
+    let mut resolver = Resolver::new(search_path, ResolveOptions::default());
+    for path in known_modules.iter() {
+        let (_, req) = module.imports.iter().find(|(p, _)| p == path)
+            .ok_or_else(|| anyhow!("{} does not import {}", module.path, path))?;
+        let resolution = resolver.resolve(std::iter::once((&module.path, &module)), path, req)
+            .with_context(|| format!("finding {}", path))?;
+        let known = resolution.modules.iter().map(|r| &r.module).find(|m| m.path == *path)
+            .ok_or_else(|| anyhow!("{} was not found", path))?;
+        log::debug!("specializing calls into {} v{}", known.path, known.version);
+        spec.add_module(known)?;
+    }
+
+    let func = function.split("::").fold(module.path.clone(), |p, s| p.child(Symbol(s.to_string())));
+    let sig = spec.signature(&func).ok_or_else(|| anyhow!("{} has no function {}", module.path, function))?;
+    let mut args = vec![None; sig.args.len()];
+    for assignment in assignments {
+        let (arg, value) = assignment.split_once('=').ok_or_else(|| anyhow!(USAGE))?;
+        let i = sig.args.iter().position(|(_, n)| n.0 == arg)
+            .ok_or_else(|| anyhow!("{} has no argument {}", func, arg))?;
+        args[i] = Some(parse_value(&sig.args[i].0, value).with_context(|| format!("argument {}", arg))?);
+    }
+
+    let name = spec.specialize(&mut module, &func, &args, name).with_context(|| format!("specializing {}", func))?;
+    module.version = version.unwrap_or_else(|| ir::Version::new(module.version.major, module.version.minor + 1, 0));
+    ir::verify::check(&module).with_context(|| format!("verifying specialized {}", module.path))?;
+    log::debug!("{} was specialized as {}", func, module.path.child(name));
+    if print {
+        println!("{}", ron::ser::to_string_pretty(&module, Default::default())
+            .map_err(|e| anyhow!("printing module: {}", e))?);
+    }
+
+    let output_path = format!("{}/{}#{}.om", output_path, module.path, module.version);
+    println!("{} -> {}", input_path, output_path);
+    let mut output = std::fs::File::create(output_path)?;
+    rmp_serde::encode::write_named(&mut output, &module)?;
+    Ok(())
+}
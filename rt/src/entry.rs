@@ -0,0 +1,37 @@
+//! The C `main` function of executables, which starts the compiled program with a new
+//! [`Runtime`]
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::io::Write;
+use std::time::Instant;
+use crate::Runtime;
+
+impl Runtime {
+    fn new(args: Vec<String>) -> Runtime {
+        Runtime {
+            args,
+            start: Instant::now(),
+            max_size: 4 * 1024 * 1024 * 1024, // 4GiB, like the VM
+            current_size: 0,
+            error: None
+        }
+    }
+}
+
+extern "C" {
+    /// the entry point of the compiled program, which calls its `start` function and returns the
+    /// exit code. The runtime is opaque to compiled code
+    fn oxlr_main(rt: *mut c_void) -> i32;
+}
+
+#[no_mangle]
+extern "C" fn main(argc: c_int, argv: *const *const c_char) -> c_int {
+    let args = (1..argc.max(0) as usize)
+        // SAFETY: the C runtime passes `argc` strings in `argv`
+        .map(|i| unsafe { CStr::from_ptr(*argv.add(i)) }.to_string_lossy().into_owned())
+        .collect();
+    let mut rt = Runtime::new(args);
+    // SAFETY: oxlr_main is given the runtime that compiled code expects
+    let code = unsafe { oxlr_main(&mut rt as *mut Runtime as *mut c_void) };
+    let _ = std::io::stdout().flush();
+    code
+}
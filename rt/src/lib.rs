@@ -0,0 +1,145 @@
+//! The runtime that executables made by `oxlr-aot` are linked against. It provides the C `main`
+//! function, which sets up a [`Runtime`] and passes it to the compiled `oxlr_main`, along with
+//! the heap, traps and the part of the standard library that compiled code can call.
+//!
+//! Compiled code passes the runtime to every function it calls as an opaque pointer. Values are
+//! represented like in the VM's compiled code: integers and bools as their data in a `u64`, unit
+//! as zero and references as the address of the value behind them. An array starts with its
+//! length as a `u64`, followed by its elements.
+//!
+//! There is no garbage collector: compiled code doesn't record where it keeps references, so the
+//! runtime can't tell which allocations are still in use. Nothing allocated on the heap is freed
+//! before the program exits, and once the heap reaches its limit every allocation fails, which
+//! compiled code reports as an out of memory trap.
+use std::alloc::Layout;
+use std::ffi::{c_char, CStr};
+use std::io::Write;
+use std::time::Instant;
+
+mod natives;
+// the test harness has its own `main`
+#[cfg(not(test))]
+mod entry;
+
+/// The state of a running program
+pub struct Runtime {
+    /// the program arguments, without the name of the executable
+    args: Vec<String>,
+    /// when the program started, for `std::time::elapsed_nanos`
+    start: Instant,
+    max_size: usize,
+    current_size: usize,
+    /// the error from the last native function that failed, which is shown with its trap
+    error: Option<String>
+}
+
+impl Runtime {
+    /// allocate `size` zeroed bytes on the heap, or return null if the heap is full. The memory
+    /// is never freed
+    fn alloc(&mut self, size: usize) -> *mut u8 {
+        let Ok(layout) = Layout::from_size_align(size.max(1), 8) else { return std::ptr::null_mut() };
+        if self.current_size + layout.size() > self.max_size {
+            return std::ptr::null_mut();
+        }
+        self.current_size += layout.size();
+        // SAFETY: the layout has a non-zero size
+        unsafe { std::alloc::alloc_zeroed(layout) }
+    }
+
+    /// allocate an array of `count` elements of `el_size` bytes, or return null if the heap is full
+    fn alloc_array(&mut self, el_size: usize, count: usize) -> *mut u8 {
+        let Some(size) = el_size.checked_mul(count).and_then(|s| s.checked_add(8)) else { return std::ptr::null_mut() };
+        let data = self.alloc(size);
+        if !data.is_null() {
+            // SAFETY: the allocation starts with room for the length
+            unsafe { *(data as *mut u64) = count as u64 };
+        }
+        data
+    }
+
+    /// allocate a string holding `s`, or return null if the heap is full
+    fn alloc_string(&mut self, s: &[u8]) -> *mut u8 {
+        let data = self.alloc_array(1, s.len());
+        if !data.is_null() {
+            // SAFETY: the array has room for the bytes after its length
+            unsafe { std::ptr::copy_nonoverlapping(s.as_ptr(), data.add(8), s.len()) };
+        }
+        data
+    }
+}
+
+/// the bytes of the string at `raw`
+///
+/// # Safety
+/// `raw` must be the address of an array of bytes
+unsafe fn string<'a>(raw: u64) -> &'a [u8] {
+    let data = raw as *const u8;
+    std::slice::from_raw_parts(data.add(8), *(data as *const u64) as usize)
+}
+
+/// stop the program with an error, like the VM does when a trap is not caught
+fn fail(message: &str) -> ! {
+    let _ = std::io::stdout().flush();
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+
+/// allocate `size` bytes on the heap, returning their address or 0 if the heap is full
+#[no_mangle]
+extern "C" fn oxlr_rt_alloc(rt: *mut Runtime, size: u64) -> u64 {
+    // SAFETY: compiled code passes on the runtime it was given
+    let rt = unsafe { &mut *rt };
+    rt.alloc(size as usize) as u64
+}
+
+/// allocate an array of `count` elements of `el_size` bytes, returning its address or 0 if the
+/// heap is full
+#[no_mangle]
+extern "C" fn oxlr_rt_alloc_array(rt: *mut Runtime, el_size: u64, count: u64) -> u64 {
+    // SAFETY: compiled code passes on the runtime it was given
+    let rt = unsafe { &mut *rt };
+    rt.alloc_array(el_size as usize, count as usize) as u64
+}
+
+/// allocate a string holding a copy of `len` bytes at `data`, returning its address or 0 if the
+/// heap is full
+#[no_mangle]
+extern "C" fn oxlr_rt_string(rt: *mut Runtime, data: *const u8, len: u64) -> u64 {
+    // SAFETY: compiled code passes on the runtime it was given, and the bytes of a literal
+    let (rt, s) = unsafe { (&mut *rt, std::slice::from_raw_parts(data, len as usize)) };
+    rt.alloc_string(s) as u64
+}
+
+/// the program arguments as an array of strings, which are `el_size` bytes apart
+#[no_mangle]
+extern "C" fn oxlr_rt_args(rt: *mut Runtime, el_size: u64) -> u64 {
+    // SAFETY: compiled code passes on the runtime it was given
+    let rt = unsafe { &mut *rt };
+    let args = rt.args.clone();
+    let arr = rt.alloc_array(el_size as usize, args.len());
+    if arr.is_null() {
+        fail("memory exhausted while allocating program arguments");
+    }
+    for (i, a) in args.iter().enumerate() {
+        let s = rt.alloc_string(a.as_bytes());
+        if s.is_null() {
+            fail("memory exhausted while allocating program arguments");
+        }
+        // SAFETY: the array has room for `args.len()` elements
+        unsafe { *(arr.add(8 + i * el_size as usize) as *mut u64) = s as u64 };
+    }
+    arr as u64
+}
+
+/// stop the program because of a trap at an instruction in `function`. If the trap is from a
+/// native function failing, its error is shown after `message`
+#[no_mangle]
+extern "C" fn oxlr_rt_trap(rt: *mut Runtime, message: *const c_char, function: *const c_char, block: u64, instr: u64) -> ! {
+    // SAFETY: compiled code passes on the runtime it was given, and NUL terminated strings
+    let (rt, message, function) = unsafe { (&mut *rt, CStr::from_ptr(message), CStr::from_ptr(function)) };
+    let mut message = message.to_string_lossy().into_owned();
+    if let Some(e) = rt.error.take() {
+        message = format!("{}: {}", message, e);
+    }
+    fail(&format!("{}\n    at {} (block {}, instruction {})", message, function.to_string_lossy(), block, instr))
+}
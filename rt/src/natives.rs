@@ -0,0 +1,125 @@
+//! The part of the standard library that compiled code can call, matching the native modules in
+//! the VM. Each function is named after its path, so `std::io::println` is `oxlr_std_io_println`,
+//! and is called like a compiled function: with the runtime and a pointer to its arguments, which
+//! it replaces with the value it returns. A function that fails returns 1 after keeping its error
+//! in the runtime, and compiled code then traps.
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::{string, Runtime};
+
+/// call `f` with the first `arity` arguments at `args`, putting the value it returns in `args[0]`
+///
+/// # Safety
+/// `rt` must be the runtime and `args` must have room for `arity` values and at least one
+unsafe fn native(rt: *mut Runtime, args: *mut u64, arity: usize,
+    f: impl FnOnce(&mut Runtime, &[u64]) -> Result<u64, String>) -> u32
+{
+    let rt = &mut *rt;
+    let a = std::slice::from_raw_parts(args, arity).to_vec();
+    match f(rt, &a) {
+        Ok(v) => {
+            *args = v;
+            0
+        },
+        Err(e) => {
+            rt.error = Some(e);
+            1
+        }
+    }
+}
+
+macro_rules! natives {
+    ($($symbol:ident($arity:expr) => $f:expr;)*) => {
+        $(
+            #[no_mangle]
+            extern "C" fn $symbol(rt: *mut Runtime, args: *mut u64) -> u32 {
+                // SAFETY: compiled code passes on the runtime and room for the arguments
+                unsafe { native(rt, args, $arity, $f) }
+            }
+        )*
+    };
+}
+
+/// the string at `raw` as text, which the VM requires strings to be
+fn text<'a>(raw: u64) -> Result<&'a str, String> {
+    // SAFETY: compiled code only passes strings where the signature says so
+    std::str::from_utf8(unsafe { string(raw) }).map_err(|e| format!("string is not valid UTF-8: {}", e))
+}
+
+fn new_string(rt: &mut Runtime, s: &str) -> Result<u64, String> {
+    let data = rt.alloc_string(s.as_bytes());
+    if data.is_null() {
+        return Err(format!("memory exhausted while allocating a string of {} bytes", s.len()));
+    }
+    Ok(data as u64)
+}
+
+fn write_to(mut out: impl Write, s: u64, newline: bool) -> Result<u64, String> {
+    let s = text(s)?;
+    let written = if newline {
+        writeln!(out, "{}", s)
+    } else {
+        write!(out, "{}", s).and_then(|()| out.flush())
+    };
+    written.map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+natives! {
+    oxlr_std_io_print(1) => |_, a| write_to(std::io::stdout(), a[0], false);
+    oxlr_std_io_println(1) => |_, a| write_to(std::io::stdout(), a[0], true);
+    oxlr_std_io_eprint(1) => |_, a| write_to(std::io::stderr(), a[0], false);
+    oxlr_std_io_eprintln(1) => |_, a| write_to(std::io::stderr(), a[0], true);
+    // reads a line from stdin without the line ending, or an empty string at the end of input
+    oxlr_std_io_read_line(0) => |rt, _| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map_err(|e| format!("reading from stdin: {}", e))?;
+        let line = line.strip_suffix('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)).unwrap_or(&line);
+        new_string(rt, line)
+    };
+
+    oxlr_std_string_len(1) => |_, a| Ok(text(a[0])?.len() as u64);
+    oxlr_std_string_concat(2) => |rt, a| new_string(rt, &(text(a[0])?.to_string() + text(a[1])?));
+    oxlr_std_string_eq(2) => |_, a| Ok((text(a[0])? == text(a[1])?) as u64);
+    oxlr_std_string_substring(3) => |rt, a| {
+        let s = text(a[0])?;
+        let (start, end) = (a[1] as usize, a[2] as usize);
+        let sub = s.get(start..end)
+            .ok_or_else(|| format!("invalid substring range {}..{} of string with length {}", start, end, s.len()))?;
+        new_string(rt, sub)
+    };
+    // the index of the first occurrence of `needle`, or -1 if it does not occur
+    oxlr_std_string_find(2) => |_, a| Ok(text(a[0])?.find(text(a[1])?).map_or(-1, |i| i as i64) as u64);
+    oxlr_std_string_parse_int(1) => |_, a| {
+        let s = text(a[0])?;
+        s.trim().parse::<i64>().map(|i| i as u64).map_err(|e| format!("parsing integer from {:?}: {}", s, e))
+    };
+    oxlr_std_string_from_u64(1) => |rt, a| new_string(rt, &a[0].to_string());
+    oxlr_std_string_from_i64(1) => |rt, a| new_string(rt, &(a[0] as i64).to_string());
+    oxlr_std_string_from_bool(1) => |rt, a| new_string(rt, &(a[0] != 0).to_string());
+
+    oxlr_std_env_arg_count(0) => |rt, _| Ok(rt.args.len() as u64);
+    oxlr_std_env_arg(1) => |rt, a| {
+        let arg = rt.args.get(a[0] as usize).cloned()
+            .ok_or_else(|| format!("argument index {} is out of range, there are {} arguments", a[0], rt.args.len()))?;
+        new_string(rt, &arg)
+    };
+    // the value of an environment variable, or an empty string if it is not set
+    oxlr_std_env_var(1) => |rt, a| new_string(rt, &std::env::var(text(a[0])?).unwrap_or_default());
+    oxlr_std_env_has_var(1) => |_, a| Ok(std::env::var_os(text(a[0])?).is_some() as u64);
+
+    oxlr_std_math_abs(1) => |_, a| Ok((a[0] as i64).wrapping_abs() as u64);
+    oxlr_std_math_min(2) => |_, a| Ok((a[0] as i64).min(a[1] as i64) as u64);
+    oxlr_std_math_max(2) => |_, a| Ok((a[0] as i64).max(a[1] as i64) as u64);
+
+    // milliseconds since the Unix epoch
+    oxlr_std_time_now_millis(0) => |_, _| {
+        Ok(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+    };
+    // nanoseconds since the program started, from a clock that never goes backwards
+    oxlr_std_time_elapsed_nanos(0) => |rt, _| Ok(rt.start.elapsed().as_nanos() as u64);
+    oxlr_std_time_sleep_millis(1) => |_, a| {
+        std::thread::sleep(Duration::from_millis(a[0]));
+        Ok(0)
+    };
+}
@@ -0,0 +1,132 @@
+//! Benchmarks of the interpreter running small programs built in memory: recursive calls, a loop
+//! that does arithmetic through phi nodes, and a loop that reads and writes fields of a struct.
+use std::collections::HashMap;
+use criterion::{criterion_group, criterion_main, Criterion};
+use ir::code::{BasicBlock, BinOp, FnBody, Instruction, Register, Value as IrValue};
+use ir::{FunctionSignature, Symbol, Type};
+use vm::{World, Machine, SearchPath};
+
+fn u64_ty() -> Type {
+    Type::Int { signed: false, width: 64 }
+}
+
+fn int(x: u64) -> IrValue {
+    IrValue::LiteralInt(ir::Integer::unsigned(64, x))
+}
+
+fn reg(r: u32) -> IrValue {
+    IrValue::Reg(Register(r))
+}
+
+fn block(instrs: Vec<Instruction>) -> BasicBlock {
+    BasicBlock { instrs, next_block: 0 }
+}
+
+fn unary_fn(max_registers: u32, blocks: Vec<BasicBlock>) -> (FunctionSignature, FnBody) {
+    (
+        FunctionSignature { args: vec![(u64_ty(), Symbol("n".into()))], return_type: u64_ty() },
+        FnBody { max_registers, blocks }
+    )
+}
+
+fn module() -> ir::Module {
+    let mut functions = HashMap::new();
+    // fib(n) = n if n < 2, else fib(n-1) + fib(n-2)
+    functions.insert(Symbol("fib".into()), unary_fn(6, vec![
+        block(vec![
+            Instruction::BinaryOp(BinOp::Eq, Register(1), reg(0), int(0)),
+            Instruction::Br { cond: reg(1), if_true: 2, if_false: 1 }
+        ]),
+        block(vec![
+            Instruction::BinaryOp(BinOp::Eq, Register(1), reg(0), int(1)),
+            Instruction::Br { cond: reg(1), if_true: 2, if_false: 3 }
+        ]),
+        block(vec![Instruction::Return(reg(0))]),
+        block(vec![
+            Instruction::BinaryOp(BinOp::Sub, Register(2), reg(0), int(1)),
+            Instruction::Call(Register(3), ir::Path::from("bench::fib"), vec![reg(2)]),
+            Instruction::BinaryOp(BinOp::Sub, Register(2), reg(0), int(2)),
+            Instruction::Call(Register(4), ir::Path::from("bench::fib"), vec![reg(2)]),
+            Instruction::BinaryOp(BinOp::Add, Register(5), reg(3), reg(4)),
+            Instruction::Return(reg(5))
+        ])
+    ]));
+    // sum(n) = n + (n-1) + ... + 1, with the loop counter and total in phi nodes
+    functions.insert(Symbol("sum".into()), unary_fn(6, vec![
+        BasicBlock { instrs: vec![], next_block: 1 },
+        block(vec![
+            Instruction::Phi(Register(1), vec![(0, reg(0)), (2, reg(3))]),
+            Instruction::Phi(Register(2), vec![(0, int(0)), (2, reg(4))]),
+            Instruction::BinaryOp(BinOp::Eq, Register(5), reg(1), int(0)),
+            Instruction::Br { cond: reg(5), if_true: 3, if_false: 2 }
+        ]),
+        BasicBlock {
+            instrs: vec![
+                Instruction::BinaryOp(BinOp::Sub, Register(3), reg(1), int(1)),
+                Instruction::BinaryOp(BinOp::Add, Register(4), reg(2), reg(1))
+            ],
+            next_block: 1
+        },
+        block(vec![Instruction::Return(reg(2))])
+    ]));
+    // fields(n) counts to n in the field of a struct, moving it between two fields each time
+    let point = Type::User(ir::Path::from("bench::Point"), None);
+    functions.insert(Symbol("fields".into()), unary_fn(6, vec![
+        BasicBlock {
+            instrs: vec![
+                Instruction::Alloc(Register(1), point),
+                Instruction::StoreField(int(0), Register(1), Symbol("x".into()))
+            ],
+            next_block: 1
+        },
+        block(vec![
+            Instruction::LoadField(Register(2), Register(1), Symbol("x".into())),
+            Instruction::BinaryOp(BinOp::Add, Register(3), reg(2), int(1)),
+            Instruction::StoreField(reg(3), Register(1), Symbol("y".into())),
+            Instruction::LoadField(Register(4), Register(1), Symbol("y".into())),
+            Instruction::StoreField(reg(4), Register(1), Symbol("x".into())),
+            Instruction::BinaryOp(BinOp::Eq, Register(5), reg(4), reg(0)),
+            Instruction::Br { cond: reg(5), if_true: 2, if_false: 1 }
+        ]),
+        block(vec![Instruction::Return(reg(4))])
+    ]));
+    let mut types = HashMap::new();
+    types.insert(Symbol("Point".into()), ir::TypeDefinition::Product {
+        parameters: Vec::new(),
+        fields: vec![(Symbol("x".into()), u64_ty()), (Symbol("y".into()), u64_ty())]
+    });
+    ir::Module {
+        path: ir::Path::from("bench"),
+        version: ir::Version::new(0, 1, 0),
+        types,
+        interfaces: HashMap::new(),
+        implementations: HashMap::new(),
+        functions,
+        imports: Vec::new(),
+        submodules: Vec::new(),
+        inlinable: false
+    }
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let world = World::new(SearchPath::default());
+    world.add_module(module()).expect("add benchmark module");
+    // each function is run by the interpreter alone, then with hot functions compiled
+    for (tier, threshold) in [("interpreted", None), ("compiled", Some(vm::machine::DEFAULT_JIT_THRESHOLD))] {
+        let mut m = Machine::new(&world);
+        m.jit_threshold = threshold;
+        for (name, n, expected) in [("fib", 20u64, 6765u64), ("sum", 100_000, 5_000_050_000), ("fields", 100_000, 100_000)] {
+            let path = ir::Path::from(format!("bench::{}", name));
+            let rv: u64 = m.call(&path, vec![n.into()]).unwrap().try_into().unwrap();
+            assert_eq!(rv, expected, "{}({})", name, n);
+            c.bench_function(&format!("{} {}({})", tier, name, n), |b| b.iter(|| m.call(&path, vec![n.into()]).unwrap()));
+        }
+    }
+}
+
+criterion_group! {
+    name = interpreter;
+    config = Criterion::default().sample_size(20);
+    targets = benchmarks
+}
+criterion_main!(interpreter);
@@ -0,0 +1,79 @@
+//! Embed the VM in a host program: register native functions, build a module in memory that calls
+//! them, add both to a world and call functions with arguments supplied from Rust, either all at
+//! once or one instruction at a time.
+use std::collections::HashMap;
+use ir::code::{BasicBlock, BinOp, FnBody, Instruction, Register, Value as IrValue};
+use vm::{World, Machine, SearchPath, NativeModule, Value, Status};
+use vm::native::{string_type, read_string};
+
+fn main() -> anyhow::Result<()> {
+    let u64_ty = ir::Type::Int { signed: false, width: 64 };
+    let host = NativeModule::new(ir::Path::from("host"), ir::Version::new(1, 0, 0))
+        .function("twice", ir::FunctionSignature {
+            args: vec![(u64_ty.clone(), ir::Symbol("x".into()))],
+            return_type: u64_ty.clone()
+        }, |_, args| {
+            let x: u64 = args[0].clone().try_into()?;
+            Ok(Value::from(x * 2))
+        })
+        .function("greet", ir::FunctionSignature {
+            args: vec![(string_type(), ir::Symbol("name".into()))],
+            return_type: string_type()
+        }, |cx, args| {
+            let name = cx.string(&args[0])?;
+            cx.alloc_string(&format!("hello, {}!", name))
+        });
+
+    let mut functions = HashMap::new();
+    functions.insert(ir::Symbol("add".into()), (
+        ir::FunctionSignature {
+            args: vec![(u64_ty.clone(), ir::Symbol("a".into())), (u64_ty.clone(), ir::Symbol("b".into()))],
+            return_type: u64_ty
+        },
+        FnBody {
+            max_registers: 3,
+            blocks: vec![BasicBlock {
+                instrs: vec![
+                    Instruction::BinaryOp(BinOp::Add, Register(2), IrValue::Reg(Register(0)), IrValue::Reg(Register(1))),
+                    Instruction::Call(Register(2), ir::Path::from("host::twice"), vec![IrValue::Reg(Register(2))]),
+                    Instruction::Return(IrValue::Reg(Register(2)))
+                ],
+                next_block: 0
+            }]
+        }
+    ));
+    let module = ir::Module {
+        path: ir::Path::from("embedded"),
+        version: ir::Version::new(0, 1, 0),
+        types: HashMap::new(),
+        interfaces: HashMap::new(),
+        implementations: HashMap::new(),
+        functions,
+        imports: vec![(ir::Path::from("host"), ir::VersionReq::parse("^1.0")?)],
+        submodules: Vec::new(),
+        inlinable: false
+    };
+
+    let world = World::new(SearchPath::default());
+    world.add_native_module(host)?;
+    world.add_module(module)?;
+    let mut machine = Machine::new(&world);
+    let sum: u64 = machine.call(&ir::Path::from("embedded::add"), vec![20u64.into(), 1u64.into()])?
+        .try_into()?;
+    println!("embedded::add(20, 1) = {}", sum);
+    let name = machine.alloc_string("oxlr")?;
+    let greeting = machine.call(&ir::Path::from("host::greet"), vec![name])?;
+    println!("host::greet(\"oxlr\") = {:?}", read_string(&greeting)?);
+
+    machine.begin_call(&ir::Path::from("embedded::add"), vec![1u64.into(), 2u64.into()])?;
+    let sum = loop {
+        for frame in machine.stack_trace() {
+            println!("  at {}", frame);
+        }
+        if let Status::Finished(v) = machine.step()? {
+            break u64::try_from(v)?;
+        }
+    };
+    println!("embedded::add(1, 2) = {}, one step at a time", sum);
+    Ok(())
+}
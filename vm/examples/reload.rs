@@ -0,0 +1,52 @@
+//! Reload a module in a running world as newer versions of it are added to the search path.
+//!
+//! Usage: `reload <module dir> <newer module file> <module file with a changed type>`
+//!
+//! The module directory must contain the first version of the `reloaded` module. The newer file is
+//! copied in and reloaded, after which calls must run its new body, then the file with a changed
+//! type is copied in and reloading it must be rejected.
+use anyhow::{anyhow, bail, Context, Result};
+use vm::{World, Machine, SearchPath};
+
+/// copy a module file into a directory of the search path
+fn add_file(dir: &str, file: &str) -> Result<()> {
+    let name = std::path::Path::new(file).file_name().ok_or_else(|| anyhow!("{} is not a file", file))?;
+    std::fs::copy(file, std::path::Path::new(dir).join(name)).with_context(|| format!("copying {}", file))?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [dir, newer, changed_type] = args.as_slice() else {
+        bail!("usage: reload <module dir> <newer module file> <module file with a changed type>");
+    };
+    let path = ir::Path::from("reloaded");
+    let value = path.child(ir::Symbol("value".into()));
+
+    let mut search_path = SearchPath::default();
+    search_path.push(dir);
+    let world = World::new(search_path);
+    world.load_module(&path, &ir::VersionReq::parse("^1.0")?)?;
+    let mut machine = Machine::new(&world);
+    let old: u64 = machine.call(&value, Vec::new())?.try_into()?;
+
+    add_file(dir, newer)?;
+    let version = world.reload_module(&path)?.ok_or_else(|| anyhow!("{} was not reloaded", newer))?;
+    let new: u64 = machine.call(&value, Vec::new())?.try_into()?;
+    if new == old {
+        bail!("{} still returns {} after reloading as v{}", value, old, version);
+    }
+    println!("{} returned {} before reloading, and {} after reloading as v{}", value, old, new, version);
+
+    add_file(dir, changed_type)?;
+    match world.reload_module(&path) {
+        Ok(v) => bail!("reloading {} with a changed type was not rejected, got {:?}", changed_type, v),
+        Err(e) => println!("reloading a changed type was rejected: {:#}", e)
+    }
+    let after: u64 = machine.call(&value, Vec::new())?.try_into()?;
+    if after != new {
+        bail!("{} returns {} after a rejected reload, instead of {}", value, after, new);
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Unload the modules of a tree that nothing reaches, while keeping the ones that are used.
+//!
+//! Usage: `unload <module dir>`
+//!
+//! The module directory must contain `unload_user`, which imports `unload_tree::used`, and
+//! `unload_tree`. After loading `unload_user`, only the `unused` submodule of `unload_tree` and its
+//! own submodule must be unloaded, and calls through the rest of the tree must still work.
+use anyhow::{bail, Result};
+use vm::{World, Machine, SearchPath};
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [dir] = args.as_slice() else {
+        bail!("usage: unload <module dir>");
+    };
+    let path = ir::Path::from("unload_user");
+    let value = path.child(ir::Symbol("value".into()));
+
+    let mut search_path = SearchPath::default();
+    search_path.push(dir);
+    let world = World::new(search_path);
+    world.load_module(&path, &ir::VersionReq::STAR)?;
+
+    let mut unloaded: Vec<String> = world.unload_unreachable().iter().map(|p| p.to_string()).collect();
+    unloaded.sort();
+    if unloaded != ["unload_tree::unused", "unload_tree::unused::inner"] {
+        bail!("expected only unload_tree::unused and its submodule to be unloaded, but unloaded {:?}", unloaded);
+    }
+    for kept in ["unload_tree", "unload_tree::used", "unload_tree::used::inner"] {
+        if world.get_module(&ir::Path::from(kept))?.is_none() {
+            bail!("{} was unloaded, but is still reachable", kept);
+        }
+    }
+    let mut machine = Machine::new(&world);
+    let v: u64 = machine.call(&value, Vec::new())?.try_into()?;
+    if v != 42 {
+        bail!("{} returned {} after unloading, instead of 42", value, v);
+    }
+    println!("unloaded {} and kept the rest of the tree, where {} still returns {}", unloaded.join(", "), value, v);
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Function bodies lowered from IR into a form that is faster to interpret. Literals are converted
+//! to runtime values ahead of time, calls refer to functions by an index into the
+//! [`Machine`](crate::Machine)'s table of functions instead of by path, and the offset of each
+//! field is cached by the instruction that uses it. The phis at the start of each block are turned
+//! into a list of copies to make when the block is entered from each of its predecessors. Blocks
+//! and instructions keep the indices they have in the IR, so a position in lowered code is also a
+//! position in the IR it came from.
+use std::{cell::RefCell, rc::Rc};
+use ir::code::{BinOp, BlockIndex, Instruction, UnaryOp};
+use crate::value::Value;
+
+/// An index into the table of functions that lowered code calls
+pub type FnIndex = usize;
+
+/// A register, by index
+pub type Reg = u32;
+
+/// A value used by an operation
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Reg(Reg),
+    /// A literal, converted to a runtime value
+    Const(Value),
+    /// A string literal, which is allocated as a new array of bytes each time it is used
+    Str(Rc<[u8]>)
+}
+
+/// A field of a structure used by an operation, with the layout of the field in the type of
+/// structure it was last used with
+#[derive(Debug)]
+pub struct Field {
+    pub name: ir::Symbol,
+    pub cache: RefCell<Option<FieldLayout>>
+}
+
+/// Where a field is in a type of structure
+#[derive(Debug)]
+pub struct FieldLayout {
+    /// The type of the structure
+    pub ty: Box<ir::Type>,
+    /// The offset of the field in bytes from the start of the structure
+    pub offset: usize,
+    /// The type of the field
+    pub field_ty: Box<ir::Type>
+}
+
+/// A single operation, corresponding to the IR [`Instruction`] of the same name
+#[derive(Debug)]
+pub enum Op {
+    /// A phi at the start of a block, which runs as part of the block's [`Edge`]s instead
+    Phi,
+    Br { cond: Operand, if_true: BlockIndex, if_false: BlockIndex },
+    BinaryOp(BinOp, Reg, Operand, Operand),
+    UnaryOp(UnaryOp, Reg, Operand),
+    LoadImm(Reg, Operand),
+    LoadRef(Reg, Reg),
+    StoreRef(Reg, Operand),
+    RefIndex(Reg, Reg, Operand),
+    RefField(Reg, Reg, Field),
+    LoadIndex(Reg, Reg, Operand),
+    StoreIndex(Reg, Operand, Operand),
+    LoadField(Reg, Reg, Field),
+    StoreField(Operand, Reg, Field),
+    Call(Reg, FnIndex, Vec<Operand>),
+    /// Interface functions are still called by path, since the implementation depends on the
+    /// type of the first argument
+    CallImpl(Reg, ir::Path, Vec<Operand>),
+    Return(Operand),
+    TailCall(FnIndex, Vec<Operand>),
+    TailCallImpl(ir::Path, Vec<Operand>),
+    Invoke { dest: Reg, func: FnIndex, args: Vec<Operand>, normal: BlockIndex, unwind: BlockIndex, exception: Reg },
+    Throw(Operand),
+    Alloc(Reg, ir::Type),
+    AllocArray(Reg, ir::Type, Operand),
+    StackAlloc(Reg, ir::Type),
+    StackAllocArray(Reg, ir::Type, Operand),
+    CopyToStack(Reg, Reg),
+    CopyToHeap(Reg, Reg),
+    /// An instruction that the interpreter does not support, which traps when it runs
+    Unsupported(Box<Instruction>),
+    /// An instruction that is used incorrectly, which traps when it runs
+    Invalid(String)
+}
+
+/// The copies made by the phis at the start of a block when it is entered from one predecessor
+#[derive(Debug)]
+pub struct Edge {
+    pub from: BlockIndex,
+    /// The destination of each phi, with the value it gets from this predecessor
+    pub copies: Vec<(Reg, Operand)>
+}
+
+/// A basic block of lowered operations
+#[derive(Debug)]
+pub struct Block {
+    /// The number of phis at the start of the block
+    pub phis: usize,
+    /// The copies to make for the phis when entering the block, for each predecessor that every
+    /// phi has a value for
+    pub edges: Vec<Edge>,
+    pub ops: Vec<Op>,
+    pub next_block: BlockIndex
+}
+
+/// A lowered function body
+#[derive(Debug)]
+pub struct Code {
+    pub max_registers: u32,
+    pub blocks: Vec<Block>
+}
+
+impl Operand {
+    fn lower(v: &ir::code::Value) -> Operand {
+        use ir::code::Value as IrValue;
+        match v {
+            IrValue::LiteralUnit => Operand::Const(Value::Nil),
+            IrValue::LiteralInt(i) => Operand::Const(Value::Int(*i)),
+            IrValue::LiteralFloat(f) => Operand::Const(Value::Float(*f)),
+            IrValue::LiteralBool(b) => Operand::Const(Value::Bool(*b)),
+            IrValue::LiteralString(s) => Operand::Str(s.as_bytes().into()),
+            IrValue::Reg(r) => Operand::Reg(r.0)
+        }
+    }
+}
+
+impl Field {
+    fn new(name: &ir::Symbol) -> Field {
+        Field { name: name.clone(), cache: RefCell::new(None) }
+    }
+}
+
+impl Code {
+    /// lower a function body, using `intern` to give an index to each function that it calls
+    pub fn lower(body: &ir::FnBody, intern: &mut impl FnMut(&ir::Path) -> FnIndex) -> Code {
+        let operands = |vs: &[ir::code::Value]| vs.iter().map(Operand::lower).collect::<Vec<_>>();
+        let blocks = body.blocks.iter().enumerate().map(|(bi, b)| {
+            let phis = b.instrs.iter().take_while(|i| matches!(i, Instruction::Phi(_, _))).count();
+            let ops = b.instrs.iter().enumerate().map(|(ii, i)| match i {
+                Instruction::Phi(_, _) if ii < phis => Op::Phi,
+                Instruction::Phi(_, _) => Op::Invalid(format!("phi after other instructions in block {}", bi)),
+                Instruction::Br { cond, if_true, if_false } =>
+                    Op::Br { cond: Operand::lower(cond), if_true: *if_true, if_false: *if_false },
+                Instruction::BinaryOp(op, dest, lhs, rhs) =>
+                    Op::BinaryOp(op.clone(), dest.0, Operand::lower(lhs), Operand::lower(rhs)),
+                Instruction::UnaryOp(op, dest, v) => Op::UnaryOp(op.clone(), dest.0, Operand::lower(v)),
+                Instruction::LoadImm(dest, v) => Op::LoadImm(dest.0, Operand::lower(v)),
+                Instruction::LoadRef(dest, r) => Op::LoadRef(dest.0, r.0),
+                Instruction::StoreRef(r, v) => Op::StoreRef(r.0, Operand::lower(v)),
+                Instruction::RefIndex(dest, r, ix) => Op::RefIndex(dest.0, r.0, Operand::lower(ix)),
+                Instruction::RefField(dest, r, f) => Op::RefField(dest.0, r.0, Field::new(f)),
+                Instruction::LoadIndex(dest, r, ix) => Op::LoadIndex(dest.0, r.0, Operand::lower(ix)),
+                Instruction::StoreIndex(r, ix, v) => Op::StoreIndex(r.0, Operand::lower(ix), Operand::lower(v)),
+                Instruction::LoadField(dest, r, f) => Op::LoadField(dest.0, r.0, Field::new(f)),
+                Instruction::StoreField(v, r, f) => Op::StoreField(Operand::lower(v), r.0, Field::new(f)),
+                Instruction::Call(dest, p, args) => Op::Call(dest.0, intern(p), operands(args)),
+                Instruction::CallImpl(dest, p, args) => Op::CallImpl(dest.0, p.clone(), operands(args)),
+                Instruction::Return(v) => Op::Return(Operand::lower(v)),
+                Instruction::TailCall(p, args) => Op::TailCall(intern(p), operands(args)),
+                Instruction::TailCallImpl(p, args) => Op::TailCallImpl(p.clone(), operands(args)),
+                Instruction::Invoke { dest, func, args, normal, unwind, exception } => Op::Invoke {
+                    dest: dest.0, func: intern(func), args: operands(args),
+                    normal: *normal, unwind: *unwind, exception: exception.0
+                },
+                Instruction::Throw(v) => Op::Throw(Operand::lower(v)),
+                Instruction::Alloc(dest, ty) => Op::Alloc(dest.0, ty.clone()),
+                Instruction::AllocArray(dest, ty, n) => Op::AllocArray(dest.0, ty.clone(), Operand::lower(n)),
+                Instruction::StackAlloc(dest, ty) => Op::StackAlloc(dest.0, ty.clone()),
+                Instruction::StackAllocArray(dest, ty, n) => Op::StackAllocArray(dest.0, ty.clone(), Operand::lower(n)),
+                Instruction::CopyToStack(dest, r) => Op::CopyToStack(dest.0, r.0),
+                Instruction::CopyToHeap(dest, r) => Op::CopyToHeap(dest.0, r.0),
+                Instruction::RefFunc(_, _) | Instruction::UnwrapVariant(_, _, _, _) => Op::Unsupported(Box::new(i.clone()))
+            }).collect();
+            Block { phis, edges: lower_phis(b), ops, next_block: b.next_block }
+        }).collect();
+        Code { max_registers: body.max_registers, blocks }
+    }
+}
+
+/// turn the phis at the start of a block into the copies to make on each edge into it
+fn lower_phis(block: &ir::code::BasicBlock) -> Vec<Edge> {
+    let phis: Vec<_> = block.instrs.iter().map_while(|i| match i {
+        Instruction::Phi(dest, values) => Some((dest, values)),
+        _ => None
+    }).collect();
+    let mut preds: Vec<BlockIndex> = phis.iter().flat_map(|(_, values)| values.iter().map(|(b, _)| *b)).collect();
+    preds.sort_unstable();
+    preds.dedup();
+    preds.into_iter().filter_map(|from| {
+        let copies = phis.iter()
+            .map(|(dest, values)| values.iter().find(|(b, _)| *b == from).map(|(_, v)| (dest.0, Operand::lower(v))))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Edge { from, copies })
+    }).collect()
+}
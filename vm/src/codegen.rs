@@ -0,0 +1,843 @@
+//! Translating IR function bodies to [Cranelift](https://cranelift.dev) IR, shared by the
+//! [`Jit`](crate::jit::Jit) and the ahead-of-time compiler. A function is only translated if every
+//! instruction that can run in it is supported and the type of every register can be worked out
+//! ahead of time. Translated code holds every value in a 64 bit integer: integers and bools as
+//! their data, unit as zero and references as the address of the value behind them.
+//!
+//! Every translated function has the signature `fn(context: *mut u8, args: *mut u64) -> u32`.
+//! `args` holds the arguments, and room for at least one value. If the function returns, the
+//! result is 0 and the value returned is put in `args[0]`. The context pointer is passed on to the
+//! [`Backend`], which decides how calls, allocations, string literals and traps are compiled and
+//! what any other result means.
+use anyhow::{anyhow, bail, Result};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::{self as cl, condcodes::IntCC, types::{I32, I64}, AbiParam, InstBuilder, MemFlags, StackSlotData, StackSlotKind};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use ir::code::{BasicBlock, BinOp, BlockIndex, Instruction, UnaryOp, Value as IrValue};
+use ir::Trap;
+use crate::memory::Ref;
+use crate::value::{Integer, Value};
+use crate::world::World;
+
+/// The block and instruction index of an IR instruction
+pub type Location = (BlockIndex, usize);
+
+/// An allocation on the heap
+#[derive(Debug)]
+pub enum Allocation<'a> {
+    /// a value of a type, which is `size` bytes
+    Value { ty: &'a ir::Type, size: usize },
+    /// an array of `count` elements of a type, each `el_size` bytes
+    Array { el: &'a ir::Type, el_size: usize, count: cl::Value }
+}
+
+/// The parts of translated code that depend on where it runs. Each method is given the context
+/// pointer of the function and the location of the instruction being translated
+pub trait Backend {
+    /// call a function with arguments of the given types, returning the value it returns
+    fn call(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, callee: &ir::Path,
+        args: &[(cl::Value, ValueType)], ret: &ValueType) -> Result<cl::Value>;
+    /// allocate on the heap, returning the address of the allocation
+    fn alloc(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, alloc: Allocation) -> Result<cl::Value>;
+    /// a reference to a new string holding `s`
+    fn string(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, s: &str) -> Result<cl::Value>;
+    /// leave the function with a trap. This must end the current block
+    fn trap(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, trap: Trap, message: &'static str) -> Result<()>;
+}
+
+/// The types of the arguments and return value of a translated function
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub params: Vec<ValueType>,
+    pub ret: ValueType
+}
+
+/// the Cranelift signature of translated functions, with the calling convention of `module`
+pub fn signature(module: &impl cranelift_module::Module) -> cl::Signature {
+    let mut sig = module.make_signature();
+    sig.params.extend([AbiParam::new(I64), AbiParam::new(I64)]);
+    sig.returns.push(AbiParam::new(I32));
+    sig
+}
+
+/// run `leave` in a new cold block if `cond` is not zero, and carry on in another block otherwise.
+/// `leave` must end the block it is given
+pub fn branch_out(b: &mut FunctionBuilder, cond: cl::Value, leave: impl FnOnce(&mut FunctionBuilder)) {
+    let out = b.create_block();
+    let cont = b.create_block();
+    b.ins().brif(cond, out, &[], cont, &[]);
+    b.switch_to_block(out);
+    b.set_cold_block(out);
+    leave(b);
+    b.switch_to_block(cont);
+}
+
+/// store `values` in a new stack slot with room for at least one value, in the layout of the
+/// `args` of a translated function
+pub fn spill(b: &mut FunctionBuilder, values: &[cl::Value]) -> cl::StackSlot {
+    let slot = b.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot,
+        (values.len().max(1) * 8) as u32, 3));
+    for (n, v) in values.iter().enumerate() {
+        b.ins().stack_store(*v, slot, (n * 8) as i32);
+    }
+    slot
+}
+
+/// translate a function body into `func`, which must have the signature from [`signature`].
+/// Fails if the function uses anything that translated code does not support, in which case
+/// `func` is left part way through and must be cleared
+pub fn translate(func: &mut cl::Function, fctx: &mut FunctionBuilderContext, world: &World,
+    sig: &ir::FunctionSignature, body: &ir::FnBody, backend: &mut impl Backend) -> Result<Signature>
+{
+    let params = sig.args.iter().map(|(ty, _)| ValueType::of_signature(ty)).collect::<Result<Vec<_>>>()?;
+    let flow = Flow::new(body)?;
+    let tys = infer_types(world, body, &flow, &params)?;
+    check_assigned(body, &flow, params.len())?;
+
+    let mut b = FunctionBuilder::new(func, fctx);
+    let entry = b.create_block();
+    b.append_block_params_for_function_params(entry);
+    b.switch_to_block(entry);
+    let context = b.block_params(entry)[0];
+    let args = b.block_params(entry)[1];
+    for r in 0..body.max_registers as usize {
+        b.declare_var(Variable::new(r), I64);
+        let v = if r < params.len() {
+            b.ins().load(I64, MemFlags::trusted(), args, (r * 8) as i32)
+        } else {
+            b.ins().iconst(I64, 0)
+        };
+        b.def_var(Variable::new(r), v);
+    }
+    let blocks = flow.reachable.iter().map(|r| r.then(|| b.create_block())).collect();
+    let mut t = Translator {
+        b, world, tys: &tys, flow: &flow, blocks, edges: Vec::new(),
+        context, args, backend, ret: None, location: (0, 0)
+    };
+    let first = t.block(0);
+    t.b.ins().jump(first, &[]);
+    match t.translate(body) {
+        Ok(()) => {
+            t.b.seal_all_blocks();
+            t.b.finalize();
+            // a function that never returns can say it returns anything
+            Ok(Signature { params, ret: t.ret.unwrap_or(ValueType::Unit) })
+        },
+        Err(e) => {
+            // the builder was left part way through the function
+            drop(t);
+            *fctx = FunctionBuilderContext::new();
+            Err(e)
+        }
+    }
+}
+
+/// The type of a value in compiled code
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+    Unit, Bool,
+    Int { signed: bool, width: u8 },
+    /// a reference, with the type that the [`Ref`] for it has
+    Ref(Box<ir::Type>)
+}
+
+impl ValueType {
+    /// the type of an argument or return value with type `ty` in a function signature
+    pub fn of_signature(ty: &ir::Type) -> Result<ValueType> {
+        Ok(match ty {
+            ir::Type::Ref(t) => ValueType::Ref(t.clone()),
+            ir::Type::Array(_) => ValueType::Ref(Box::new(ty.clone())),
+            t => ValueType::of_scalar(t)?
+        })
+    }
+
+    /// the type of a value of type `ty` loaded from memory, matching [`Ref::value`]
+    fn of_memory(ty: &ir::Type) -> Result<ValueType> {
+        Ok(match ty {
+            ir::Type::Ref(_) | ir::Type::Array(_) => ValueType::Ref(Box::new(ty.clone())),
+            t => ValueType::of_scalar(t)?
+        })
+    }
+
+    fn of_scalar(ty: &ir::Type) -> Result<ValueType> {
+        Ok(match ty {
+            ir::Type::Unit => ValueType::Unit,
+            ir::Type::Bool => ValueType::Bool,
+            ir::Type::Int { signed, width: width @ (8 | 16 | 32 | 64) } => ValueType::Int { signed: *signed, width: *width },
+            t => bail!("values of type {:?} are not supported", t)
+        })
+    }
+
+    fn of_literal(v: &IrValue) -> Result<ValueType> {
+        Ok(match v {
+            IrValue::LiteralUnit => ValueType::Unit,
+            IrValue::LiteralBool(_) => ValueType::Bool,
+            IrValue::LiteralInt(i) => ValueType::Int { signed: i.signed, width: i.width },
+            IrValue::LiteralString(_) => ValueType::Ref(Box::new(ir::Type::Array(Box::new(ir::Type::Int { signed: false, width: 8 })))),
+            v => bail!("literal {:?} is not supported", v)
+        })
+    }
+
+    /// the representation of `v` in compiled code, if it has this type
+    pub fn raw(&self, v: &Value) -> Option<u64> {
+        match (self, v) {
+            (ValueType::Unit, Value::Nil) => Some(0),
+            (ValueType::Bool, Value::Bool(b)) => Some(*b as u64),
+            (ValueType::Int { signed, width }, Value::Int(i)) if i.signed == *signed && i.width == *width => Some(i.data),
+            (ValueType::Ref(ty), Value::Ref(r)) if r.ty == *ty => Some(r.data as u64),
+            _ => None
+        }
+    }
+
+    /// the value of this type that compiled code represents with `raw`
+    pub fn value(&self, raw: u64) -> Value {
+        match self {
+            ValueType::Unit => Value::Nil,
+            ValueType::Bool => Value::Bool(raw != 0),
+            ValueType::Int { signed, width } => Value::Int(Integer::new(*width, *signed, raw)),
+            ValueType::Ref(ty) => Value::Ref(Ref { ty: ty.clone(), data: raw as *mut u8 })
+        }
+    }
+}
+
+/// The control flow between the blocks of a function body
+struct Flow {
+    /// the number of phis at the start of each block
+    phis: Vec<usize>,
+    /// whether each block can run
+    reachable: Vec<bool>,
+    /// the blocks that jump to each block
+    preds: Vec<Vec<BlockIndex>>
+}
+
+impl Flow {
+    fn new(body: &ir::FnBody) -> Result<Flow> {
+        let n = body.blocks.len();
+        let mut phis = Vec::with_capacity(n);
+        for (bi, b) in body.blocks.iter().enumerate() {
+            let count = b.instrs.iter().take_while(|i| matches!(i, Instruction::Phi(_, _))).count();
+            if instrs(b)[count..].iter().any(|i| matches!(i, Instruction::Phi(_, _))) {
+                bail!("phi after other instructions in block {}", bi);
+            }
+            phis.push(count);
+        }
+        let mut reachable = vec![false; n];
+        let mut preds = vec![Vec::new(); n];
+        let mut work = vec![0];
+        if n == 0 {
+            bail!("function has no blocks");
+        }
+        reachable[0] = true;
+        while let Some(b) = work.pop() {
+            for s in successors(&body.blocks[b]) {
+                if s >= n {
+                    bail!("jump to block {} in a function with {} blocks", s, n);
+                }
+                if !preds[s].contains(&b) {
+                    preds[s].push(b);
+                }
+                if !reachable[s] {
+                    reachable[s] = true;
+                    work.push(s);
+                }
+            }
+        }
+        Ok(Flow { phis, reachable, preds })
+    }
+
+    fn blocks<'b>(&self, body: &'b ir::FnBody) -> impl Iterator<Item = (BlockIndex, &'b BasicBlock)> + use<'_, 'b> {
+        body.blocks.iter().enumerate().filter(|(bi, _)| self.reachable[*bi])
+    }
+}
+
+/// the instructions in a block that run, up to the one that leaves it
+fn instrs(block: &BasicBlock) -> &[Instruction] {
+    let end = block.instrs.iter().position(|i| matches!(i, Instruction::Br { .. } | Instruction::Return(_)))
+        .map_or(block.instrs.len(), |e| e + 1);
+    &block.instrs[..end]
+}
+
+fn successors(block: &BasicBlock) -> Vec<BlockIndex> {
+    match instrs(block).last() {
+        Some(Instruction::Br { if_true, if_false, .. }) => vec![*if_true, *if_false],
+        Some(Instruction::Return(_)) => Vec::new(),
+        _ => vec![block.next_block]
+    }
+}
+
+fn phis(block: &BasicBlock) -> impl Iterator<Item = (u32, &Vec<(BlockIndex, IrValue)>)> {
+    block.instrs.iter().map_while(|i| match i {
+        Instruction::Phi(dest, values) => Some((dest.0, values)),
+        _ => None
+    })
+}
+
+/// the register an instruction assigns
+fn dest(i: &Instruction) -> Option<u32> {
+    use Instruction::*;
+    match i {
+        LoadImm(d, _) | BinaryOp(_, d, _, _) | UnaryOp(_, d, _) | LoadRef(d, _) | RefField(d, _, _)
+            | LoadField(d, _, _) | RefIndex(d, _, _) | LoadIndex(d, _, _) | Call(d, _, _)
+            | Alloc(d, _) | AllocArray(d, _, _) | StackAlloc(d, _) | StackAllocArray(d, _, _) => Some(d.0),
+        _ => None
+    }
+}
+
+/// the registers an instruction reads
+fn reads(i: &Instruction) -> Vec<u32> {
+    use Instruction::*;
+    let reg = |v: &IrValue| match v {
+        IrValue::Reg(r) => Some(r.0),
+        _ => None
+    };
+    match i {
+        LoadImm(_, v) | UnaryOp(_, _, v) | Return(v) | Br { cond: v, .. } | AllocArray(_, _, v)
+            | StackAllocArray(_, _, v) => reg(v).into_iter().collect(),
+        BinaryOp(_, _, a, b) => [reg(a), reg(b)].into_iter().flatten().collect(),
+        LoadRef(_, r) | RefField(_, r, _) | LoadField(_, r, _) => vec![r.0],
+        StoreRef(r, v) | RefIndex(_, r, v) | LoadIndex(_, r, v) | StoreField(v, r, _) => [Some(r.0), reg(v)].into_iter().flatten().collect(),
+        StoreIndex(r, ix, v) => [Some(r.0), reg(ix), reg(v)].into_iter().flatten().collect(),
+        Call(_, _, args) => args.iter().filter_map(reg).collect(),
+        _ => Vec::new()
+    }
+}
+
+/// work out the type of every register from the arguments and the instructions that assign it,
+/// failing if a register is given values of different types
+fn infer_types(world: &World, body: &ir::FnBody, flow: &Flow, params: &[ValueType]) -> Result<Vec<Option<ValueType>>> {
+    let mut tys = vec![None; body.max_registers as usize];
+    if params.len() > tys.len() {
+        bail!("function takes {} arguments but only has {} registers", params.len(), tys.len());
+    }
+    for (t, p) in tys.iter_mut().zip(params) {
+        *t = Some(p.clone());
+    }
+    loop {
+        let mut changed = false;
+        for (_, b) in flow.blocks(body) {
+            let mut found = Vec::new();
+            for (dest, values) in phis(b) {
+                for (_, v) in values {
+                    if let Some(ty) = value_ty(&tys, v)? {
+                        found.push((dest, ty));
+                    }
+                }
+            }
+            for i in instrs(b).iter().skip_while(|i| matches!(i, Instruction::Phi(_, _))) {
+                if let (Some(dest), Some(ty)) = (dest(i), result_ty(world, &tys, i)?) {
+                    found.push((dest, ty));
+                }
+            }
+            for (dest, ty) in found {
+                match tys.get_mut(dest as usize) {
+                    None => bail!("register {} does not exist", dest),
+                    Some(t @ None) => {
+                        *t = Some(ty);
+                        changed = true;
+                    },
+                    Some(Some(t)) if *t != ty => bail!("register {} holds both {:?} and {:?}", dest, t, ty),
+                    Some(Some(_)) => {}
+                }
+            }
+        }
+        if !changed {
+            return Ok(tys);
+        }
+    }
+}
+
+/// the type of a value, or `None` if it is a register whose type is not known yet
+fn value_ty(tys: &[Option<ValueType>], v: &IrValue) -> Result<Option<ValueType>> {
+    match v {
+        IrValue::Reg(r) => tys.get(r.0 as usize).cloned().ok_or_else(|| anyhow!("register {} does not exist", r.0)),
+        v => ValueType::of_literal(v).map(Some)
+    }
+}
+
+/// the type of the value an instruction assigns, or `None` if it depends on registers whose type
+/// is not known yet or it does not assign one
+fn result_ty(world: &World, tys: &[Option<ValueType>], i: &Instruction) -> Result<Option<ValueType>> {
+    use Instruction::*;
+    let reg = |r: &ir::code::Register| value_ty(tys, &IrValue::Reg(r.clone()));
+    Ok(match i {
+        LoadImm(_, v) => value_ty(tys, v)?,
+        BinaryOp(op, _, a, b) => match (value_ty(tys, a)?, value_ty(tys, b)?) {
+            (Some(a), Some(b)) => Some(binary_ty(op, &a, &b)?),
+            _ => None
+        },
+        UnaryOp(op, _, v) => match (op, value_ty(tys, v)?) {
+            (_, None) => None,
+            (ir::code::UnaryOp::LogNot, Some(ValueType::Bool)) => Some(ValueType::Bool),
+            (ir::code::UnaryOp::BitNot, Some(t @ ValueType::Int { .. })) => Some(t),
+            (ir::code::UnaryOp::Neg, Some(t @ ValueType::Int { signed: true, .. })) => Some(t),
+            (op, Some(t)) => bail!("{:?} of {:?} is not supported", op, t)
+        },
+        LoadRef(_, r) => reg(r)?.map(|t| ValueType::of_memory(referent(&t)?)).transpose()?,
+        RefField(_, r, f) => reg(r)?.map(|t| Ok::<_, anyhow::Error>(ValueType::Ref(Box::new(world.field_offset(referent(&t)?, f)?.1)))).transpose()?,
+        LoadField(_, r, f) => reg(r)?.map(|t| ValueType::of_memory(&world.field_offset(referent(&t)?, f)?.1)).transpose()?,
+        RefIndex(_, r, _) => reg(r)?.map(|t| Ok::<_, anyhow::Error>(ValueType::Ref(Box::new(element(&t)?.clone())))).transpose()?,
+        LoadIndex(_, r, _) => reg(r)?.map(|t| ValueType::of_memory(element(&t)?)).transpose()?,
+        Call(_, p, _) => {
+            let f = world.get_function(p)?.ok_or_else(|| anyhow!("function {} not found", p))?;
+            Some(ValueType::of_signature(&f.signature().return_type)?)
+        },
+        Alloc(_, ir::Type::Array(_)) | StackAlloc(_, ir::Type::Array(_)) => bail!("arrays must be allocated with AllocArray"),
+        Alloc(_, ty) | StackAlloc(_, ty) => Some(ValueType::Ref(Box::new(ty.clone()))),
+        AllocArray(_, el, _) | StackAllocArray(_, el, _) => Some(ValueType::Ref(Box::new(ir::Type::Array(Box::new(el.clone()))))),
+        StoreRef(..) | StoreField(..) | StoreIndex(..) | Br { .. } | Return(_) => None,
+        i => bail!("instruction {:?} is not supported", i)
+    })
+}
+
+fn binary_ty(op: &BinOp, a: &ValueType, b: &ValueType) -> Result<ValueType> {
+    // like the interpreter, integer operands must have the same type
+    if let (ValueType::Int { signed: sa, width: wa }, ValueType::Int { signed: sb, width: wb }) = (a, b) {
+        if sa != sb || wa != wb {
+            bail!("mismatched integer operands {:?} and {:?}", a, b);
+        }
+    }
+    Ok(match (op, a) {
+        (BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div, ValueType::Int { .. }) if matches!(b, ValueType::Int { .. }) => a.clone(),
+        (BinOp::Eq | BinOp::NEq, _) => ValueType::Bool,
+        (op, _) => bail!("{:?} of {:?} and {:?} is not supported", op, a, b)
+    })
+}
+
+/// the type of the value behind a reference
+fn referent(t: &ValueType) -> Result<&ir::Type> {
+    match t {
+        ValueType::Ref(ty) => Ok(ty),
+        t => bail!("expected a reference, found {:?}", t)
+    }
+}
+
+/// the element type of a reference to an array
+fn element(t: &ValueType) -> Result<&ir::Type> {
+    match referent(t)? {
+        ir::Type::Array(el) => Ok(el),
+        t => bail!("indexing {:?} is not supported", t)
+    }
+}
+
+/// check that every register is assigned before it is read on every path through the function,
+/// since compiled code has no way to represent the unit value that unassigned registers hold in
+/// the interpreter
+fn check_assigned(body: &ir::FnBody, flow: &Flow, args: usize) -> Result<()> {
+    let n = body.max_registers as usize;
+    let entry: Vec<bool> = (0..n).map(|r| r < args).collect();
+    // the registers assigned at the end of each block that has been reached so far
+    let mut out: Vec<Option<Vec<bool>>> = vec![None; body.blocks.len()];
+    let start = |bi: BlockIndex, out: &[Option<Vec<bool>>]| -> Option<Vec<bool>> {
+        let mut assigned = (bi == 0).then(|| entry.clone());
+        for &p in &flow.preds[bi] {
+            if let Some(o) = &out[p] {
+                let mut o = o.clone();
+                for (dest, _) in phis(&body.blocks[bi]) {
+                    o[dest as usize] = true;
+                }
+                assigned = Some(match assigned {
+                    None => o,
+                    Some(a) => a.iter().zip(o).map(|(a, o)| *a && o).collect()
+                });
+            }
+        }
+        assigned
+    };
+    loop {
+        let mut changed = false;
+        for (bi, b) in flow.blocks(body) {
+            let Some(mut assigned) = start(bi, &out) else { continue };
+            for i in &instrs(b)[flow.phis[bi]..] {
+                if let Some(d) = dest(i) {
+                    assigned[d as usize] = true;
+                }
+            }
+            if out[bi].as_ref() != Some(&assigned) {
+                out[bi] = Some(assigned);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    for (bi, b) in flow.blocks(body) {
+        for (dest, values) in phis(b) {
+            for &p in &flow.preds[bi] {
+                match values.iter().find(|(from, _)| *from == p) {
+                    None => bail!("phi for register {} in block {} has no value for predecessor block {}", dest, bi, p),
+                    Some((_, IrValue::Reg(r))) if !out[p].as_ref().is_some_and(|o| o[r.0 as usize]) =>
+                        bail!("register {} may be read by a phi in block {} before it is assigned", r.0, bi),
+                    Some(_) => {}
+                }
+            }
+        }
+        let mut assigned = start(bi, &out).expect("reachable blocks are reached");
+        for i in &instrs(b)[flow.phis[bi]..] {
+            if let Some(r) = reads(i).into_iter().find(|r| !assigned[*r as usize]) {
+                bail!("register {} may be read in block {} before it is assigned", r, bi);
+            }
+            if let Some(d) = dest(i) {
+                assigned[d as usize] = true;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// translates a function body into Cranelift IR
+struct Translator<'a, 'f, B> {
+    b: FunctionBuilder<'f>,
+    world: &'a World,
+    tys: &'a [Option<ValueType>],
+    flow: &'a Flow,
+    /// the Cranelift block for each reachable block
+    blocks: Vec<Option<cl::Block>>,
+    /// blocks that run the phis on an edge, with the blocks they go from and to
+    edges: Vec<(cl::Block, BlockIndex, BlockIndex)>,
+    /// the pointer that the function was given to pass on to the backend
+    context: cl::Value,
+    args: cl::Value,
+    backend: &'a mut B,
+    /// the type of the values returned so far
+    ret: Option<ValueType>,
+    /// the block and instruction being translated
+    location: Location
+}
+
+impl<B: Backend> Translator<'_, '_, B> {
+    fn translate(&mut self, body: &ir::FnBody) -> Result<()> {
+        for (bi, b) in self.flow.blocks(body) {
+            let block = self.block(bi);
+            self.b.switch_to_block(block);
+            let instrs = instrs(b);
+            for (ii, i) in instrs.iter().enumerate().skip(self.flow.phis[bi]) {
+                self.location = (bi, ii);
+                self.instruction(i)?;
+            }
+            if !matches!(instrs.last(), Some(Instruction::Br { .. } | Instruction::Return(_))) {
+                let next = self.edge(bi, b.next_block);
+                self.b.ins().jump(next, &[]);
+            }
+        }
+        for (block, from, to) in std::mem::take(&mut self.edges) {
+            self.b.switch_to_block(block);
+            // read every value before assigning any, since the phis are a parallel copy
+            let mut copies = Vec::new();
+            for (dest, values) in phis(&body.blocks[to]) {
+                let (_, v) = values.iter().find(|(p, _)| *p == from).expect("phi values were checked");
+                let (v, ty) = self.value(v)?;
+                if Some(&ty) != self.tys[dest as usize].as_ref() {
+                    bail!("phi for register {} in block {} is given a {:?}", dest, to, ty);
+                }
+                copies.push((dest, v));
+            }
+            for (dest, v) in copies {
+                self.b.def_var(Variable::new(dest as usize), v);
+            }
+            let target = self.block(to);
+            self.b.ins().jump(target, &[]);
+        }
+        Ok(())
+    }
+
+    fn block(&self, bi: BlockIndex) -> cl::Block {
+        self.blocks[bi].expect("jumps go to reachable blocks")
+    }
+
+    /// the block to jump to for going from one block to another, which runs the phis at the start
+    /// of the block it goes to
+    fn edge(&mut self, from: BlockIndex, to: BlockIndex) -> cl::Block {
+        if self.flow.phis[to] == 0 {
+            return self.block(to);
+        }
+        let block = self.b.create_block();
+        self.edges.push((block, from, to));
+        block
+    }
+
+    fn reg(&mut self, r: u32) -> Result<(cl::Value, ValueType)> {
+        let ty = self.tys.get(r as usize).cloned().flatten().ok_or_else(|| anyhow!("register {} has no type", r))?;
+        Ok((self.b.use_var(Variable::new(r as usize)), ty))
+    }
+
+    fn value(&mut self, v: &IrValue) -> Result<(cl::Value, ValueType)> {
+        let raw = match v {
+            IrValue::Reg(r) => return self.reg(r.0),
+            IrValue::LiteralUnit => 0,
+            IrValue::LiteralBool(b) => *b as u64,
+            IrValue::LiteralInt(i) => i.data,
+            IrValue::LiteralString(st) => {
+                let addr = self.backend.string(&mut self.b, self.context, self.location, st)?;
+                return Ok((addr, ValueType::of_literal(v)?));
+            },
+            v => bail!("literal {:?} is not supported", v)
+        };
+        Ok((self.b.ins().iconst(I64, raw as i64), ValueType::of_literal(v)?))
+    }
+
+    fn set(&mut self, dest: &ir::code::Register, v: cl::Value) {
+        self.b.def_var(Variable::new(dest.0 as usize), v);
+    }
+
+    /// trap if an integer computed in 64 bits does not fit in the width of its type. integers are
+    /// kept sign or zero extended to 64 bits, like [`Integer`] in the interpreter
+    fn check_width(&mut self, v: cl::Value, signed: bool, width: u8, message: &'static str) -> Result<()> {
+        if width == 64 {
+            return Ok(());
+        }
+        let ty = cl::Type::int(width as u16).ok_or_else(|| anyhow!("integers of width {} are not supported", width))?;
+        let narrow = self.b.ins().ireduce(ty, v);
+        let wide = if signed { self.b.ins().sextend(I64, narrow) } else { self.b.ins().uextend(I64, narrow) };
+        let out = self.b.ins().icmp(IntCC::NotEqual, wide, v);
+        self.trap_if(out, Trap::Overflow, message)
+    }
+
+    fn trap_if(&mut self, cond: cl::Value, trap: Trap, message: &'static str) -> Result<()> {
+        let (backend, context, location) = (&mut *self.backend, self.context, self.location);
+        let mut trapped = Ok(());
+        branch_out(&mut self.b, cond, |b| trapped = backend.trap(b, context, location, trap, message));
+        trapped
+    }
+
+    /// load a value of type `ty` from memory, like [`Ref::value`]
+    fn load(&mut self, ty: &ir::Type, addr: cl::Value) -> Result<(cl::Value, ValueType)> {
+        let jt = ValueType::of_memory(ty)?;
+        let flags = MemFlags::trusted();
+        let v = match ty {
+            ir::Type::Unit => self.b.ins().iconst(I64, 0),
+            ir::Type::Bool => {
+                let byte = self.b.ins().uload8(I64, flags, addr, 0);
+                let b = self.b.ins().icmp_imm(IntCC::NotEqual, byte, 0);
+                self.b.ins().uextend(I64, b)
+            },
+            ir::Type::Int { width: 8, .. } => self.b.ins().uload8(I64, flags, addr, 0),
+            ir::Type::Int { width: 16, .. } => self.b.ins().uload16(I64, flags, addr, 0),
+            ir::Type::Int { width: 32, .. } => self.b.ins().uload32(flags, addr, 0),
+            _ => self.b.ins().load(I64, flags, addr, 0)
+        };
+        Ok((v, jt))
+    }
+
+    /// store a value into memory holding type `ty`, like [`Ref::set_value`]
+    fn store(&mut self, ty: &ir::Type, v: &IrValue, addr: cl::Value) -> Result<()> {
+        let (v, vt) = self.value(v)?;
+        let flags = MemFlags::trusted();
+        match (ty, &vt) {
+            (ir::Type::Bool, ValueType::Bool) => { self.b.ins().istore8(flags, v, addr, 0); },
+            (ir::Type::Int { signed: ts, width: tw }, ValueType::Int { signed, width }) if ts == signed && width <= tw => {
+                match tw {
+                    8 => self.b.ins().istore8(flags, v, addr, 0),
+                    16 => self.b.ins().istore16(flags, v, addr, 0),
+                    32 => self.b.ins().istore32(flags, v, addr, 0),
+                    _ => self.b.ins().store(flags, v, addr, 0)
+                };
+            },
+            (ir::Type::Ref(_), ValueType::Ref(_)) => { self.b.ins().store(flags, v, addr, 0); },
+            (ir::Type::Array(_), ValueType::Ref(r)) if matches!(r.as_ref(), ir::Type::Array(_)) => {
+                self.b.ins().store(flags, v, addr, 0);
+            },
+            (ty, vt) => bail!("storing {:?} in {:?} is not supported", vt, ty)
+        }
+        Ok(())
+    }
+
+    /// the address of a field of the value behind a reference, and the type of the field
+    fn field(&mut self, r: &ir::code::Register, field: &ir::Symbol) -> Result<(cl::Value, ir::Type)> {
+        let (ptr, ty) = self.reg(r.0)?;
+        let (offset, field_ty) = self.world.field_offset(referent(&ty)?, field)?;
+        Ok((self.b.ins().iadd_imm(ptr, offset as i64), field_ty))
+    }
+
+    /// the address of an element of the array behind a reference, trapping if the index is out
+    /// of bounds, and the type of the element
+    fn element(&mut self, r: &ir::code::Register, index: &IrValue) -> Result<(cl::Value, ir::Type)> {
+        let (ptr, ty) = self.reg(r.0)?;
+        let el = element(&ty)?.clone();
+        let (index, it) = self.value(index)?;
+        if !matches!(it, ValueType::Int { signed: false, .. }) {
+            bail!("index of type {:?} is not supported", it);
+        }
+        let count = self.b.ins().load(I64, MemFlags::trusted(), ptr, 0);
+        let out = self.b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, index, count);
+        self.trap_if(out, Trap::IndexOutOfBounds, "array index out of bounds")?;
+        let offset = self.b.ins().imul_imm(index, self.world.size_of_type(&el)? as i64);
+        let offset = self.b.ins().iadd_imm(offset, std::mem::size_of::<usize>() as i64);
+        Ok((self.b.ins().iadd(ptr, offset), el))
+    }
+
+    fn instruction(&mut self, i: &Instruction) -> Result<()> {
+        match i {
+            Instruction::LoadImm(dest, v) => {
+                let (v, _) = self.value(v)?;
+                self.set(dest, v);
+            },
+            Instruction::BinaryOp(op, dest, lhs, rhs) => {
+                let (a, at) = self.value(lhs)?;
+                let (b, bt) = self.value(rhs)?;
+                let ty = binary_ty(op, &at, &bt)?;
+                let v = match (op, ty) {
+                    (BinOp::Add, ValueType::Int { signed, width }) => {
+                        let (sum, overflow) = if signed {
+                            self.b.ins().sadd_overflow(a, b)
+                        } else {
+                            self.b.ins().uadd_overflow(a, b)
+                        };
+                        self.trap_if(overflow, Trap::Overflow, "integer overflow in addition")?;
+                        self.check_width(sum, signed, width, "integer overflow in addition")?;
+                        sum
+                    },
+                    (BinOp::Sub, ValueType::Int { signed, width }) => {
+                        let (diff, overflow) = if signed {
+                            self.b.ins().ssub_overflow(a, b)
+                        } else {
+                            self.b.ins().usub_overflow(a, b)
+                        };
+                        self.trap_if(overflow, Trap::Overflow, "integer overflow in subtraction")?;
+                        self.check_width(diff, signed, width, "integer overflow in subtraction")?;
+                        diff
+                    },
+                    (BinOp::Mul, ValueType::Int { signed, width }) => {
+                        let (product, overflow) = if signed {
+                            self.b.ins().smul_overflow(a, b)
+                        } else {
+                            self.b.ins().umul_overflow(a, b)
+                        };
+                        self.trap_if(overflow, Trap::Overflow, "integer overflow in multiplication")?;
+                        self.check_width(product, signed, width, "integer overflow in multiplication")?;
+                        product
+                    },
+                    (BinOp::Div, ValueType::Int { signed, width }) => {
+                        let zero = self.b.ins().icmp_imm(IntCC::Equal, b, 0);
+                        self.trap_if(zero, Trap::DivisionByZero, "integer division by zero")?;
+                        if !signed {
+                            self.b.ins().udiv(a, b)
+                        } else {
+                            if width == 64 {
+                                // the only quotient that does not fit is the smallest value over -1
+                                let min = self.b.ins().icmp_imm(IntCC::Equal, a, i64::MIN);
+                                let neg = self.b.ins().icmp_imm(IntCC::Equal, b, -1);
+                                let out = self.b.ins().band(min, neg);
+                                self.trap_if(out, Trap::Overflow, "integer overflow in division")?;
+                            }
+                            let quotient = self.b.ins().sdiv(a, b);
+                            self.check_width(quotient, signed, width, "integer overflow in division")?;
+                            quotient
+                        }
+                    },
+                    (BinOp::Eq | BinOp::NEq, _) => {
+                        // values of different types are never equal
+                        let eq = if at == bt {
+                            let eq = self.b.ins().icmp(IntCC::Equal, a, b);
+                            self.b.ins().uextend(I64, eq)
+                        } else {
+                            self.b.ins().iconst(I64, 0)
+                        };
+                        if matches!(op, BinOp::NEq) { self.b.ins().bxor_imm(eq, 1) } else { eq }
+                    },
+                    (op, _) => bail!("{:?} is not supported", op)
+                };
+                self.set(dest, v);
+            },
+            Instruction::UnaryOp(op, dest, v) => {
+                let (v, ty) = self.value(v)?;
+                let v = match (op, ty) {
+                    (UnaryOp::BitNot, ValueType::Int { signed: false, width }) if width < 64 => {
+                        // unsigned integers are kept zero extended
+                        let not = self.b.ins().bnot(v);
+                        self.b.ins().band_imm(not, (1i64 << width) - 1)
+                    },
+                    (UnaryOp::BitNot, _) => self.b.ins().bnot(v),
+                    (UnaryOp::Neg, ValueType::Int { width, .. }) => {
+                        // the smallest value of the width has no negation that fits
+                        let min = self.b.ins().icmp_imm(IntCC::Equal, v, i64::MIN >> (64 - width as u32));
+                        self.trap_if(min, Trap::Overflow, "integer overflow in negation")?;
+                        self.b.ins().ineg(v)
+                    },
+                    (UnaryOp::LogNot, _) => self.b.ins().bxor_imm(v, 1),
+                    (UnaryOp::Neg, t) => bail!("negation of {:?} is not supported", t)
+                };
+                self.set(dest, v);
+            },
+            Instruction::LoadRef(dest, r) => {
+                let (ptr, ty) = self.reg(r.0)?;
+                let (v, _) = self.load(&referent(&ty)?.clone(), ptr)?;
+                self.set(dest, v);
+            },
+            Instruction::StoreRef(r, v) => {
+                let (ptr, ty) = self.reg(r.0)?;
+                self.store(&referent(&ty)?.clone(), v, ptr)?;
+            },
+            Instruction::RefField(dest, r, f) => {
+                let (addr, _) = self.field(r, f)?;
+                self.set(dest, addr);
+            },
+            Instruction::LoadField(dest, r, f) => {
+                let (addr, ty) = self.field(r, f)?;
+                let (v, _) = self.load(&ty, addr)?;
+                self.set(dest, v);
+            },
+            Instruction::StoreField(v, r, f) => {
+                let (addr, ty) = self.field(r, f)?;
+                self.store(&ty, v, addr)?;
+            },
+            Instruction::RefIndex(dest, r, index) => {
+                let (addr, _) = self.element(r, index)?;
+                self.set(dest, addr);
+            },
+            Instruction::LoadIndex(dest, r, index) => {
+                let (addr, ty) = self.element(r, index)?;
+                let (v, _) = self.load(&ty, addr)?;
+                self.set(dest, v);
+            },
+            Instruction::StoreIndex(r, index, v) => {
+                let (addr, ty) = self.element(r, index)?;
+                self.store(&ty, v, addr)?;
+            },
+            Instruction::Call(dest, path, args) => {
+                let ret = result_ty(self.world, self.tys, i)?.expect("calls have a result type");
+                let args = args.iter().map(|a| self.value(a)).collect::<Result<Vec<_>>>()?;
+                let v = self.backend.call(&mut self.b, self.context, self.location, path, &args, &ret)?;
+                self.set(dest, v);
+            },
+            // compiled code has no data stack, so stack allocations are made with the backend
+            // too, which keeps them for at least as long as the function runs
+            Instruction::Alloc(dest, ty) | Instruction::StackAlloc(dest, ty) => {
+                let size = self.world.size_of_type(ty)?;
+                let addr = self.backend.alloc(&mut self.b, self.context, self.location, Allocation::Value { ty, size })?;
+                self.set(dest, addr);
+            },
+            Instruction::AllocArray(dest, el, count) | Instruction::StackAllocArray(dest, el, count) => {
+                let (count, ct) = self.value(count)?;
+                if !matches!(ct, ValueType::Int { signed: false, .. }) {
+                    bail!("array length of type {:?} is not supported", ct);
+                }
+                let el_size = self.world.size_of_type(el)?;
+                let addr = self.backend.alloc(&mut self.b, self.context, self.location, Allocation::Array { el, el_size, count })?;
+                self.set(dest, addr);
+            },
+            Instruction::Br { cond, if_true, if_false } => {
+                let (cond, ty) = self.value(cond)?;
+                if ty != ValueType::Bool {
+                    bail!("branch on {:?} is not supported", ty);
+                }
+                let from = self.location.0;
+                let t = self.edge(from, *if_true);
+                let f = self.edge(from, *if_false);
+                self.b.ins().brif(cond, t, &[], f, &[]);
+            },
+            Instruction::Return(v) => {
+                let (v, ty) = self.value(v)?;
+                match &self.ret {
+                    Some(r) if *r != ty => bail!("function returns both {:?} and {:?}", r, ty),
+                    _ => self.ret = Some(ty)
+                }
+                self.b.ins().store(MemFlags::trusted(), v, self.args, 0);
+                let ok = self.b.ins().iconst(I32, 0);
+                self.b.ins().return_(&[ok]);
+            },
+            i => bail!("instruction {:?} is not supported", i)
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! The import graph between modules, used to detect circular imports and to show which version of
+//! each module was loaded to satisfy each import.
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
+
+/// A directed graph with an edge from each module to every module it imports
+#[derive(Debug, Default)]
+pub struct ImportGraph {
+    modules: Vec<GraphModule>,
+    index: HashMap<ir::Path, usize>
+}
+
+#[derive(Debug)]
+struct GraphModule {
+    /// The path the module is loaded under
+    path: ir::Path,
+    version: ir::Version,
+    /// Modules in the same group are loaded together, so imports between them are never circular
+    group: Option<usize>,
+    /// The file the module was loaded from, if any
+    source: Option<PathBuf>,
+    imports: Vec<(ir::Path, ir::VersionReq)>
+}
+
+/// An error for a chain of imports that leads back to where it started
+#[derive(Debug)]
+pub struct ImportCycle {
+    /// The modules in the cycle, each importing the next, and the last importing the first
+    pub modules: Vec<(ir::Path, ir::Version)>
+}
+
+impl ImportGraph {
+    /// Add a module to the graph, in an optional group of modules that are loaded together
+    pub fn add(&mut self, m: &ir::Module, group: Option<usize>, source: Option<PathBuf>) {
+        self.index.insert(m.path.clone(), self.modules.len());
+        self.modules.push(GraphModule {
+            path: m.path.clone(),
+            version: m.version.clone(),
+            group, source,
+            imports: m.imports.clone()
+        });
+    }
+
+    /// the modules imported by module `ix` that are in the graph, ignoring imports within a group
+    fn edges(&self, ix: usize) -> impl Iterator<Item=usize> + '_ {
+        let m = &self.modules[ix];
+        m.imports.iter()
+            .filter_map(|(p, _)| self.index.get(p).copied())
+            .filter(move |&t| m.group.is_none() || self.modules[t].group != m.group)
+    }
+
+    /// Find a cycle of imports that passes through the module at `path`, if there is one
+    pub fn find_cycle_through(&self, path: &ir::Path) -> Option<ImportCycle> {
+        let start = *self.index.get(path)?;
+        // modules that have been searched without finding a way back to the start
+        let mut done = vec![false; self.modules.len()];
+        // iterative depth first search, keeping the current chain of imports on the stack
+        let mut stack: Vec<(usize, Vec<usize>)> = vec![(start, self.edges(start).collect())];
+        while let Some((node, rest)) = stack.last_mut() {
+            let node = *node;
+            match rest.pop() {
+                Some(next) if next == start => return Some(ImportCycle {
+                    modules: stack.iter()
+                        .map(|(n, _)| (self.modules[*n].path.clone(), self.modules[*n].version.clone()))
+                        .collect()
+                }),
+                Some(next) if !done[next] && !stack.iter().any(|(n, _)| *n == next) => {
+                    let edges = self.edges(next).collect();
+                    stack.push((next, edges));
+                },
+                Some(_) => {},
+                None => {
+                    done[node] = true;
+                    stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Display for ImportGraph {
+    /// Lists each module in the graph with the module version that was loaded for each import
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut order: Vec<&GraphModule> = self.modules.iter().collect();
+        order.sort_by_key(|m| m.path.to_string());
+        for m in order {
+            write!(f, "{} v{}", m.path, m.version)?;
+            if let Some(s) = &m.source {
+                write!(f, " ({})", s.display())?;
+            }
+            writeln!(f)?;
+            for (p, req) in m.imports.iter() {
+                match self.index.get(p) {
+                    Some(&t) => writeln!(f, "    {} {} -> {} v{}", crate::resolve::unversioned_path(p), req, p, self.modules[t].version)?,
+                    None => writeln!(f, "    {} {} -> (not loaded)", p, req)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for ImportCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "circular import: ")?;
+        for (p, v) in self.modules.iter() {
+            write!(f, "{} v{} -> ", p, v)?;
+        }
+        let (p, v) = &self.modules[0];
+        write!(f, "{} v{}", p, v)
+    }
+}
+
+impl std::error::Error for ImportCycle {}
@@ -0,0 +1,156 @@
+//! Errors that occur while running OXLR code, with the location they occurred at and the stack of
+//! OXLR function calls that led there.
+use std::fmt::Display;
+use crate::value::Value;
+
+/// What went wrong when an error occurred
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// A called function does not exist in any loaded module
+    FunctionNotFound(ir::Path),
+    /// The type of the first argument to an interface function has no implementation of it
+    ImplementationNotFound { function: ir::Path, ty: ir::Type },
+    /// An instruction was given a value of the wrong type
+    TypeMismatch { expected: String, found: String },
+    /// Any other trap caused by running code
+    Trap { trap: ir::Trap, message: String },
+    /// An exception was thrown and not handled by any function. The thrown value itself is kept
+    /// by the [`Machine`](crate::Machine)
+    Exception(String),
+    /// A native function returned an error
+    Native { function: ir::Path, error: anyhow::Error },
+    /// Any other error, such as running out of memory or failing to load a module
+    Other(anyhow::Error)
+}
+
+/// A function call on the stack when an error occurred
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub function: ir::Path,
+    /// The block and instruction index that was running in the function, or `None` for native
+    /// functions
+    pub location: Option<(usize, usize)>
+}
+
+/// An error from running OXLR code
+#[derive(Debug)]
+pub struct VmError {
+    pub kind: ErrorKind,
+    /// The function calls that were running when the error occurred, starting from the innermost
+    pub stack: Vec<StackFrame>
+}
+
+impl ErrorKind {
+    /// an error for a value that is not of the `expected` type
+    pub fn mismatch(expected: &str, found: &Value) -> ErrorKind {
+        ErrorKind::TypeMismatch { expected: expected.into(), found: format!("{:?}", found) }
+    }
+
+    /// an error for a trap
+    pub fn trap(trap: ir::Trap, message: impl Into<String>) -> ErrorKind {
+        ErrorKind::Trap { trap, message: message.into() }
+    }
+}
+
+impl VmError {
+    pub fn new(kind: ErrorKind) -> VmError {
+        VmError { kind, stack: Vec::new() }
+    }
+
+    /// an error returned by a native function
+    pub fn native(function: &ir::Path, error: anyhow::Error) -> VmError {
+        VmError {
+            kind: ErrorKind::Native { function: function.clone(), error },
+            stack: vec![StackFrame { function: function.clone(), location: None }]
+        }
+    }
+
+    /// add the function that called the functions already on the stack
+    pub fn in_frame(mut self, function: ir::Path, block: usize, instruction: usize) -> VmError {
+        self.stack.push(StackFrame { function, location: Some((block, instruction)) });
+        self
+    }
+
+    /// the kind of trap that the error is, if it was caused by running code. Traps can be handled
+    /// by the program like exceptions, while other errors such as failing to load a module always
+    /// stop it
+    pub fn trap(&self) -> Option<ir::Trap> {
+        match &self.kind {
+            ErrorKind::FunctionNotFound(_) => Some(ir::Trap::FunctionNotFound),
+            ErrorKind::ImplementationNotFound { .. } => Some(ir::Trap::ImplementationNotFound),
+            ErrorKind::TypeMismatch { .. } => Some(ir::Trap::TypeMismatch),
+            ErrorKind::Native { .. } => Some(ir::Trap::NativeError),
+            ErrorKind::Trap { trap, .. } => Some(*trap),
+            ErrorKind::Exception(_) | ErrorKind::Other(_) => None
+        }
+    }
+
+    /// the location of the innermost OXLR instruction that was running when the error occurred
+    pub fn location(&self) -> Option<&StackFrame> {
+        self.stack.iter().find(|f| f.location.is_some())
+    }
+}
+
+/// errors from running code are either a [`VmError`] from a call that already failed, an
+/// [`ErrorKind`] describing a new error, or some other error
+impl From<anyhow::Error> for VmError {
+    fn from(e: anyhow::Error) -> VmError {
+        match e.downcast::<VmError>() {
+            Ok(ve) => ve,
+            Err(e) => match e.downcast::<ErrorKind>() {
+                Ok(kind) => VmError::new(kind),
+                Err(e) => VmError::new(ErrorKind::Other(e))
+            }
+        }
+    }
+}
+
+impl From<ErrorKind> for VmError {
+    fn from(kind: ErrorKind) -> VmError {
+        VmError::new(kind)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::FunctionNotFound(p) => write!(f, "function {} not found", p),
+            ErrorKind::ImplementationNotFound { function, ty } => write!(f, "no implementation of {} for {:?}", function, ty),
+            ErrorKind::TypeMismatch { expected, found } => write!(f, "expected {}, got {}", expected, found),
+            ErrorKind::Trap { message, .. } => write!(f, "{}", message),
+            ErrorKind::Exception(v) => write!(f, "uncaught exception: {}", v),
+            ErrorKind::Native { function, error } => write!(f, "native function {} failed: {:#}", function, error),
+            ErrorKind::Other(e) => write!(f, "{:#}", e)
+        }
+    }
+}
+
+impl Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some((block, instr)) => write!(f, "{} (block {}, instruction {})", self.function, block, instr),
+            None => write!(f, "{} (native)", self.function)
+        }
+    }
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        // deep recursion can leave a very long stack, so only the ends of it are shown
+        const SHOWN: usize = 16;
+        for (i, frame) in self.stack.iter().enumerate() {
+            if self.stack.len() > 2 * SHOWN && i >= SHOWN && i < self.stack.len() - SHOWN {
+                if i == SHOWN {
+                    write!(f, "\n    ... {} more", self.stack.len() - 2 * SHOWN)?;
+                }
+                continue;
+            }
+            write!(f, "\n    at {}", frame)?;
+        }
+        std::result::Result::Ok(())
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+impl std::error::Error for VmError {}
@@ -0,0 +1,209 @@
+//! Compiling hot functions from IR to native code with [Cranelift](https://cranelift.dev), using
+//! the translation in [`codegen`](crate::codegen). Anything that cannot be translated keeps running
+//! in the interpreter. Traps are returned to the [`Machine`](crate::Machine) as the index of the
+//! [`Site`] that caused them, along with calls to other functions and allocations, which compiled
+//! code makes through the machine.
+use anyhow::{anyhow, bail, Result};
+use cranelift_codegen::ir::{self as cl, condcodes::IntCC, types::{I32, I64}, AbiParam, InstBuilder, SigRef};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+use ir::Trap;
+use crate::bytecode::FnIndex;
+use crate::codegen::{self, branch_out, spill, Allocation, Location, ValueType};
+use crate::world::World;
+
+/// What happens at a point in compiled code that can stop it
+#[derive(Debug)]
+pub enum SiteKind {
+    /// the code traps
+    Trap(Trap, &'static str),
+    /// a call to another function, with the types of its arguments and of the value it returns
+    Call { callee: FnIndex, args: Vec<ValueType>, ret: ValueType },
+    /// a value of a type is allocated on the heap
+    Alloc(ir::Type),
+    /// an array with elements of a type is allocated on the heap
+    AllocArray(ir::Type)
+}
+
+/// A point in compiled code that can stop it, with the block and instruction index of the IR
+/// instruction it came from
+#[derive(Debug)]
+pub struct Site {
+    pub kind: SiteKind,
+    pub location: Location
+}
+
+/// The signature of compiled functions, which is that of [translated](codegen) functions given
+/// the machine as their context. If the function does not return, the result is one more than the
+/// index of the [`Site`] that stopped it
+type JitFn = unsafe extern "C" fn(machine: *mut u8, args: *mut u64) -> u32;
+
+/// The functions in the machine that compiled code calls, passing the machine pointer it was given
+#[derive(Debug, Clone, Copy)]
+pub struct Helpers {
+    /// `fn(machine, site: u32, args: *mut u64) -> u32` makes the call at a [`SiteKind::Call`]
+    /// with the arguments in `args`, putting the value returned in `args[0]`. Returns 0 if the
+    /// call returned
+    pub call: usize,
+    /// `fn(machine, site: u32, count: u64) -> u64` makes the allocation at a [`SiteKind::Alloc`]
+    /// or [`SiteKind::AllocArray`], returning its address or 0 if it failed
+    pub alloc: usize
+}
+
+/// A function compiled to native code
+pub struct Compiled {
+    code: JitFn,
+    pub params: Vec<ValueType>,
+    pub ret: ValueType,
+    pub sites: Vec<Site>
+}
+
+impl Compiled {
+    /// run the function with the arguments in `args`, which must have the types in `params` and
+    /// at least one element
+    ///
+    /// # Safety
+    /// `machine` must be the machine that the helpers given to [`Jit::compile`] expect
+    pub unsafe fn run(&self, machine: *mut u8, args: &mut [u64]) -> u32 {
+        assert!(args.len() >= self.params.len().max(1));
+        (self.code)(machine, args.as_mut_ptr())
+    }
+}
+
+/// A compiler from IR function bodies to native code. Compiled code stays in memory as long as
+/// the compiler does
+pub struct Jit {
+    module: JITModule,
+    ctx: cranelift_codegen::Context,
+    fctx: FunctionBuilderContext
+}
+
+impl Jit {
+    /// make a compiler for the machine the VM is running on
+    pub fn new() -> Result<Jit> {
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false")?;
+        flags.set("is_pic", "false")?;
+        flags.set("opt_level", "speed")?;
+        let isa = cranelift_native::builder().map_err(|e| anyhow!("unsupported host: {}", e))?
+            .finish(settings::Flags::new(flags))?;
+        if isa.pointer_type() != I64 {
+            bail!("compiling to native code needs 64 bit pointers");
+        }
+        let module = JITModule::new(JITBuilder::with_isa(isa, cranelift_module::default_libcall_names()));
+        Ok(Jit { ctx: module.make_context(), module, fctx: FunctionBuilderContext::new() })
+    }
+
+    /// compile a function, using `intern` to give an index to each function that it calls.
+    /// Fails if the function uses anything that compiled code does not support
+    pub fn compile(&mut self, world: &World, sig: &ir::FunctionSignature, body: &ir::FnBody,
+        intern: &mut impl FnMut(&ir::Path) -> FnIndex, helpers: Helpers) -> Result<Compiled>
+    {
+        let fsig = codegen::signature(&self.module);
+        self.ctx.func.signature = fsig.clone();
+        let mut call_sig = self.module.make_signature();
+        call_sig.params.extend([AbiParam::new(I64), AbiParam::new(I32), AbiParam::new(I64)]);
+        call_sig.returns.push(AbiParam::new(I32));
+        let mut alloc_sig = self.module.make_signature();
+        alloc_sig.params.extend([AbiParam::new(I64), AbiParam::new(I32), AbiParam::new(I64)]);
+        alloc_sig.returns.push(AbiParam::new(I64));
+        let mut backend = JitBackend {
+            helpers, intern, sites: Vec::new(),
+            call_sig: (call_sig, None), alloc_sig: (alloc_sig, None)
+        };
+        let signature = match codegen::translate(&mut self.ctx.func, &mut self.fctx, world, sig, body, &mut backend) {
+            Ok(s) => s,
+            Err(e) => {
+                self.module.clear_context(&mut self.ctx);
+                return Err(e);
+            }
+        };
+        let id = self.module.declare_anonymous_function(&fsig)?;
+        let defined = self.module.define_function(id, &mut self.ctx);
+        self.module.clear_context(&mut self.ctx);
+        defined.map_err(|e| anyhow!("compiling to native code failed: {:?}", e))?;
+        self.module.finalize_definitions()?;
+        let code = self.module.get_finalized_function(id);
+        Ok(Compiled {
+            // SAFETY: the function was compiled with the signature of JitFn
+            code: unsafe { std::mem::transmute::<*const u8, JitFn>(code) },
+            params: signature.params,
+            ret: signature.ret,
+            sites: backend.sites
+        })
+    }
+}
+
+/// Compiles calls, allocations and traps into returns to the machine or calls to its [`Helpers`]
+struct JitBackend<'i, I> {
+    helpers: Helpers,
+    intern: &'i mut I,
+    sites: Vec<Site>,
+    /// the signatures of the helpers, and their references once imported into the function
+    call_sig: (cl::Signature, Option<SigRef>),
+    alloc_sig: (cl::Signature, Option<SigRef>)
+}
+
+impl<I> JitBackend<'_, I> {
+    fn site(&mut self, kind: SiteKind, location: Location) -> u32 {
+        self.sites.push(Site { kind, location });
+        (self.sites.len() - 1) as u32
+    }
+
+    /// leave the function at `site` if `cond` is not zero
+    fn fail_if(b: &mut FunctionBuilder, cond: cl::Value, site: u32) {
+        branch_out(b, cond, |b| {
+            let status = b.ins().iconst(I32, site as i64 + 1);
+            b.ins().return_(&[status]);
+        });
+    }
+
+    /// call a helper with the machine, a site and one more argument
+    fn helper(b: &mut FunctionBuilder, sig: &mut (cl::Signature, Option<SigRef>), f: usize, args: [cl::Value; 3]) -> cl::Value {
+        let sig = *sig.1.get_or_insert_with(|| b.import_signature(sig.0.clone()));
+        let f = b.ins().iconst(I64, f as i64);
+        let call = b.ins().call_indirect(sig, f, &args);
+        b.inst_results(call)[0]
+    }
+}
+
+impl<I: FnMut(&ir::Path) -> FnIndex> codegen::Backend for JitBackend<'_, I> {
+    fn call(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, callee: &ir::Path,
+        args: &[(cl::Value, ValueType)], ret: &ValueType) -> Result<cl::Value>
+    {
+        let slot = spill(b, &args.iter().map(|(v, _)| *v).collect::<Vec<_>>());
+        let callee = (self.intern)(callee);
+        let site = self.site(SiteKind::Call { callee, args: args.iter().map(|(_, t)| t.clone()).collect(), ret: ret.clone() }, location);
+        let site_v = b.ins().iconst(I32, site as i64);
+        let addr = b.ins().stack_addr(I64, slot, 0);
+        let status = Self::helper(b, &mut self.call_sig, self.helpers.call, [context, site_v, addr]);
+        Self::fail_if(b, status, site);
+        Ok(b.ins().stack_load(I64, slot, 0))
+    }
+
+    fn alloc(&mut self, b: &mut FunctionBuilder, context: cl::Value, location: Location, alloc: Allocation) -> Result<cl::Value> {
+        let (kind, count) = match alloc {
+            Allocation::Value { ty, .. } => (SiteKind::Alloc(ty.clone()), b.ins().iconst(I64, 0)),
+            Allocation::Array { el, count, .. } => (SiteKind::AllocArray(el.clone()), count)
+        };
+        let site = self.site(kind, location);
+        let site_v = b.ins().iconst(I32, site as i64);
+        let addr = Self::helper(b, &mut self.alloc_sig, self.helpers.alloc, [context, site_v, count]);
+        let failed = b.ins().icmp_imm(IntCC::Equal, addr, 0);
+        Self::fail_if(b, failed, site);
+        Ok(addr)
+    }
+
+    fn string(&mut self, _: &mut FunctionBuilder, _: cl::Value, _: Location, _: &str) -> Result<cl::Value> {
+        bail!("string literals are not supported")
+    }
+
+    fn trap(&mut self, b: &mut FunctionBuilder, _: cl::Value, location: Location, trap: Trap, message: &'static str) -> Result<()> {
+        let site = self.site(SiteKind::Trap(trap, message), location);
+        let status = b.ins().iconst(I32, site as i64 + 1);
+        b.ins().return_(&[status]);
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+//! The OXLR virtual machine. Modules are loaded into a [`World`], either from module files found
+//! in a [`SearchPath`] or directly from [`ir::Module`]s built in memory, and then a [`Machine`]
+//! runs their functions:
+//!
+//! ```no_run
+//! use vm::{World, Machine, SearchPath};
+//!
+//! let mut search_path = SearchPath::default();
+//! search_path.push("modules");
+//! let world = World::new(search_path);
+//! world.load_module(&ir::Path::from("example"), &ir::VersionReq::STAR)?;
+//! let mut machine = Machine::new(&world);
+//! let sum: u64 = machine.call(&ir::Path::from("example::add"), vec![1u64.into(), 2u64.into()])?
+//!     .try_into()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+pub mod world;
+pub mod machine;
+pub mod bytecode;
+pub mod codegen;
+pub mod jit;
+pub mod error;
+pub mod resolve;
+pub mod lockfile;
+pub mod search;
+pub mod deps;
+pub mod value;
+pub mod memory;
+pub mod native;
+pub mod stdlib;
+
+pub use world::{World, Function};
+pub use machine::{Machine, Status};
+pub use error::{VmError, ErrorKind};
+pub use search::SearchPath;
+pub use value::Value;
+pub use native::{NativeModule, NativeFunction, NativeContext};
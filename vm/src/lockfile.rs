@@ -0,0 +1,81 @@
+//! Lockfiles record the exact version and content hash of every module that was loaded from the
+//! module search path, so that a later run can be restricted to loading exactly the same modules.
+//!
+//! The format is plain text with one module per line, giving its path, version and the hash of the
+//! file it was loaded from, separated by spaces. Blank lines and lines starting with `#` are ignored.
+use std::{fmt::Display, str::FromStr};
+use anyhow::{anyhow, Context, Error, Result};
+use itertools::Itertools;
+
+/// A single module recorded in a lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEntry {
+    pub path: ir::Path,
+    pub version: ir::Version,
+    /// Hash of the module or package file that the module was loaded from
+    pub hash: ir::ContentHash
+}
+
+/// A set of modules that were resolved and loaded together
+#[derive(Debug, Clone, Default)]
+pub struct Lockfile {
+    pub entries: Vec<LockEntry>
+}
+
+impl Lockfile {
+    /// Read a lockfile from the filesystem
+    pub fn read(path: impl AsRef<std::path::Path>) -> Result<Lockfile> {
+        std::fs::read_to_string(path.as_ref())
+            .with_context(|| format!("reading lockfile {}", path.as_ref().display()))?
+            .parse()
+    }
+
+    /// Write this lockfile to the filesystem, replacing any existing file
+    pub fn write(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        std::fs::write(path.as_ref(), self.to_string())
+            .with_context(|| format!("writing lockfile {}", path.as_ref().display()))
+    }
+
+    /// Find the entry for a specific version of a module
+    pub fn get(&self, path: &ir::Path, version: &ir::Version) -> Option<&LockEntry> {
+        self.entries.iter().find(|e| e.path == *path && e.version == *version)
+    }
+
+    /// True if the lockfile has an entry for any version of a module
+    pub fn contains(&self, path: &ir::Path) -> bool {
+        self.entries.iter().any(|e| e.path == *path)
+    }
+}
+
+impl FromStr for Lockfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (ix, line) in s.lines().enumerate().map(|(ix, l)| (ix, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (path, version, hash) = line.split_whitespace().collect_tuple()
+                .ok_or_else(|| anyhow!("line {} of lockfile should have a module path, version and hash", ix+1))?;
+            entries.push(LockEntry {
+                path: ir::Path::from(path),
+                version: ir::Version::parse(version).with_context(|| format!("line {} of lockfile", ix+1))?,
+                hash: hash.parse().with_context(|| format!("line {} of lockfile", ix+1))?
+            });
+        }
+        Ok(Lockfile { entries })
+    }
+}
+
+impl Display for Lockfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# modules loaded by the OXLR vm: path, version, content hash")?;
+        let mut entries: Vec<&LockEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.path.to_string().cmp(&b.path.to_string()).then(a.version.cmp(&b.version)));
+        for e in entries {
+            writeln!(f, "{} {} {}", e.path, e.version, e.hash)?;
+        }
+        Ok(())
+    }
+}
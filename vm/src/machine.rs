@@ -0,0 +1,1032 @@
+//! The interpreter, which runs function bodies after lowering them to [`bytecode`](crate::bytecode)
+//! the first time they are called. Calls between OXLR functions do not recurse on the host stack:
+//! each call pushes a [`Frame`] onto the stack in [`Memory`] that records where execution is in the
+//! function, so execution can be suspended after any instruction, inspected and resumed.
+//!
+//! Functions that are called often or loop many times are compiled to native code by the
+//! [`jit`](crate::jit) when a call to them starts, as long as they only use what compiled code
+//! supports. Compiled code still has a frame on the stack while it runs, and makes calls to other
+//! functions through the machine, so a compiled function can call interpreted ones and the other
+//! way around.
+use std::{collections::HashMap, rc::Rc};
+use anyhow::{anyhow, bail, Result};
+use crate::world::{World, Function};
+use crate::value::*;
+use crate::memory::{self, Memory, Frame, Position, ReturnTo};
+use crate::bytecode::{Code, Field, FieldLayout, FnIndex, Op, Operand, Reg};
+use crate::jit::{Compiled, Helpers, Jit, SiteKind};
+use crate::native::{NativeContext, NativeFunction, string_type};
+use crate::error::{VmError, ErrorKind, StackFrame};
+use ir::Trap;
+
+/// the default maximum number of OXLR function calls that can be running at once
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 100_000;
+
+/// the default number of calls and loop iterations after which a function is compiled
+pub const DEFAULT_JIT_THRESHOLD: u32 = 1000;
+
+/// the maximum number of compiled functions that can be running at once, since each one runs on
+/// the host stack. Calls past this are interpreted
+const MAX_COMPILED_DEPTH: usize = 64;
+
+unsafe fn memcpy(src: *const u8, dest: *mut u8, size: usize) {
+    let src_data = std::slice::from_raw_parts(src, size);
+    let dest_data = std::slice::from_raw_parts_mut(dest, size);
+    for (s, d) in src_data.iter().zip(dest_data.iter_mut()) {
+        *d = *s;
+    }
+}
+
+/// The state of a call made by the host after running some of it
+#[derive(Debug)]
+pub enum Status {
+    /// the call has not returned yet, and can be resumed
+    Suspended,
+    /// the call returned a value
+    Finished(Value)
+}
+
+/// why the function body in the top frame stopped running
+enum Event {
+    /// the instruction budget ran out
+    Suspend,
+    /// call a function with a new frame
+    Call(FnIndex, Rc<Code>, Vec<Value>, ReturnTo),
+    /// call a function in place of the current one, returning its result
+    TailCall(FnIndex, Target, Vec<Value>),
+    Return(Value)
+}
+
+/// how to run a function
+#[derive(Clone)]
+enum Target {
+    Native(Rc<NativeFunction>),
+    Code(Rc<Code>)
+}
+
+/// whether a function has been compiled to native code
+enum Tier {
+    Interpreted,
+    Compiled(Rc<Compiled>),
+    /// the function uses something that compiled code does not support
+    Failed
+}
+
+/// a function that lowered code can call
+struct Callee {
+    path: ir::Path,
+    /// how to run the function, once it has been looked up
+    target: Option<Target>,
+    /// the number of times the function has been called or looped, for deciding when to compile it
+    heat: u32,
+    tier: Tier
+}
+
+/// the functions that lowered code calls, by index
+#[derive(Default)]
+struct FunctionTable {
+    callees: Vec<Callee>,
+    indices: HashMap<ir::Path, FnIndex>,
+    /// the generation of the world that the functions were looked up in
+    generation: u64
+}
+
+impl FunctionTable {
+    /// get the index of a function by path, adding it to the table if it is not there yet
+    fn intern(&mut self, path: &ir::Path) -> FnIndex {
+        if let Some(ix) = self.indices.get(path) {
+            return *ix;
+        }
+        let ix = self.callees.len();
+        self.callees.push(Callee { path: path.clone(), target: None, heat: 0, tier: Tier::Interpreted });
+        self.indices.insert(path.clone(), ix);
+        ix
+    }
+
+    fn path(&self, ix: FnIndex) -> &ir::Path {
+        &self.callees[ix].path
+    }
+
+    /// forget how to run every function if the modules in the world have changed since they were
+    /// looked up, since paths may now refer to different functions
+    fn check_generation(&mut self, world: &World) {
+        if self.generation != world.generation() {
+            for c in self.callees.iter_mut() {
+                c.target = None;
+                c.heat = 0;
+                c.tier = Tier::Interpreted;
+            }
+            self.generation = world.generation();
+        }
+    }
+
+    /// record how to run a function that has been looked up, lowering its body if it has one
+    fn define(&mut self, ix: FnIndex, f: &Function) -> Target {
+        let target = match f {
+            Function::Native { f, .. } => Target::Native(f.clone()),
+            Function::Ir { .. } => {
+                let body = f.body().expect("IR functions have bodies");
+                Target::Code(Rc::new(Code::lower(body, &mut |p| self.intern(p))))
+            }
+        };
+        self.callees[ix].target = Some(target.clone());
+        target
+    }
+}
+
+/// An interpreter that runs functions from the modules loaded in a [`World`]
+pub struct Machine<'w> {
+    world: &'w World,
+    mem: Memory<'w>,
+    functions: FunctionTable,
+    /// the value of the exception being thrown, until it is handled
+    exception: Option<Value>,
+    /// the maximum number of OXLR function calls that can be running at once. Calls past the
+    /// limit trap with a stack overflow
+    pub max_call_depth: usize,
+    /// the number of calls and loop iterations after which a function is compiled to native code,
+    /// or `None` to only interpret
+    pub jit_threshold: Option<u32>,
+    /// the compiler, made when the first function is compiled
+    jit: Option<Jit>,
+    /// the compiled functions that are running, innermost last
+    running: Vec<Rc<Compiled>>,
+    /// the error from a call or allocation that compiled code made through the machine
+    jit_error: Option<VmError>,
+    /// whether the top frame was just pushed and has not run yet
+    entering: bool
+}
+
+impl<'w> Machine<'w> {
+    pub fn new(world: &'w World) -> Machine<'w> {
+        Machine {
+            mem: Memory::new(world), world,
+            functions: FunctionTable::default(),
+            exception: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            jit_threshold: Some(DEFAULT_JIT_THRESHOLD),
+            jit: None,
+            running: Vec::new(),
+            jit_error: None,
+            entering: false
+        }
+    }
+
+    /// the world that functions are looked up in
+    pub fn world(&self) -> &'w World {
+        self.world
+    }
+
+    /// take the value thrown by an exception that no function handled, after a call failed with
+    /// [`ErrorKind::Exception`]
+    pub fn take_exception(&mut self) -> Option<Value> {
+        self.exception.take()
+    }
+
+    /// allocate a string on the heap, to pass to a function
+    pub fn alloc_string(&mut self, s: &str) -> Result<Value> {
+        self.mem.alloc_bytes(s.as_bytes())
+    }
+
+    /// allocate an array of strings on the heap
+    pub fn alloc_string_array(&mut self, strings: &[String]) -> Result<Value> {
+        let arr = self.mem.alloc_array(&string_type(), strings.len())?;
+        if let Value::Ref(r) = &arr {
+            for (i, s) in strings.iter().enumerate() {
+                let sv = self.alloc_string(s)?;
+                r.indexed(self.world, i)?.set_value(sv)?;
+            }
+        }
+        Ok(arr)
+    }
+
+    /// run a program by calling the `start` function in `module`. `start` either takes no
+    /// arguments or takes the program arguments as an array of strings, and returns either
+    /// nothing or an integer exit code
+    pub fn start(&mut self, module: &ir::Path, args: &[String]) -> Result<Value, VmError> {
+        let (f, args) = self.start_fn(module, args)?;
+        log::trace!("starting execution");
+        self.push_host_frame(&f, args)?;
+        self.finish()
+    }
+
+    /// find and check the start function, and make its arguments
+    fn start_fn(&mut self, module: &ir::Path, args: &[String]) -> Result<(Function, Vec<Value>)> {
+        let mut path = module.clone();
+        path.0.push(ir::Symbol("start".into()));
+        let f = self.world.get_function(&path)?
+            .ok_or_else(|| anyhow!("module {} has no start function", module))?;
+        let sig = f.signature();
+        let args_type = ir::Type::Array(Box::new(string_type()));
+        let takes_args = match sig.args.as_slice() {
+            [] => false,
+            [(ty, _)] if *ty == args_type => true,
+            _ => bail!("{} must take no arguments or a single array of strings ({:?}), but takes {:?}", path, args_type, sig.args)
+        };
+        if !matches!(sig.return_type, ir::Type::Unit | ir::Type::Int { .. }) {
+            bail!("{} must return nothing or an integer, but returns {:?}", path, sig.return_type);
+        }
+        let args = if takes_args { vec![self.alloc_string_array(args)?] } else { Vec::new() };
+        Ok((f, args))
+    }
+
+    /// call a function by path with arguments supplied by the host, returning its result. The
+    /// module containing the function is loaded if it is imported but has not been loaded yet
+    pub fn call(&mut self, path: &ir::Path, args: Vec<Value>) -> Result<Value, VmError> {
+        let f = self.host_lookup(path, &args)?;
+        log::trace!("calling {} from host", path);
+        match &f {
+            Function::Native { path, f } => self.call_native(path, f, args),
+            Function::Ir { .. } => {
+                self.push_host_frame(&f, args)?;
+                self.finish()
+            }
+        }
+    }
+
+    /// start a call to a function by path without running any of it. The call runs when it is
+    /// resumed with [`resume`](Machine::resume) or [`step`](Machine::step). Calls can be begun
+    /// while another is suspended, and must finish before the earlier call can be resumed
+    pub fn begin_call(&mut self, path: &ir::Path, args: Vec<Value>) -> Result<(), VmError> {
+        let f = self.host_lookup(path, &args)?;
+        if let Function::Native { .. } = f {
+            return Err(anyhow!("native function {} cannot be suspended, call it with Machine::call", path).into());
+        }
+        log::trace!("beginning call to {} from host", path);
+        self.push_host_frame(&f, args)
+    }
+
+    /// run the most recently begun call until it returns, or until `max_steps` instructions have
+    /// run if a limit is given
+    pub fn resume(&mut self, max_steps: Option<usize>) -> Result<Status, VmError> {
+        let mut budget = max_steps;
+        loop {
+            // compiled code runs a whole call at once, so it is only used when there is no budget
+            if std::mem::take(&mut self.entering) && budget.is_none() {
+                if let Some((compiled, args)) = self.compiled_entry() {
+                    if let Some(rv) = self.run_compiled(&compiled, args)? {
+                        return Ok(Status::Finished(rv));
+                    }
+                    continue;
+                }
+            }
+            let frame = self.mem.stack.last().ok_or_else(|| anyhow!("no call to resume"))?;
+            let code = frame.code.clone();
+            let mut pos = frame.pos;
+            let event = self.run(&code, &mut pos, &mut budget);
+            self.mem.cur_frame().pos = pos;
+            match event {
+                Result::Ok(Event::Suspend) => return Ok(Status::Suspended),
+                Result::Ok(Event::Call(ix, code, args, ret)) => {
+                    if let Err(e) = self.push_frame(ix, code, args, ret.clone()) {
+                        // the call never started, so the error is in the calling frame, and an
+                        // Invoke that made the call handles it like one thrown by the callee
+                        let caught = match ret {
+                            ReturnTo::Invoke { unwind, exception, .. } => self.catch(e, unwind, exception),
+                            _ => Err(e)
+                        };
+                        if let Err(e) = caught {
+                            self.unwind(e)?;
+                        }
+                    }
+                },
+                Result::Ok(Event::TailCall(ix, target, args)) => {
+                    if let Some(rv) = self.tail_call(ix, target, args)? {
+                        return Ok(Status::Finished(rv));
+                    }
+                },
+                Result::Ok(Event::Return(rv)) => {
+                    if let Some(rv) = self.return_value(rv)? {
+                        return Ok(Status::Finished(rv));
+                    }
+                },
+                Err(e) => self.unwind(e.into())?
+            }
+        }
+    }
+
+    /// run a single instruction of the most recently begun call
+    pub fn step(&mut self) -> Result<Status, VmError> {
+        self.resume(Some(1))
+    }
+
+    /// the function calls that are running, starting from the innermost, with the position of
+    /// the instruction that will run next in each
+    pub fn stack_trace(&self) -> Vec<StackFrame> {
+        self.mem.stack.iter().rev()
+            .map(|f| StackFrame { function: self.functions.path(f.function).clone(), location: Some((f.pos.block, f.pos.instr)) })
+            .collect()
+    }
+
+    /// run the most recently begun call until it returns
+    fn finish(&mut self) -> Result<Value, VmError> {
+        match self.resume(None)? {
+            Status::Finished(rv) => Ok(rv),
+            Status::Suspended => unreachable!("ran out of an unlimited budget")
+        }
+    }
+
+    /// find a function to call from the host and check the number of arguments
+    fn host_lookup(&self, path: &ir::Path, args: &[Value]) -> Result<Function, VmError> {
+        let f = self.world.get_function(path)?
+            .ok_or_else(|| ErrorKind::FunctionNotFound(path.clone()))?;
+        let expected = f.signature().args.len();
+        if args.len() != expected {
+            return Err(anyhow!("function {} takes {} arguments, but {} were given", path, expected, args.len()).into());
+        }
+        Ok(f)
+    }
+
+    /// push a frame for a call from the host to a function with a body
+    fn push_host_frame(&mut self, f: &Function, args: Vec<Value>) -> Result<(), VmError> {
+        self.functions.check_generation(self.world);
+        let ix = self.functions.intern(&f.path());
+        let target = match self.functions.callees[ix].target.clone() {
+            Some(target) => target,
+            None => self.functions.define(ix, f)
+        };
+        match target {
+            Target::Code(code) => self.push_frame(ix, code, args, ReturnTo::Host),
+            Target::Native(_) => unreachable!("native functions do not have frames")
+        }
+    }
+
+    /// push a frame to call a function with a body
+    fn push_frame(&mut self, ix: FnIndex, code: Rc<Code>, args: Vec<Value>, ret: ReturnTo) -> Result<(), VmError> {
+        if self.mem.stack.len() >= self.max_call_depth {
+            return Err(ErrorKind::trap(Trap::StackOverflow,
+                format!("call stack overflow calling {}, the maximum depth is {}", self.functions.path(ix), self.max_call_depth)).into());
+        }
+        self.check_arg_count(ix, &code, &args)?;
+        let mut frame = Frame::new(ix, code, ret);
+        frame.registers.splice(..args.len(), args);
+        self.mem.stack.push(frame);
+        self.warm(ix);
+        Ok(())
+    }
+
+    fn check_arg_count(&self, ix: FnIndex, code: &Code, args: &[Value]) -> Result<(), VmError> {
+        if args.len() > code.max_registers as usize {
+            return Err(ErrorKind::trap(Trap::InvalidCode,
+                format!("{} takes {} arguments but only has {} registers", self.functions.path(ix), args.len(), code.max_registers)).into());
+        }
+        Ok(())
+    }
+
+    /// replace the top frame with a call to another function, which returns to the same place.
+    /// Returns the value if the function returned to the host
+    fn tail_call(&mut self, ix: FnIndex, target: Target, args: Vec<Value>) -> Result<Option<Value>, VmError> {
+        match target {
+            Target::Native(f) => {
+                let path = self.functions.path(ix).clone();
+                match self.call_native(&path, &f, args) {
+                    Result::Ok(rv) => self.return_value(rv),
+                    Err(e) => self.unwind(e).map(|_| None)
+                }
+            },
+            Target::Code(code) => {
+                if let Err(e) = self.check_arg_count(ix, &code, &args) {
+                    return self.unwind(e).map(|_| None);
+                }
+                // release the frame and stack allocations of the function that made the tail call
+                let ret = self.mem.cur_frame().ret.clone();
+                self.mem.pop_stack();
+                let mut frame = Frame::new(ix, code, ret);
+                frame.registers.splice(..args.len(), args);
+                self.mem.stack.push(frame);
+                self.warm(ix);
+                Ok(None)
+            }
+        }
+    }
+
+    /// pop the top frame and give the value it returned to whatever called it. Returns the value
+    /// if it was called by the host
+    fn return_value(&mut self, rv: Value) -> Result<Option<Value>, VmError> {
+        let ret = self.mem.cur_frame().ret.clone();
+        self.mem.pop_stack();
+        let stored = match ret {
+            ReturnTo::Host => return Ok(Some(rv)),
+            ReturnTo::Register(dest) => self.mem.cur_frame().store(dest, rv)
+                .map(|_| self.mem.cur_frame().pos.instr += 1),
+            ReturnTo::Invoke { dest, normal, .. } => self.mem.cur_frame().store(dest, rv)
+                .and_then(|_| self.jump(normal))
+        };
+        if let Err(e) = stored {
+            self.unwind(e.into())?;
+        }
+        Ok(None)
+    }
+
+    /// continue the top frame at the start of a block
+    fn jump(&mut self, block: ir::code::BlockIndex) -> Result<()> {
+        let code = self.mem.cur_frame().code.clone();
+        let mut pos = self.mem.cur_frame().pos;
+        self.enter_block(&code, &mut pos, block)?;
+        self.mem.cur_frame().pos = pos;
+        Ok(())
+    }
+
+    /// move from the block at `pos` to the start of block `to` in the top frame, running the phis
+    /// at the start of `to` as parallel copies
+    fn enter_block(&mut self, code: &Code, pos: &mut Position, to: ir::code::BlockIndex) -> Result<()> {
+        // a jump to a block that does not exist traps when the block is run
+        let phis = match code.blocks.get(to) {
+            Some(block) if block.phis > 0 => {
+                let edge = block.edges.iter().find(|e| e.from == pos.block).ok_or_else(|| ErrorKind::trap(Trap::InvalidCode,
+                    format!("phis in block {} have no value for predecessor block {}", to, pos.block)))?;
+                let values = edge.copies.iter().map(|(_, v)| self.mem.operand(v)).collect::<Result<Vec<_>>>()?;
+                let frame = self.mem.cur_frame();
+                for ((dest, _), v) in edge.copies.iter().zip(values) {
+                    frame.store(*dest, v)?;
+                }
+                block.phis
+            },
+            _ => 0
+        };
+        // jumping backwards is a loop
+        if to <= pos.block && self.jit_threshold.is_some() {
+            let ix = self.mem.cur_frame().function;
+            let callee = &mut self.functions.callees[ix];
+            callee.heat = callee.heat.saturating_add(1);
+        }
+        pos.block = to;
+        pos.instr = phis;
+        Ok(())
+    }
+
+    /// handle an error from the instruction running in the top frame by popping frames until one
+    /// that called with `Invoke` can handle it, and continuing there. If the error reaches a call
+    /// made by the host, it is returned with the functions it passed through
+    fn unwind(&mut self, mut e: VmError) -> Result<(), VmError> {
+        loop {
+            let frame = self.mem.stack.last().expect("error in a running function");
+            e = e.in_frame(self.functions.path(frame.function).clone(), frame.pos.block, frame.pos.instr);
+            let ret = frame.ret.clone();
+            self.mem.pop_stack();
+            match ret {
+                ReturnTo::Host => return Err(e),
+                ReturnTo::Register(_) => continue,
+                ReturnTo::Invoke { unwind, exception, .. } => match self.catch(e, unwind, exception) {
+                    Result::Ok(()) => return Ok(()),
+                    // the error cannot be handled, or the invoking frame failed to handle it, so
+                    // continue with that error
+                    Err(ce) => e = ce
+                }
+            }
+        }
+    }
+
+    /// continue the top frame in the unwind block of an `Invoke`, with the value for an error in
+    /// the exception register. Gives back the error if it cannot be handled, or the error from
+    /// handling it
+    fn catch(&mut self, e: VmError, unwind: ir::code::BlockIndex, exception: Reg) -> Result<(), VmError> {
+        let Some(v) = self.thrown_value(&e) else {
+            return Err(e);
+        };
+        Ok(self.mem.cur_frame().store(exception, v).and_then(|_| self.jump(unwind))?)
+    }
+
+    /// the value to give to a handler for an error: the value thrown by an exception, or the code
+    /// of a trap. Other errors cannot be handled
+    fn thrown_value(&mut self, e: &VmError) -> Option<Value> {
+        match e.kind {
+            ErrorKind::Exception(_) => self.exception.take(),
+            _ => e.trap().map(|trap| {
+                log::trace!("handling trap {:?}: {}", trap, e);
+                Value::Int(Integer::unsigned(32, trap.code() as u64))
+            })
+        }
+    }
+
+    fn call_native(&mut self, path: &ir::Path, f: &NativeFunction, args: Vec<Value>) -> Result<Value, VmError> {
+        let mut cx = NativeContext { world: self.world, mem: &mut self.mem };
+        f.call(path, &mut cx, args).map_err(|e| VmError::native(path, e))
+    }
+
+    /// count a call to a function that has just been pushed, for deciding when to compile it
+    fn warm(&mut self, ix: FnIndex) {
+        self.entering = true;
+        if self.jit_threshold.is_some() {
+            let callee = &mut self.functions.callees[ix];
+            callee.heat = callee.heat.saturating_add(1);
+        }
+    }
+
+    /// the compiled code for the function in the top frame and its arguments, if it is hot enough
+    /// to compile and the arguments have the types the code was compiled for
+    fn compiled_entry(&mut self) -> Option<(Rc<Compiled>, Vec<u64>)> {
+        let ix = self.mem.stack.last()?.function;
+        let compiled = self.compiled(ix)?;
+        let frame = self.mem.stack.last()?;
+        let mut args = compiled.params.iter().zip(&frame.registers)
+            .map(|(ty, v)| ty.raw(v))
+            .collect::<Option<Vec<_>>>()?;
+        // the value returned is put in the first argument
+        args.resize(args.len().max(1), 0);
+        Some((compiled, args))
+    }
+
+    /// get the compiled code for a function, compiling it if it has become hot enough
+    fn compiled(&mut self, ix: FnIndex) -> Option<Rc<Compiled>> {
+        let threshold = self.jit_threshold?;
+        if self.running.len() >= MAX_COMPILED_DEPTH {
+            return None;
+        }
+        let callee = &self.functions.callees[ix];
+        match &callee.tier {
+            Tier::Compiled(c) => return Some(c.clone()),
+            Tier::Failed => return None,
+            Tier::Interpreted if callee.heat < threshold => return None,
+            Tier::Interpreted => {}
+        }
+        if self.jit.is_none() {
+            match Jit::new() {
+                Result::Ok(jit) => self.jit = Some(jit),
+                Err(e) => {
+                    log::warn!("cannot compile to native code, only interpreting: {}", e);
+                    self.jit_threshold = None;
+                    return None;
+                }
+            }
+        }
+        let path = self.functions.path(ix).clone();
+        let compiled = self.lookup(&path).and_then(|f| {
+            let body = f.body().ok_or_else(|| anyhow!("native functions are not compiled"))?;
+            let helpers = Helpers { call: jit_call as *const () as usize, alloc: jit_alloc as *const () as usize };
+            let jit = self.jit.as_mut().expect("compiler was made");
+            let functions = &mut self.functions;
+            jit.compile(self.world, f.signature(), body, &mut |p| functions.intern(p), helpers)
+        });
+        let tier = match compiled {
+            Result::Ok(c) => {
+                log::debug!("compiled {} to native code", path);
+                Tier::Compiled(Rc::new(c))
+            },
+            Err(e) => {
+                log::debug!("not compiling {}: {}", path, e);
+                Tier::Failed
+            }
+        };
+        let callee = &mut self.functions.callees[ix];
+        callee.tier = tier;
+        match &callee.tier {
+            Tier::Compiled(c) => Some(c.clone()),
+            _ => None
+        }
+    }
+
+    /// run the function in the top frame with compiled code, then return from it or unwind it
+    /// like the interpreter would. Returns the value if it was called by the host
+    fn run_compiled(&mut self, compiled: &Rc<Compiled>, mut args: Vec<u64>) -> Result<Option<Value>, VmError> {
+        self.running.push(compiled.clone());
+        let machine = self as *mut Machine as *mut u8;
+        // SAFETY: the helpers are given this machine
+        let status = unsafe { compiled.run(machine, &mut args) };
+        self.running.pop();
+        if status == 0 {
+            return self.return_value(compiled.ret.value(args[0]));
+        }
+        let site = &compiled.sites[status as usize - 1];
+        let (block, instr) = site.location;
+        self.mem.cur_frame().pos = Position { block, instr };
+        let e = match &site.kind {
+            SiteKind::Trap(trap, message) => ErrorKind::trap(*trap, *message).into(),
+            _ => self.jit_error.take().expect("a failed call or allocation leaves an error")
+        };
+        self.unwind(e)?;
+        Ok(None)
+    }
+
+    /// make the call at a site in the innermost compiled function, with the arguments in `args`,
+    /// and put the value it returns in `args[0]`
+    fn call_from_compiled(&mut self, site: u32, args: *mut u64) -> Result<(), VmError> {
+        let compiled = self.running.last().expect("called from compiled code").clone();
+        let SiteKind::Call { callee, args: tys, ret } = &compiled.sites[site as usize].kind else {
+            unreachable!("site {} is not a call", site)
+        };
+        // SAFETY: compiled code passes as many arguments as the site has types
+        let params = tys.iter().enumerate().map(|(n, ty)| ty.value(unsafe { *args.add(n) })).collect();
+        log::trace!("calling {} from compiled code", self.functions.path(*callee));
+        let rv = match self.resolve(*callee)? {
+            Target::Native(f) => {
+                let path = self.functions.path(*callee).clone();
+                self.call_native(&path, &f, params)?
+            },
+            Target::Code(code) => {
+                self.push_frame(*callee, code, params, ReturnTo::Host)?;
+                self.finish()?
+            }
+        };
+        let raw = ret.raw(&rv).ok_or_else(|| ErrorKind::mismatch(&format!("{:?}", ret), &rv))?;
+        unsafe { *args = raw; }
+        Ok(())
+    }
+
+    /// make the allocation at a site in the innermost compiled function
+    fn alloc_from_compiled(&mut self, site: u32, count: u64) -> Result<Value> {
+        let compiled = self.running.last().expect("called from compiled code").clone();
+        match &compiled.sites[site as usize].kind {
+            SiteKind::Alloc(ty) => self.mem.alloc(ty),
+            SiteKind::AllocArray(el) => self.mem.alloc_array(el, count as usize),
+            k => unreachable!("site {:?} is not an allocation", k)
+        }
+    }
+
+    /// find a function to call by path
+    fn lookup(&self, fn_path: &ir::Path) -> Result<Function> {
+        Ok(self.world.get_function(fn_path)?
+            .ok_or_else(|| ErrorKind::FunctionNotFound(fn_path.clone()))?)
+    }
+
+    /// get how to run a function in the table, looking it up and lowering its body the first time
+    fn resolve(&mut self, ix: FnIndex) -> Result<Target> {
+        self.functions.check_generation(self.world);
+        if let Some(target) = &self.functions.callees[ix].target {
+            return Ok(target.clone());
+        }
+        let f = self.lookup(self.functions.path(ix))?;
+        Ok(self.functions.define(ix, &f))
+    }
+
+    /// find the implementation of an interface function for the type of the first argument
+    fn lookup_impl(&mut self, fn_path: &ir::Path, params: &[Value]) -> Result<(FnIndex, Target)> {
+        let self_val = params.first().ok_or_else(|| ErrorKind::trap(Trap::InvalidCode, "call impl requires at least one parameter"))?;
+        let ty = self_val.type_of(&self.mem);
+        let f = self.world.find_impl(fn_path, &ty)?
+            .ok_or_else(|| ErrorKind::ImplementationNotFound { function: fn_path.clone(), ty })?;
+        let ix = self.functions.intern(&f.path());
+        Ok((ix, self.resolve(ix)?))
+    }
+
+    /// get the value of an operand used as an index
+    fn index(&mut self, op: &Operand) -> Result<usize> {
+        match self.mem.operand(op)? {
+            Value::Int(Integer { signed: false, data, .. }) => Ok(data as usize),
+            v => bail!(ErrorKind::mismatch("unsigned integer index", &v))
+        }
+    }
+
+    fn operands(&mut self, ops: &[Operand]) -> Result<Vec<Value>> {
+        ops.iter().map(|p| self.mem.operand(p)).collect()
+    }
+
+    /// make a call from the top frame. Native functions are called immediately, and the value
+    /// they return is put in `dest`
+    fn make_call(&mut self, ix: FnIndex, target: Target, params: Vec<Value>, dest: Reg) -> Result<Option<Event>> {
+        match target {
+            Target::Native(f) => {
+                let path = self.functions.path(ix).clone();
+                let rv = self.call_native(&path, &f, params)?;
+                self.mem.cur_frame().store(dest, rv)?;
+                Ok(None)
+            },
+            Target::Code(code) => Ok(Some(Event::Call(ix, code, params, ReturnTo::Register(dest))))
+        }
+    }
+
+    /// interpret the function body in the top frame from `pos` until it makes a call, returns,
+    /// or runs out of the instruction budget, keeping `pos` at the instruction that is running
+    fn run(&mut self, code: &Code, pos: &mut Position, budget: &mut Option<usize>) -> Result<Event> {
+        'blocks: loop {
+            let cur_block = code.blocks.get(pos.block).ok_or_else(|| ErrorKind::trap(Trap::InvalidCode,
+                format!("jump to block {} in a function with {} blocks", pos.block, code.blocks.len())))?;
+            while let Some(op) = cur_block.ops.get(pos.instr) {
+                match budget {
+                    Some(0) => return Ok(Event::Suspend),
+                    Some(n) => *n -= 1,
+                    None => {}
+                }
+                match op {
+                    // phis run when their block is entered, and execution starts after them
+                    Op::Phi => {},
+                    Op::Br { cond, if_true, if_false } => {
+                        // ostensibly this is the last instruction in the block
+                        match self.mem.operand(cond)? {
+                            Value::Bool(b) => {
+                                self.enter_block(code, pos, if b { *if_true } else { *if_false })?;
+                                continue 'blocks;
+                            },
+                            v => bail!(ErrorKind::mismatch("bool", &v))
+                        }
+                    },
+
+                    Op::BinaryOp(op, dest, lhs, rhs) => {
+                        use ir::code::BinOp;
+                        let lhs = self.mem.operand(lhs)?;
+                        let rhs = self.mem.operand(rhs)?;
+                        if let (Value::Int(a), Value::Int(b)) = (&lhs, &rhs) {
+                            check_int(a)?;
+                            if a.signed != b.signed || a.width != b.width {
+                                bail!(ErrorKind::trap(Trap::InvalidCode,
+                                    format!("integer operands of different types {:?} {:?} {:?}", a, op, b)));
+                            }
+                        }
+                        let res = match (op, lhs, rhs) {
+                            (BinOp::Add, Value::Int(a), Value::Int(b)) =>
+                                Value::Int(a.checked_add(b).ok_or_else(|| overflow(op, a, b))?),
+                            (BinOp::Sub, Value::Int(a), Value::Int(b)) =>
+                                Value::Int(a.checked_sub(b).ok_or_else(|| overflow(op, a, b))?),
+                            (BinOp::Mul, Value::Int(a), Value::Int(b)) =>
+                                Value::Int(a.checked_mul(b).ok_or_else(|| overflow(op, a, b))?),
+                            (BinOp::Div, Value::Int(_), Value::Int(b)) if b.data == 0 =>
+                                bail!(ErrorKind::trap(Trap::DivisionByZero, "integer division by zero")),
+                            (BinOp::Div, Value::Int(a), Value::Int(b)) =>
+                                Value::Int(a.checked_div(b).ok_or_else(|| overflow(op, a, b))?),
+                            (BinOp::Eq,  a, b) => Value::Bool(a == b),
+                            (BinOp::NEq,  a, b) => Value::Bool(a != b),
+                            //TODO: implement the rest of the binary operators. for most of these,
+                            //the operation also needs to be added to the corrosponding value as
+                            //well (Integer/Float).
+                            (op, lhs, rhs) => bail!(ErrorKind::trap(Trap::Unsupported,
+                                format!("unimplemented binary operator {:?} ({:?}) {:?}", lhs, op, rhs)))
+                        };
+                        self.mem.cur_frame().store(*dest, res)?;
+                    },
+                    Op::UnaryOp(op, dest, inp) => {
+                        use ir::code::UnaryOp;
+                        let inp = self.mem.operand(inp)?;
+                        if let Value::Int(v) = &inp {
+                            check_int(v)?;
+                        }
+                        let res = match (op, inp) {
+                            (UnaryOp::LogNot, Value::Bool(v)) => Value::Bool(!v),
+                            (UnaryOp::BitNot, Value::Int(v)) => Value::Int(v.bitwise_negate()),
+                            (UnaryOp::Neg,    Value::Int(v)) if v.signed => Value::Int(v.checked_negate()
+                                .ok_or_else(|| ErrorKind::trap(Trap::Overflow, format!("integer overflow in Neg {:?}", v)))?),
+                            (op, v) => bail!(ErrorKind::mismatch(&format!("operand to {:?}", op), &v))
+                        };
+                        self.mem.cur_frame().store(*dest, res)?;
+                    },
+
+                    Op::LoadImm(dest, v) => {
+                        let v = self.mem.operand(v)?;
+                        self.mem.cur_frame().store(*dest, v)?
+                    },
+                    Op::LoadRef(dest, r#ref) => {
+                        let v = match self.mem.cur_frame().get(*r#ref)? {
+                            Value::Ref(r) => r.value()?,
+                            v => bail!(ErrorKind::mismatch("ref", v))
+                        };
+                        self.mem.cur_frame().store(*dest, v)?
+                    },
+                    Op::StoreRef(dest, src) => {
+                        let val = self.mem.operand(src)?;
+                        match self.mem.cur_frame().get(*dest)? {
+                            Value::Ref(r) => r.set_value(val)?,
+                            v => bail!(ErrorKind::mismatch("ref", v))
+                        }
+                    },
+
+                    Op::RefField(dest, src_ref, field) => {
+                        let r = match self.mem.cur_frame().get(*src_ref)? {
+                            Value::Ref(r) => field_ref(self.world, r, field)?,
+                            v => bail!(ErrorKind::mismatch("ref", v))
+                        };
+                        self.mem.cur_frame().store(*dest, Value::Ref(r))?
+                    }
+                    Op::LoadField(dest, r#ref, field) => {
+                        let v = match self.mem.cur_frame().get(*r#ref)? {
+                            Value::Ref(r) => field_ref(self.world, r, field)?.value()?,
+                            v => bail!(ErrorKind::mismatch("ref", v))
+                        };
+                        self.mem.cur_frame().store(*dest, v)?
+                    },
+                    Op::StoreField(src, r#ref, field) => {
+                        let val = self.mem.operand(src)?;
+                        match self.mem.cur_frame().get(*r#ref)? {
+                            Value::Ref(r) => field_ref(self.world, r, field)?.set_value(val)?,
+                            v => bail!(ErrorKind::mismatch("ref", v))
+                        }
+                    },
+
+                    Op::RefIndex(dest, src_ref, index) => {
+                        let index = self.index(index)?;
+                        let r = match self.mem.cur_frame().get(*src_ref)? {
+                            Value::Ref(r) => r.indexed(self.world, index)?,
+                            v => bail!(ErrorKind::mismatch("ref or array", v))
+                        };
+                        self.mem.cur_frame().store(*dest, Value::Ref(r))?
+                    },
+                    Op::LoadIndex(dest, r#ref, index) => {
+                        let index = self.index(index)?;
+                        let v = match self.mem.cur_frame().get(*r#ref)? {
+                            Value::Ref(r) => r.indexed(self.world, index)?.value()?,
+                            v => bail!(ErrorKind::mismatch("ref or array", v))
+                        };
+                        self.mem.cur_frame().store(*dest, v)?
+                    },
+                    Op::StoreIndex(r#ref, index, src) => {
+                        let index = self.index(index)?;
+                        let val = self.mem.operand(src)?;
+                        match self.mem.cur_frame().get(*r#ref)? {
+                            Value::Ref(r) => r.indexed(self.world, index)?.set_value(val)?,
+                            v => bail!(ErrorKind::mismatch("ref or array", v))
+                        }
+                    }
+
+                    Op::Call(dest, ix, params) => {
+                        log::trace!("calling {}", self.functions.path(*ix));
+                        // TODO: Check types to make sure call is valid!
+                        let target = self.resolve(*ix)?;
+                        let params = self.operands(params)?;
+                        if let Some(event) = self.make_call(*ix, target, params, *dest)? {
+                            return Ok(event);
+                        }
+                    },
+                    Op::CallImpl(dest, fn_path, params) => {
+                        log::trace!("calling {}", fn_path);
+                        // TODO: Check types to make sure call is valid!
+                        let params = self.operands(params)?;
+                        let (ix, target) = self.lookup_impl(fn_path, &params)?;
+                        if let Some(event) = self.make_call(ix, target, params, *dest)? {
+                            return Ok(event);
+                        }
+                    },
+                    Op::Return(v) => {
+                        log::trace!("return");
+                        let rv = self.mem.operand(v)?;
+                        return Ok(Event::Return(rv))
+                    },
+                    Op::TailCall(ix, params) => {
+                        log::trace!("tail calling {}", self.functions.path(*ix));
+                        let target = self.resolve(*ix)?;
+                        let params = self.operands(params)?;
+                        return Ok(Event::TailCall(*ix, target, params))
+                    },
+                    Op::TailCallImpl(fn_path, params) => {
+                        log::trace!("tail calling {}", fn_path);
+                        let params = self.operands(params)?;
+                        let (ix, target) = self.lookup_impl(fn_path, &params)?;
+                        return Ok(Event::TailCall(ix, target, params))
+                    },
+                    Op::Invoke { dest, func, args, normal, unwind, exception } => {
+                        log::trace!("invoking {}", self.functions.path(*func));
+                        let target = self.resolve(*func)?;
+                        let params = self.operands(args)?;
+                        let nf = match target {
+                            Target::Native(nf) => nf,
+                            Target::Code(code) => {
+                                let ret = ReturnTo::Invoke { dest: *dest, normal: *normal, unwind: *unwind, exception: *exception };
+                                return Ok(Event::Call(*func, code, params, ret))
+                            }
+                        };
+                        let path = self.functions.path(*func).clone();
+                        // native functions run immediately, so errors from them are handled here
+                        let next = match self.call_native(&path, &nf, params) {
+                            Result::Ok(rv) => {
+                                self.mem.cur_frame().store(*dest, rv)?;
+                                *normal
+                            },
+                            Err(e) => match self.thrown_value(&e) {
+                                Some(v) => {
+                                    self.mem.cur_frame().store(*exception, v)?;
+                                    *unwind
+                                },
+                                None => return Err(e.into())
+                            }
+                        };
+                        self.enter_block(code, pos, next)?;
+                        continue 'blocks;
+                    },
+                    Op::Throw(v) => {
+                        let v = self.mem.operand(v)?;
+                        log::trace!("throw {:?}", v);
+                        let desc = format!("{:?}", v);
+                        self.exception = Some(v);
+                        bail!(ErrorKind::Exception(desc))
+                    },
+                    Op::Unsupported(instr) =>
+                        bail!(ErrorKind::trap(Trap::Unsupported, format!("unimplemented instruction {:?}", instr))),
+                    Op::Invalid(msg) => bail!(ErrorKind::trap(Trap::InvalidCode, msg.clone())),
+                    Op::Alloc(dest, r#type) => {
+                        let nrf = self.mem.alloc(r#type)?;
+                        self.mem.cur_frame().store(*dest, nrf)?;
+                    },
+                    Op::AllocArray(dest, r#type, count) => {
+                        let count = match self.mem.operand(count)? {
+                            Value::Int(Integer { signed: false, data, .. }) => data as usize,
+                            v => bail!(ErrorKind::mismatch("unsigned integer count", &v))
+                        };
+                        let nrf = self.mem.alloc_array(r#type, count)?;
+                        self.mem.cur_frame().store(*dest, nrf)?;
+                    },
+                    Op::StackAlloc(dest, r#type) => {
+                        let nrf = self.mem.stack_alloc(r#type)?;
+                        self.mem.cur_frame().store(*dest, nrf)?;
+                    },
+                    Op::StackAllocArray(dest, r#type, count) => {
+                        let count = match self.mem.operand(count)? {
+                            Value::Int(Integer { signed: false, data, .. }) => data as usize,
+                            v => bail!(ErrorKind::mismatch("unsigned integer count", &v))
+                        };
+                        let nrf = self.mem.stack_alloc_array(r#type, count)?;
+                        self.mem.cur_frame().store(*dest, nrf)?;
+                    },
+
+                    Op::CopyToStack(dest, src) => {
+                        match self.mem.cur_frame().load(*src)? {
+                            Value::Ref(memory::Ref { ty, data }) => {
+                                let (copy, size) = if let ir::Type::Array(el_ty) = ty.as_ref() {
+                                    let count = unsafe { *(data as *mut usize) };
+                                    (self.mem.stack_alloc_array(el_ty.as_ref(), count)?,
+                                        self.world.array_size(el_ty, count)?)
+                                } else {
+                                    (self.mem.stack_alloc(ty.as_ref())?,
+                                        self.world.size_of_type(ty.as_ref())?)
+                                };
+                                let Value::Ref(copied) = &copy else {
+                                    bail!(ErrorKind::trap(Trap::InvalidCode, format!("copy allocated {:?} instead of a ref", copy)))
+                                };
+                                unsafe { memcpy(data, copied.data, size); }
+                                self.mem.cur_frame().store(*dest, copy)?;
+                            }
+                            v => bail!(ErrorKind::mismatch("ref", &v))
+                        }
+                    },
+
+                    // sad code duplication - should there just be a single alloc function with a
+                    // destination argument instead?
+                    Op::CopyToHeap(dest, src) => {
+                        match self.mem.cur_frame().load(*src)? {
+                            Value::Ref(memory::Ref { ty, data }) => {
+                                let (copy, size) = if let ir::Type::Array(el_ty) = ty.as_ref() {
+                                    let count = unsafe { *(data as *mut usize) };
+                                    (self.mem.alloc_array(el_ty.as_ref(), count)?,
+                                        self.world.array_size(el_ty, count)?)
+                                } else {
+                                    (self.mem.alloc(ty.as_ref())?,
+                                        self.world.size_of_type(ty.as_ref())?)
+                                };
+                                let Value::Ref(copied) = &copy else {
+                                    bail!(ErrorKind::trap(Trap::InvalidCode, format!("copy allocated {:?} instead of a ref", copy)))
+                                };
+                                unsafe { memcpy(data, copied.data, size); }
+                                self.mem.cur_frame().store(*dest, copy)?;
+                            }
+                            v => bail!(ErrorKind::mismatch("ref", &v))
+                        }
+                    }
+                }
+                pos.instr += 1;
+            }
+            self.enter_block(code, pos, cur_block.next_block)?;
+        }
+    }
+
+}
+
+/// a reference to a field of the structure behind `r`, using the layout cached in `field` if the
+/// structure has the same type as the last time
+fn field_ref(world: &World, r: &memory::Ref, field: &Field) -> Result<memory::Ref> {
+    let mut cache = field.cache.borrow_mut();
+    if let Some(layout) = cache.as_ref() {
+        if layout.ty == r.ty {
+            return Ok(r.at_offset(layout.offset, layout.field_ty.clone()));
+        }
+    }
+    let (offset, field_ty) = world.field_offset(r.type_of(), &field.name)?;
+    let field_ty = Box::new(field_ty);
+    *cache = Some(FieldLayout { ty: r.ty.clone(), offset, field_ty: field_ty.clone() });
+    Ok(r.at_offset(offset, field_ty))
+}
+
+/// called by compiled code to make a call through the machine. Returns 0 if the call returned
+extern "C" fn jit_call(machine: *mut u8, site: u32, args: *mut u64) -> u32 {
+    // SAFETY: compiled code is only run by Machine::run_compiled, which passes itself
+    let m = unsafe { &mut *(machine as *mut Machine) };
+    match m.call_from_compiled(site, args) {
+        Result::Ok(()) => 0,
+        Err(e) => {
+            m.jit_error = Some(e);
+            1
+        }
+    }
+}
+
+/// called by compiled code to allocate on the heap. Returns the address of the allocation, or 0
+/// if it failed
+extern "C" fn jit_alloc(machine: *mut u8, site: u32, count: u64) -> u64 {
+    // SAFETY: compiled code is only run by Machine::run_compiled, which passes itself
+    let m = unsafe { &mut *(machine as *mut Machine) };
+    match m.alloc_from_compiled(site, count) {
+        Result::Ok(Value::Ref(r)) => r.data as u64,
+        Result::Ok(v) => unreachable!("allocated {:?}", v),
+        Err(e) => {
+            m.jit_error = Some(e.into());
+            0
+        }
+    }
+}
+
+/// trap if an integer operand has a width that is not supported
+fn check_int(i: &Integer) -> Result<()> {
+    if !Integer::valid_width(i.width) {
+        bail!(ErrorKind::trap(Trap::InvalidCode, format!("integer {:?} has an unsupported width", i)));
+    }
+    Ok(())
+}
+
+fn overflow(op: &ir::code::BinOp, a: Integer, b: Integer) -> ErrorKind {
+    ErrorKind::trap(Trap::Overflow, format!("integer overflow in {:?} {:?} {:?}", a, op, b))
+}
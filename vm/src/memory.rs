@@ -1,7 +1,10 @@
 use crate::world::*;
 use crate::value::*;
-use std::{alloc::Layout, mem::size_of, ptr::null_mut};
+use crate::error::ErrorKind;
+use crate::bytecode::{Code, FnIndex, Operand, Reg};
+use std::{alloc::Layout, mem::size_of, ptr::null_mut, rc::Rc};
 use anyhow::*;
+use ir::Trap;
 
 struct Header {
     ty: Box<ir::Type>,
@@ -34,11 +37,22 @@ impl Ref {
         }
     }
 
+    /// Copy the contents of an array of bytes
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        match self.ty.as_ref() {
+            ir::Type::Array(el) if **el == ir::Type::Int { signed: false, width: 8 } => unsafe {
+                let count = *(self.data as *mut usize);
+                Ok(std::slice::from_raw_parts(self.data.add(size_of::<usize>()), count).to_vec())
+            },
+            t => Err(anyhow!("expected array of bytes, got {:?}", t))
+        }
+    }
+
     /// Read the date inside the ref and return it as a Value
-    pub fn value(&self) -> Value {
+    pub fn value(&self) -> Result<Value> {
         unsafe {
             let ptr = self.data;
-            match self.ty.as_ref() {
+            Ok(match self.ty.as_ref() {
                 ir::Type::Unit => Value::Nil,
                 ir::Type::Bool => Value::Bool(*ptr > 0),
                 ir::Type::Int { signed, width } => {
@@ -47,23 +61,24 @@ impl Ref {
                         16 => Value::Int(Integer::new(16, *signed, *(ptr as *mut u16) as u64)),
                         32 => Value::Int(Integer::new(32, *signed, *(ptr as *mut u32) as u64)),
                         64 => Value::Int(Integer::new(64, *signed, *(ptr as *mut u64))),
-                        _ => panic!()
+                        w => bail!(ErrorKind::trap(Trap::InvalidCode, format!("invalid integer width {}", w)))
                     }
                 },
+                ir::Type::Float { width: 32 } => Value::Float(Float::F32(*(ptr as *mut f32))),
+                ir::Type::Float { width: 64 } => Value::Float(Float::F64(*(ptr as *mut f64))),
                 // TODO: this is quite unsafe, really we should have some way to validate that this
                 // is a valid pointer. Perhaps though since this is a private interface it's fine.
                 ir::Type::Ref(_) | ir::Type::Array(_) => Value::Ref(Ref {
                     ty: self.ty.clone(),
                     data: *(ptr as *mut *mut u8)
                 }),
-                ir::Type::AbstractRef(_) => todo!(),
-                _ => todo!()
-            }
+                t => bail!(ErrorKind::trap(Trap::Unsupported, format!("cannot load a value of type {:?}", t)))
+            })
         }
     }
 
     /// Move the data from the value into the ref
-    pub fn set_value(&self, val: Value) {
+    pub fn set_value(&self, val: Value) -> Result<()> {
         unsafe {
             let ptr = self.data;
             match (self.ty.as_ref(), val) {
@@ -77,39 +92,46 @@ impl Ref {
                         16 => *(ptr as *mut u16) = data as u16,
                         32 => *(ptr as *mut u32) = data as u32,
                         64 => *(ptr as *mut u64) = data,
-                        _ => panic!()
+                        w => bail!(ErrorKind::trap(Trap::InvalidCode, format!("invalid integer width {}", w)))
                     }
                 },
+                (ir::Type::Float { width: 32 }, Value::Float(Float::F32(f))) => *(ptr as *mut f32) = f,
+                (ir::Type::Float { width: 64 }, Value::Float(Float::F64(f))) => *(ptr as *mut f64) = f,
                 // handle nested references
                 (ir::Type::Ref(_), Value::Ref(r)) => {
                     // should we validate the type here?
                     *(ptr as *mut *mut u8) = r.data;
                 },
-                (ir::Type::Array(_), Value::Ref(r)) => {
-                    // should we validate the type here?
-                    if let ir::Type::Array(_) = r.type_of() {
-                        *(ptr as *mut *mut u8) = r.data;
-                    } else {
-                        panic!()
-                    }
+                (ir::Type::Array(_), Value::Ref(r)) if matches!(r.type_of(), ir::Type::Array(_)) => {
+                    // should we validate the element type here?
+                    *(ptr as *mut *mut u8) = r.data;
                 }
-                t => todo!("set value on ref with unimpl inner type {:?}", t)
+                (t, v) => bail!(ErrorKind::mismatch(&format!("{:?}", t), &v))
             }
         }
+        Ok(())
     }
 
     pub fn indexed(&self, world: &World, index: usize) -> Result<Ref> {
-        //TODO: bounds checking
         match self.type_of() {
             ir::Type::Array(el_ty) => {
+                let count = self.element_count().unwrap_or(0);
+                if index >= count {
+                    bail!(ErrorKind::trap(Trap::IndexOutOfBounds,
+                        format!("index {} out of bounds for array of length {}", index, count)));
+                }
                 Ok(Ref {
                     data: unsafe {
-                        self.data.offset((std::mem::size_of::<usize>() + index * world.size_of_type(el_ty)?) as isize)
+                        self.data.add(std::mem::size_of::<usize>() + index * world.size_of_type(el_ty)?)
                     },
                     ty: el_ty.clone()
                 })
             },
             ir::Type::Tuple(ts) => {
+                if index >= ts.len() {
+                    bail!(ErrorKind::trap(Trap::IndexOutOfBounds,
+                        format!("index {} out of bounds for tuple of {} elements", index, ts.len())));
+                }
                 let mut offset = 0;
                 for t in ts.iter().take(index) {
                     let ralign = world.required_alignment(t)?; 
@@ -117,48 +139,24 @@ impl Ref {
                     offset += world.size_of_type(t)?;
                 }
                 Ok(Ref {
-                    data: unsafe { self.data.offset(offset as isize) },
+                    data: unsafe { self.data.add(offset) },
                     ty: Box::new(ts[index].clone())
                 })
             },
-            t => Err(anyhow!("cannot index into unindexed type: {:?}", t))
+            t => Err(ErrorKind::trap(Trap::TypeMismatch, format!("cannot index into unindexed type: {:?}", t)).into())
         }
     }
 
-    pub fn field<'w>(&self, world: &'w World, field: &ir::Symbol) -> Result<Ref> {
-        match self.type_of() {
-            ir::Type::User(path, None) => {
-                match world.get_type(path) {
-                    Some(ir::TypeDefinition::NewType(ty)) => {
-                        Ok(Ref {
-                            ty: Box::new(ty.clone()),
-                            data: self.data
-                        }) // should probably check what the field name is?
-                    },
-                    Some(ir::TypeDefinition::Sum { .. }) => {
-                        Err(anyhow!("invalid type for field lookup"))
-                    },
-                    Some(ir::TypeDefinition::Product { fields, .. }) => {
-                        if let Some((_, ty)) = fields.iter().find(|(n, _)| n == field) {
-                            let mut offset = 0;
-                            for (_,t) in fields.iter().take_while(|(n,_)| n != field) {
-                                let ralign = world.required_alignment(ty)?;
-                                while offset % ralign != 0 { offset += 1; }
-                                offset += world.size_of_type(t)?;
-                            }
-                            Ok(Ref {
-                                ty: Box::new(ty.clone()),
-                                data: unsafe { self.data.offset(offset as isize) }
-                            })
-                        } else {
-                            Err(anyhow!("field not defined on type"))
-                        }
-                    },
-                    None => Err(anyhow!("unknown type, path = {}, field = {:?}", path, field)),
-                }
-            },
-            ir::Type::User(path, Some(params)) => todo!(),
-            _ => Err(anyhow!("invalid type for field lookup"))
+    pub fn field(&self, world: &World, field: &ir::Symbol) -> Result<Ref> {
+        let (offset, ty) = world.field_offset(self.type_of(), field)?;
+        Ok(self.at_offset(offset, Box::new(ty)))
+    }
+
+    /// A reference to a value of type `ty` that is `offset` bytes into the value behind this one
+    pub fn at_offset(&self, offset: usize, ty: Box<ir::Type>) -> Ref {
+        Ref {
+            ty,
+            data: unsafe { self.data.add(offset) }
         }
     }
 }
@@ -174,10 +172,9 @@ pub struct Memory<'w> {
 }
 
 impl<'w> Memory<'w> {
-    pub fn new(world: &'w World) -> Memory {
+    pub fn new(world: &'w World) -> Memory<'w> {
         let stack_size = 1024 * 1024; // 1 MiB
-        let mut stack_data = Vec::with_capacity(stack_size);
-        stack_data.resize(stack_size, 0);
+        let stack_data = vec![0; stack_size];
         Memory {
             world, last_alloc: null_mut(),
             max_size: 4 * 1024 * 1024 * 1024, // 4GiB
@@ -201,8 +198,8 @@ impl<'w> Memory<'w> {
             unsafe {
                 if self.current_size + layout.size() > self.max_size {
                     if ran_gc {
-                        bail!("memory exhausted, increase max heap size from {} (current size = {}, attempted to allocate {} for {:?})",
-                            self.max_size, self.current_size, layout.size(), ty)
+                        bail!(ErrorKind::trap(Trap::OutOfMemory, format!("memory exhausted, increase max heap size from {} (current size = {}, attempted to allocate {} for {:?})",
+                            self.max_size, self.current_size, layout.size(), ty)))
                     } else {
                         ran_gc = true;
                         self.gc();
@@ -230,6 +227,12 @@ impl<'w> Memory<'w> {
         }
     }
 
+    /// the padding needed before the next stack allocation to align it like heap allocations
+    fn stack_padding(&self) -> usize {
+        let next = self.stack_data.as_ptr() as usize + self.stack_ptr;
+        next.wrapping_neg() % std::mem::align_of::<Header>()
+    }
+
     /// allocate a new value on the stack, and return reference to it
     pub fn stack_alloc(&mut self, ty: &ir::Type) -> Result<Value> {
         if let ir::Type::Array(_) = ty {
@@ -237,20 +240,19 @@ impl<'w> Memory<'w> {
         }
 
         let size = self.world.size_of_type(ty)?;
-        if self.stack_ptr + size > self.stack_data.len() {
-            bail!("data stack overflow, increase stack size from {} (attempted to allocate {} for {:?})",
-            self.stack_data.len(), size, ty)
+        let start = self.stack_ptr + self.stack_padding();
+        if start + size > self.stack_data.len() {
+            bail!(ErrorKind::trap(Trap::StackOverflow, format!("data stack overflow, increase stack size from {} (attempted to allocate {} for {:?})",
+            self.stack_data.len(), size, ty)))
         }
-        // TODO: this isn't aligned
-        let mem = self.stack_data[self.stack_ptr..].as_ptr() as *mut u8;
+        let mem = self.stack_data[start..].as_ptr() as *mut u8;
         // Zero out the allocation
-        for x in self.stack_data[self.stack_ptr..(self.stack_ptr+size)].iter_mut() {
+        for x in self.stack_data[start..(start+size)].iter_mut() {
             *x = 0;
         }
-        self.stack_ptr += size;
-        self.cur_frame().data_stack_size += size;
-        // should this be aligned? how do we know how much padding to allocate until after
-        // we get the pointer?
+        let used = start + size - self.stack_ptr;
+        self.stack_ptr += used;
+        self.cur_frame().data_stack_size += used;
         Ok(Value::Ref(Ref {
             ty: Box::new(ty.clone()),
             data: mem
@@ -266,8 +268,8 @@ impl<'w> Memory<'w> {
             unsafe {
                 if self.current_size + layout.size() > self.max_size {
                     if ran_gc {
-                        bail!("memory exhausted, increase max heap size from {} (current size = {}, attempted to allocate {} for {} x {:?})",
-                            self.max_size, self.current_size, layout.size(), count, el_ty)
+                        bail!(ErrorKind::trap(Trap::OutOfMemory, format!("memory exhausted, increase max heap size from {} (current size = {}, attempted to allocate {} for {} x {:?})",
+                            self.max_size, self.current_size, layout.size(), count, el_ty)))
                     } else {
                         ran_gc = true;
                         self.gc();
@@ -296,24 +298,34 @@ impl<'w> Memory<'w> {
         }
     }
 
+    /// allocate a new array of bytes on the heap containing a copy of `data`
+    pub fn alloc_bytes(&mut self, data: &[u8]) -> Result<Value> {
+        let arr = self.alloc_array(&ir::Type::Int { signed: false, width: 8 }, data.len())?;
+        if let Value::Ref(r) = &arr {
+            unsafe {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), r.data.add(size_of::<usize>()), data.len());
+            }
+        }
+        Ok(arr)
+    }
+
     /// allocate a new array on the stack, and return a reference to it
     pub fn stack_alloc_array(&mut self, el_ty: &ir::Type, count: usize) -> Result<Value> {
         let size = self.world.array_size(el_ty, count)?;
-        if self.stack_ptr + size > self.stack_data.len() {
-            bail!("data stack overflow, increase stack size from {} (attempted to allocate {} for array {} x {:?})",
-            self.stack_data.len(), size, count, el_ty)
+        let start = self.stack_ptr + self.stack_padding();
+        if start + size > self.stack_data.len() {
+            bail!(ErrorKind::trap(Trap::StackOverflow, format!("data stack overflow, increase stack size from {} (attempted to allocate {} for array {} x {:?})",
+            self.stack_data.len(), size, count, el_ty)))
         }
-        // TODO: this isn't aligned
-        let mem = self.stack_data[self.stack_ptr..].as_ptr() as *mut u8;
+        let mem = self.stack_data[start..].as_ptr() as *mut u8;
         // Zero out the allocation
-        for x in self.stack_data[self.stack_ptr..(self.stack_ptr+size)].iter_mut() {
+        for x in self.stack_data[start..(start+size)].iter_mut() {
             *x = 0;
         }
         unsafe { *(mem as *mut usize) = count; }
-        self.stack_ptr += size;
-        self.cur_frame().data_stack_size += size;
-        // should this be aligned? how do we know how much padding to allocate until after
-        // we get the pointer?
+        let used = start + size - self.stack_ptr;
+        self.stack_ptr += used;
+        self.cur_frame().data_stack_size += used;
         Ok(Value::Ref(Ref {
             ty: Box::new(ir::Type::Array(Box::new(el_ty.clone()))),
             data: mem
@@ -332,43 +344,102 @@ impl<'w> Memory<'w> {
         log::info!("running garbage collection. current size={}, max size={}", self.current_size, self.max_size);
     }
 
+    /// get the value of an operand, reading registers from the current frame. String literals are
+    /// allocated on the heap as arrays of bytes
+    pub fn operand(&mut self, val: &Operand) -> Result<Value> {
+        match val {
+            Operand::Str(s) => self.alloc_bytes(s),
+            v => self.cur_frame().operand(v)
+        }
+    }
+
     pub fn cur_frame(&mut self) -> &mut Frame {
         self.stack.last_mut().unwrap()
     }
 }
 
+/// The point that execution has reached in a function body
+#[derive(Debug, Clone, Copy)]
+pub struct Position {
+    pub block: ir::code::BlockIndex,
+    /// the index of the next instruction to run in the block, or of the call instruction that is
+    /// waiting for a function to return
+    pub instr: usize
+}
+
+/// What happens to the value returned from a frame
+#[derive(Debug, Clone)]
+pub enum ReturnTo {
+    /// it is given to the host that called the function
+    Host,
+    /// it is put in a register of the calling frame, which continues after the call
+    Register(Reg),
+    /// the calling frame made the call with an `Invoke` instruction, which also says where to
+    /// continue if the function throws
+    Invoke {
+        dest: Reg,
+        normal: ir::code::BlockIndex,
+        unwind: ir::code::BlockIndex,
+        exception: Reg
+    }
+}
+
+/// The registers and execution state of a function call
 #[derive(Debug)]
 pub struct Frame {
     pub registers: Vec<Value>,
-    pub data_stack_size: usize
+    pub data_stack_size: usize,
+    /// the function running in this frame, by its index in the machine's function table
+    pub function: FnIndex,
+    /// the lowered body of the function
+    pub code: Rc<Code>,
+    pub pos: Position,
+    pub ret: ReturnTo
+}
+
+impl Position {
+    /// the position at the start of a function body, after the phis in the entry block
+    pub fn entry(code: &Code) -> Position {
+        Position { block: 0, instr: code.blocks.first().map_or(0, |b| b.phis) }
+    }
 }
 
 impl Frame {
-    pub fn new(num_reg: usize) -> Frame {
+    pub fn new(function: FnIndex, code: Rc<Code>, ret: ReturnTo) -> Frame {
         Frame {
-            registers: std::iter::repeat(Value::Nil).take(num_reg).collect(),
-            data_stack_size: 0
+            registers: vec![Value::Nil; code.max_registers as usize],
+            data_stack_size: 0,
+            pos: Position::entry(&code),
+            function, code,
+            ret
         }
     }
 
-    pub fn load(&self, ix: &ir::code::Register) -> Value {
-        self.registers[ix.0 as usize].clone()
+    pub fn load(&self, ix: Reg) -> Result<Value> {
+        self.get(ix).cloned()
+    }
+
+    /// borrow the value in a register
+    pub fn get(&self, ix: Reg) -> Result<&Value> {
+        self.registers.get(ix as usize).ok_or_else(|| invalid_register(ix, self.registers.len()))
     }
 
-    pub fn store(&mut self, ix: &ir::code::Register, v: Value) {
-        self.registers[ix.0 as usize] = v;
+    pub fn store(&mut self, ix: Reg, v: Value) -> Result<()> {
+        let count = self.registers.len();
+        *self.registers.get_mut(ix as usize).ok_or_else(|| invalid_register(ix, count))? = v;
+        Ok(())
     }
 
-    pub fn convert_value(&self, val: &ir::code::Value) -> Value {
+    pub fn operand(&self, val: &Operand) -> Result<Value> {
         match val {
-            ir::code::Value::LiteralUnit => Value::Nil,
-            ir::code::Value::LiteralInt(d) => Value::Int(*d),
-            ir::code::Value::LiteralFloat(d) => Value::Float(*d),
-            ir::code::Value::LiteralString(_) => todo!(),
-            ir::code::Value::LiteralBool(b) => Value::Bool(*b),
-            ir::code::Value::Reg(r) => self.registers[r.0 as usize].clone(),
+            Operand::Reg(r) => self.load(*r),
+            Operand::Const(v) => Ok(v.clone()),
+            Operand::Str(_) => bail!(ErrorKind::trap(Trap::InvalidCode,
+                "string literals must be allocated with Memory::operand"))
         }
     }
 }
 
-
+fn invalid_register(ix: Reg, count: usize) -> Error {
+    ErrorKind::trap(Trap::InvalidCode, format!("register {} used in a function with {} registers", ix, count)).into()
+}
@@ -0,0 +1,131 @@
+//! Native functions are implemented in Rust by the host and registered with a [`World`] as part of
+//! a [`NativeModule`]. They are called with ordinary `Call` and `CallImpl` instructions, just like
+//! functions with IR bodies. Arguments and return values are checked against the function's
+//! signature, so a native function can rely on getting the values it declared.
+//!
+//! Strings are passed as arrays of bytes containing UTF-8 text (`Array(Int(8, unsigned))`), which
+//! [`NativeContext`] can read and allocate.
+use std::{collections::HashMap, rc::Rc};
+use anyhow::*;
+use crate::world::World;
+use crate::value::Value;
+use crate::memory::Memory;
+
+type NativeImpl = dyn Fn(&mut NativeContext, Vec<Value>) -> Result<Value>;
+
+/// A function implemented by the host
+pub struct NativeFunction {
+    pub signature: ir::FunctionSignature,
+    f: Box<NativeImpl>
+}
+
+/// A module containing native functions. The module can also define types, interfaces and
+/// implementations like any other, which may refer to its native functions
+pub struct NativeModule {
+    pub module: ir::Module,
+    pub functions: HashMap<ir::Symbol, Rc<NativeFunction>>
+}
+
+/// Access to the running machine for native functions
+pub struct NativeContext<'m, 'w> {
+    pub world: &'w World,
+    pub mem: &'m mut Memory<'w>
+}
+
+/// the type of strings passed to and from native functions
+pub fn string_type() -> ir::Type {
+    ir::Type::Array(Box::new(ir::Type::Int { signed: false, width: 8 }))
+}
+
+impl NativeFunction {
+    pub fn new(signature: ir::FunctionSignature, f: impl Fn(&mut NativeContext, Vec<Value>) -> Result<Value> + 'static) -> NativeFunction {
+        NativeFunction { signature, f: Box::new(f) }
+    }
+
+    /// call the function after checking that the arguments match its signature, and check that
+    /// the value it returns does too
+    pub fn call(&self, path: &ir::Path, cx: &mut NativeContext, args: Vec<Value>) -> Result<Value> {
+        if args.len() != self.signature.args.len() {
+            bail!("native function {} takes {} arguments, but {} were given", path, self.signature.args.len(), args.len());
+        }
+        for (v, (ty, name)) in args.iter().zip(self.signature.args.iter()) {
+            if !value_matches(v, ty) {
+                bail!("argument {} to native function {} should be {:?}, got {:?}", name.0, path, ty, v);
+            }
+        }
+        let rv = (self.f)(cx, args)?;
+        if !value_matches(&rv, &self.signature.return_type) {
+            bail!("native function {} returned {:?}, but should return {:?}", path, rv, self.signature.return_type);
+        }
+        Ok(rv)
+    }
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction").field("signature", &self.signature).finish_non_exhaustive()
+    }
+}
+
+/// true if a value can be passed where a value of type `ty` is expected
+pub fn value_matches(v: &Value, ty: &ir::Type) -> bool {
+    match (v, ty) {
+        (Value::Nil, ir::Type::Unit) => true,
+        (Value::Bool(_), ir::Type::Bool) => true,
+        (Value::Int(i), ir::Type::Int { signed, width }) => i.signed == *signed && i.width == *width,
+        (Value::Float(f), ir::Type::Float { width }) => f.width() == *width,
+        (Value::Ref(r), ir::Type::Ref(t)) => r.type_of() == t.as_ref(),
+        (Value::Ref(r), ir::Type::Array(_)) => r.type_of() == ty,
+        // type parameters accept any value
+        (_, ir::Type::Var(_)) => true,
+        _ => false
+    }
+}
+
+/// copy the contents of a string value
+pub fn read_string(v: &Value) -> Result<String> {
+    match v {
+        Value::Ref(r) if *r.type_of() == string_type() => String::from_utf8(r.bytes()?)
+            .map_err(|e| anyhow!("string is not valid UTF-8: {}", e)),
+        v => Err(anyhow!("expected string, got {:?}", v))
+    }
+}
+
+impl NativeModule {
+    /// create a native module with no functions
+    pub fn new(path: ir::Path, version: ir::Version) -> NativeModule {
+        NativeModule {
+            module: ir::Module {
+                path, version,
+                types: HashMap::new(),
+                interfaces: HashMap::new(),
+                implementations: HashMap::new(),
+                functions: HashMap::new(),
+                imports: Vec::new(),
+                submodules: Vec::new(),
+                inlinable: false
+            },
+            functions: HashMap::new()
+        }
+    }
+
+    /// add a native function to the module
+    pub fn function(mut self, name: &str, signature: ir::FunctionSignature,
+        f: impl Fn(&mut NativeContext, Vec<Value>) -> Result<Value> + 'static) -> NativeModule
+    {
+        self.functions.insert(ir::Symbol(name.into()), Rc::new(NativeFunction::new(signature, f)));
+        self
+    }
+}
+
+impl NativeContext<'_, '_> {
+    /// read a string passed to a native function
+    pub fn string(&self, v: &Value) -> Result<String> {
+        read_string(v)
+    }
+
+    /// allocate a string on the heap to return from a native function
+    pub fn alloc_string(&mut self, s: &str) -> Result<Value> {
+        self.mem.alloc_bytes(s.as_bytes())
+    }
+}
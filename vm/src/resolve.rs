@@ -0,0 +1,522 @@
+//! Dependency resolution for module imports. Before anything is loaded, the resolver gathers the
+//! version requirements of every import reachable from the requested module and searches the
+//! module search paths for a set of module files that satisfies all of them at once, preferring
+//! the highest versions available.
+//!
+//! Each module or package file is selected or rejected as a whole. If two requirements on the same
+//! module cannot be met by a single version, the resolver backtracks to try older versions of the
+//! modules that introduced them, and if no combination works it reports every requirement on the
+//! module along with the chain of imports that led to it.
+//!
+//! When different major versions of a module are allowed to coexist, the extra versions are loaded
+//! under a versioned path, where the last symbol of the module path is suffixed with `#` and the
+//! major version (so version 1 of `foo::bar` is loaded as `foo::bar#1`). Paths in the modules that
+//! import that version are rewritten to refer to it, so no changes are needed at runtime.
+//!
+//! Circular imports between modules are rejected unless explicitly allowed, except for imports
+//! between modules in the same file, which are always loaded together.
+//!
+//! If a [`Lockfile`] is given, only the exact module versions it records are considered. Module
+//! files whose contents do not match the recorded hash are skipped, and resolution fails if no file
+//! with the locked version of a module matches.
+use std::{collections::{HashMap, VecDeque}, fmt::Display, path::PathBuf, sync::Arc};
+use anyhow::Result;
+use crate::world::flatten_module_tree;
+use crate::search::{SearchPath, ModuleFile, FileEntry, scan_dir};
+use crate::lockfile::{Lockfile, LockEntry};
+use crate::deps::{ImportGraph, ImportCycle};
+
+/// Options that change how dependency resolution is performed
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    /// Allow modules with the same path but different major versions to be loaded at the same
+    /// time, for when imports require incompatible versions of a module
+    pub allow_major_coexistence: bool,
+    /// Allow modules in different files to import each other in a cycle
+    pub allow_import_cycles: bool,
+    /// Only resolve the requested module and the others in the same file, leaving the modules it
+    /// imports to be resolved separately
+    pub shallow: bool,
+    /// Only load the modules recorded in this lockfile
+    pub lock: Option<Lockfile>
+}
+
+/// The decoded contents of a single module file, which is either loaded or not as a whole
+struct Unit {
+    file: ModuleFile,
+    /// The hash of the whole file
+    hash: ir::ContentHash,
+    /// The modules in the file, with submodules flattened and relative paths resolved
+    modules: Vec<ir::Module>,
+    /// Imports from every module in the file, as (importing module, its version, import path, requirement)
+    imports: Vec<(ir::Path, ir::Version, ir::Path, ir::VersionReq)>
+}
+
+/// A requirement that some version of a module be loaded
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    path: ir::Path,
+    req: ir::VersionReq,
+    /// The unit that contains the importing module, or `None` for a direct request
+    from_unit: Option<usize>,
+    /// The importing module followed by each module that caused the one before it to be loaded
+    chain: Arc<Vec<(ir::Path, ir::Version)>>
+}
+
+/// A version of a module selected to be loaded
+#[derive(Debug, Clone)]
+struct Instance {
+    path: ir::Path,
+    version: ir::Version,
+    /// The unit providing this module, or `None` if it was already loaded before resolution started
+    unit: Option<usize>,
+    /// The path the module is loaded under, which differs from `path` for coexisting major versions
+    loaded_as: Option<ir::Path>,
+    /// Requirements that have been satisfied by this instance
+    bound: Vec<Requirement>
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    instances: Vec<Instance>,
+    units: Vec<usize>
+}
+
+/// An error explaining why a set of modules to load could not be found
+#[derive(Debug)]
+pub enum ResolveError {
+    /// No set of module versions satisfies every requirement on a module
+    Unsatisfiable {
+        path: ir::Path,
+        requirements: Vec<Requirement>,
+        /// The version of the module that was already loaded before resolution, if any
+        loaded: Option<ir::Version>,
+        available: Vec<ir::Version>,
+        /// Files that could have contained the module but could not be read, with the error
+        unreadable: Vec<(PathBuf, String)>
+    },
+    /// A module file has different contents from the file recorded in the lockfile
+    LockMismatch {
+        path: ir::Path,
+        version: ir::Version,
+        file: PathBuf,
+        expected: ir::ContentHash,
+        found: ir::ContentHash
+    },
+    /// A module is required that is not recorded in the lockfile at all
+    NotLocked {
+        path: ir::Path,
+        requirement: Requirement
+    },
+    /// The selected modules import each other in a cycle
+    ImportCycle(ImportCycle)
+}
+
+/// The set of modules chosen by the resolver, ready to be added to the world
+pub struct Resolution {
+    pub modules: Vec<ResolvedModule>
+}
+
+/// A module chosen by the resolver
+pub struct ResolvedModule {
+    /// The module, with the paths of any coexisting major versions rewritten
+    pub module: ir::Module,
+    /// The file the module was read from
+    pub file: PathBuf,
+    /// The exact version and file hash of the module
+    pub lock: LockEntry
+}
+
+/// Searches module directories for a consistent set of module versions to load
+pub struct Resolver {
+    options: ResolveOptions,
+    search_path: SearchPath,
+    /// The module files found in each directory that has been scanned
+    scanned: HashMap<PathBuf, Vec<FileEntry>>,
+    units: Vec<Unit>,
+    decoded: HashMap<ModuleFile, Option<usize>>,
+    /// The error for each module file that could not be decoded
+    read_errors: HashMap<ModuleFile, String>
+}
+
+/// returns the path that a module is loaded under when it coexists with a different major version
+pub fn versioned_path(path: &ir::Path, major: u64) -> ir::Path {
+    let mut p = path.clone();
+    let last = p.0.len() - 1;
+    p[last] = ir::Symbol(format!("{}#{}", p[last].0, major));
+    p
+}
+
+/// returns the unversioned module path for a path produced by [`versioned_path`]
+pub fn unversioned_path(path: &ir::Path) -> ir::Path {
+    let mut p = path.clone();
+    let last = p.0.len() - 1;
+    if let Some((name, _)) = p[last].0.split_once('#') {
+        p[last] = ir::Symbol(name.to_string());
+    }
+    p
+}
+
+fn decode_module_file(file: &ModuleFile) -> Result<(Vec<ir::Module>, ir::ContentHash)> {
+    let data = std::fs::read(file.path())?;
+    let modules = match file {
+        ModuleFile::Module(_) => vec![rmp_serde::from_read_ref(&data)?],
+        ModuleFile::Package(_) => ir::Package::read(data.as_slice())?.modules,
+    };
+    Ok((modules, ir::ContentHash::of(&data)))
+}
+
+impl Resolver {
+    /// Create a resolver that searches for module files in a search path
+    pub fn new(search_path: SearchPath, options: ResolveOptions) -> Resolver {
+        Resolver { options, search_path, scanned: HashMap::new(), units: Vec::new(), decoded: HashMap::new(), read_errors: HashMap::new() }
+    }
+
+    /// all module files that might contain the module at `path`, in order of preference
+    fn files_for(&mut self, path: &ir::Path) -> Vec<FileEntry> {
+        let mut entries = Vec::new();
+        for (dir, prefix) in self.search_path.candidate_dirs(path) {
+            entries.extend(self.scanned.entry(dir)
+                .or_insert_with_key(|dir| scan_dir(dir, &prefix))
+                .iter().cloned());
+        }
+        entries
+    }
+
+    /// Find a set of modules to load so that `path` is loaded at a version matching `req` along
+    /// with everything it imports, keeping all of the `loaded` modules as they are
+    pub fn resolve<'m>(&mut self, loaded: impl Iterator<Item=(&'m ir::Path, &'m ir::Module)>,
+        path: &ir::Path, req: &ir::VersionReq) -> Result<Resolution>
+    {
+        let mut state = State::default();
+        // cycles can pass through modules that are already loaded, when their imports were
+        // deferred or resolved separately
+        let mut graph = ImportGraph::default();
+        for (loaded_as, m) in loaded {
+            graph.add(m, None, None);
+            state.instances.push(Instance {
+                path: unversioned_path(loaded_as),
+                version: m.version.clone(),
+                unit: None,
+                loaded_as: Some(loaded_as.clone()),
+                bound: Vec::new()
+            });
+        }
+        let root = Requirement { path: path.clone(), req: req.clone(), from_unit: None, chain: Arc::new(Vec::new()) };
+        let state = self.solve(state, VecDeque::from(vec![root])).map_err(|e| *e)?;
+        Ok(self.finish(state, graph).map_err(|e| *e)?)
+    }
+
+    /// decode the unit in a file if it has not been already, returning its index if it is valid
+    fn unit(&mut self, file: &ModuleFile) -> Option<usize> {
+        if let Some(u) = self.decoded.get(file) {
+            return *u;
+        }
+        let unit = decode_module_file(file).and_then(|(ms, hash)| {
+            let mut flat = Vec::new();
+            for mut m in ms {
+                m.resolve_relative_paths()?;
+                flatten_module_tree(m, &mut flat)?;
+            }
+            Ok((flat, hash))
+        });
+        let u = match unit {
+            Ok((modules, hash)) => {
+                let imports = modules.iter()
+                    .flat_map(|m| m.imports.iter()
+                        .map(|(p, r)| (m.path.clone(), m.version.clone(), p.clone(), r.clone())))
+                    .collect();
+                self.units.push(Unit { file: file.clone(), hash, modules, imports });
+                Some(self.units.len() - 1)
+            },
+            Err(e) => {
+                log::warn!("error reading module file {:?}: {:#}", file, e);
+                self.read_errors.insert(file.clone(), format!("{:#}", e));
+                None
+            }
+        };
+        self.decoded.insert(file.clone(), u);
+        u
+    }
+
+    /// all module files that might contain a version of `path` matching `req`
+    fn candidate_files(&mut self, path: &ir::Path, req: &ir::VersionReq) -> Vec<ModuleFile> {
+        self.files_for(path).into_iter()
+            // a file named after the module must have a matching version, but files named after
+            // one of its parents may contain it as a submodule at any version
+            .filter(|e| (e.name == *path && req.matches(&e.version)) || (path.starts_with(&e.name) && e.name != *path))
+            .map(|e| e.file)
+            .collect()
+    }
+
+    /// all units that provide some version of `path`, with the provided version. If there is a
+    /// lockfile, only units that contain exactly the locked modules are included
+    fn candidates(&mut self, path: &ir::Path, req: &ir::VersionReq) -> std::result::Result<Vec<(usize, ir::Version)>, Box<ResolveError>> {
+        let mut cs = Vec::new();
+        let mut mismatch = None;
+        for f in self.candidate_files(path, req) {
+            if let Some(u) = self.unit(&f) {
+                if let Some(m) = self.units[u].modules.iter().find(|m| m.path == *path) {
+                    if cs.iter().any(|(cu, _)| *cu == u) {
+                        continue;
+                    }
+                    // another directory may have a copy of the file that does match
+                    match self.check_lock(u) {
+                        Ok(true) => cs.push((u, m.version.clone())),
+                        Ok(false) => {},
+                        Err(e) => {
+                            log::debug!("skipping {}: {}", f.path().display(), e);
+                            mismatch.get_or_insert(e);
+                        }
+                    }
+                }
+            }
+        }
+        match mismatch {
+            Some(e) if cs.is_empty() => return Err(e),
+            _ => {}
+        }
+        // sort is stable, so files found earlier in the search path are preferred for equal versions
+        cs.sort_by(|(_, a), (_, b)| b.cmp(a));
+        Ok(cs)
+    }
+
+    /// check that every module in a unit is in the lockfile, if there is one. Fails if a module is
+    /// locked but the unit's hash differs from the one in the lockfile
+    fn check_lock(&self, u: usize) -> std::result::Result<bool, Box<ResolveError>> {
+        let lock = match &self.options.lock {
+            Some(l) => l,
+            None => return Ok(true)
+        };
+        let unit = &self.units[u];
+        for m in unit.modules.iter() {
+            match lock.get(&m.path, &m.version) {
+                Some(e) if e.hash != unit.hash => return Err(Box::new(ResolveError::LockMismatch {
+                    path: m.path.clone(),
+                    version: m.version.clone(),
+                    file: unit.file.path().to_path_buf(),
+                    expected: e.hash,
+                    found: unit.hash
+                })),
+                Some(_) => {},
+                None => {
+                    log::trace!("skipping {} v{} from {}, which is not in the lockfile", m.path, m.version, unit.file.path().display());
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn conflict(&mut self, state: &State, r: Requirement) -> Box<ResolveError> {
+        let mut requirements = Vec::new();
+        let mut loaded = None;
+        for i in state.instances.iter().filter(|i| i.path == r.path) {
+            requirements.extend(i.bound.iter().cloned());
+            if i.unit.is_none() {
+                loaded = Some(i.version.clone());
+            }
+        }
+        let path = r.path.clone();
+        if self.options.lock.as_ref().is_some_and(|l| !l.contains(&path)) {
+            return Box::new(ResolveError::NotLocked { path, requirement: r });
+        }
+        requirements.push(r);
+        let available = self.candidates(&path, &ir::VersionReq::STAR).unwrap_or_default()
+            .into_iter().map(|(_, v)| v).collect();
+        let unreadable = self.candidate_files(&path, &ir::VersionReq::STAR).into_iter()
+            .filter_map(|f| self.read_errors.get(&f).map(|e| (f.path().to_path_buf(), e.clone())))
+            .collect();
+        Box::new(ResolveError::Unsatisfiable { path, requirements, loaded, available, unreadable })
+    }
+
+    /// add a unit to a state to satisfy `r`, or return `None` if it provides modules that clash
+    /// with ones that have already been selected
+    fn select(&self, state: &State, u: usize, r: &Requirement, pending: &mut VecDeque<Requirement>) -> Option<State> {
+        let mut next = state.clone();
+        let unit = &self.units[u];
+        for m in unit.modules.iter() {
+            let clash = state.instances.iter().any(|i| i.path == m.path
+                && (!self.options.allow_major_coexistence || i.version.major == m.version.major));
+            if clash {
+                log::trace!("rejecting {} v{}, which clashes with an already selected version", m.path, m.version);
+                return None;
+            }
+            next.instances.push(Instance {
+                path: m.path.clone(),
+                version: m.version.clone(),
+                unit: Some(u),
+                loaded_as: None,
+                bound: if m.path == r.path { vec![r.clone()] } else { Vec::new() }
+            });
+        }
+        next.units.push(u);
+        for (from, from_version, ip, ireq) in unit.imports.iter() {
+            let mut chain = vec![(from.clone(), from_version.clone())];
+            chain.extend(r.chain.iter().cloned());
+            let ir = Requirement { path: ip.clone(), req: ireq.clone(), from_unit: Some(u), chain: Arc::new(chain) };
+            // imports between modules in the same file always refer to each other
+            if let Some(i) = next.instances.iter_mut().find(|i| i.unit == Some(u) && i.path == *ip) {
+                if !ireq.matches(&i.version) {
+                    log::warn!("module {} v{} requires {} {}, but the same file contains v{}", from, from_version, ip, ireq, i.version);
+                    return None;
+                }
+                i.bound.push(ir);
+            } else if !self.options.shallow {
+                pending.push_back(ir);
+            }
+        }
+        Some(next)
+    }
+
+    fn solve(&mut self, mut state: State, mut pending: VecDeque<Requirement>) -> std::result::Result<State, Box<ResolveError>> {
+        let r = match pending.pop_front() {
+            Some(r) => r,
+            None => return Ok(state)
+        };
+        let existing: Vec<usize> = (0..state.instances.len()).filter(|&i| state.instances[i].path == r.path).collect();
+        if let Some(&i) = existing.iter().find(|&&i| r.req.matches(&state.instances[i].version)) {
+            state.instances[i].bound.push(r);
+            return self.solve(state, pending);
+        }
+        if !existing.is_empty() && !self.options.allow_major_coexistence {
+            return Err(self.conflict(&state, r));
+        }
+        let candidates: Vec<usize> = self.candidates(&r.path, &r.req)?.into_iter()
+            .filter(|(_, v)| r.req.matches(v))
+            .map(|(u, _)| u)
+            .collect();
+        let mut last_conflict = None;
+        for u in candidates {
+            let mut next_pending = pending.clone();
+            if let Some(next) = self.select(&state, u, &r, &mut next_pending) {
+                match self.solve(next, next_pending) {
+                    Ok(s) => return Ok(s),
+                    Err(e) if matches!(*e, ResolveError::LockMismatch { .. }) => return Err(e),
+                    Err(e) => last_conflict = Some(e)
+                }
+            }
+        }
+        Err(match last_conflict {
+            Some(e) => e,
+            None => self.conflict(&state, r)
+        })
+    }
+
+    /// assign paths to each selected instance and collect the modules to load, checking for
+    /// circular imports with the loaded modules in `graph`
+    fn finish(&mut self, mut state: State, mut graph: ImportGraph) -> std::result::Result<Resolution, Box<ResolveError>> {
+        let mut by_path: HashMap<ir::Path, Vec<usize>> = HashMap::new();
+        for (ix, i) in state.instances.iter().enumerate() {
+            by_path.entry(i.path.clone()).or_default().push(ix);
+        }
+        for ixs in by_path.values_mut() {
+            ixs.sort_by(|a, b| state.instances[*b].version.cmp(&state.instances[*a].version));
+            let mut plain_taken = ixs.iter().any(|&ix| state.instances[ix].loaded_as.as_ref() == Some(&state.instances[ix].path));
+            for &ix in ixs.iter() {
+                let i = &mut state.instances[ix];
+                if i.loaded_as.is_none() {
+                    i.loaded_as = Some(if plain_taken { versioned_path(&i.path, i.version.major) } else { i.path.clone() });
+                    plain_taken = true;
+                }
+            }
+        }
+
+        // for each unit, the paths that need to be rewritten to refer to a versioned module
+        let mut rebinds: HashMap<usize, HashMap<ir::Path, ir::Path>> = HashMap::new();
+        for i in state.instances.iter() {
+            let loaded_as = i.loaded_as.as_ref().expect("every instance has been assigned a path");
+            if *loaded_as == i.path {
+                continue;
+            }
+            for u in i.unit.iter().chain(i.bound.iter().flat_map(|r| r.from_unit.iter())) {
+                rebinds.entry(*u).or_default().insert(i.path.clone(), loaded_as.clone());
+            }
+        }
+
+        let mut modules = Vec::new();
+        for u in state.units {
+            let ms = std::mem::take(&mut self.units[u].modules);
+            let rebind = |p: &mut ir::Path| {
+                let rb = match rebinds.get(&u) {
+                    Some(rb) => rb,
+                    None => return
+                };
+                if let Some(k) = (1..=p.len()).rev().find(|&k| rb.contains_key(&p.subpath(p.len() - k))) {
+                    let mut np = rb[&p.subpath(p.len() - k)].clone();
+                    np.0.extend(p.0[k..].iter().cloned());
+                    *p = np;
+                }
+            };
+            for mut m in ms {
+                let lock = LockEntry {
+                    path: m.path.clone(),
+                    version: m.version.clone(),
+                    hash: self.units[u].hash
+                };
+                rebind(&mut m.path);
+                m.for_each_path_mut(&mut |p| { rebind(p); Ok(()) })
+                    .expect("rebinding paths cannot fail");
+                graph.add(&m, Some(u), None);
+                modules.push(ResolvedModule { module: m, file: self.units[u].file.path().to_path_buf(), lock });
+            }
+        }
+        if !self.options.allow_import_cycles {
+            if let Some(c) = modules.iter().find_map(|rm| graph.find_cycle_through(&rm.module.path)) {
+                return Err(Box::new(ResolveError::ImportCycle(c)));
+            }
+        }
+        Ok(Resolution { modules })
+    }
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::Unsatisfiable { path, requirements, loaded, available, unreadable } => {
+                writeln!(f, "could not find a version of module {} that satisfies all requirements:", path)?;
+                for r in requirements.iter() {
+                    writeln!(f, "    {}", r)?;
+                }
+                if let Some(v) = loaded {
+                    writeln!(f, "version {} is already loaded", v)?;
+                }
+                if available.is_empty() {
+                    write!(f, "no versions of {} were found in the module search path", path)?;
+                } else {
+                    write!(f, "available versions: {}", available.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))?;
+                }
+                for (file, e) in unreadable.iter() {
+                    write!(f, "\ncould not read {}: {}", file.display(), e)?;
+                }
+                Ok(())
+            },
+            ResolveError::LockMismatch { path, version, file, expected, found } =>
+                write!(f, "module {} v{} in {} does not match the lockfile: expected hash {}, found {}",
+                    path, version, file.display(), expected, found),
+            ResolveError::NotLocked { path, requirement } =>
+                write!(f, "module {} is not in the lockfile, but is needed:\n    {}", path, requirement),
+            ResolveError::ImportCycle(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} ", self.path, self.req)?;
+        if self.chain.is_empty() {
+            write!(f, "requested directly")
+        } else {
+            write!(f, "required by ")?;
+            for (ix, (p, v)) in self.chain.iter().enumerate() {
+                if ix > 0 {
+                    write!(f, " <- ")?;
+                }
+                write!(f, "{} v{}", p, v)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
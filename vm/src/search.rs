@@ -0,0 +1,136 @@
+//! The module search path is the list of directories that module and package files are loaded
+//! from, in order of preference. Module files can be stored directly in a search directory, or in
+//! nested subdirectories that mirror the module path, so `std::io#1.0.0.om` could also be found as
+//! `std/io#1.0.0.om` (or `std/std::io#1.0.0.om`) underneath any search directory.
+use std::path::PathBuf;
+
+/// The environment variable containing the default module search directories, separated by `:`
+pub const MODULE_PATH_VAR: &str = "OXLR_MODULE_PATH";
+
+/// A list of directories to search for module files
+#[derive(Debug, Clone, Default)]
+pub struct SearchPath {
+    dirs: Vec<PathBuf>
+}
+
+/// A file in a module search directory that may contain modules
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ModuleFile {
+    /// A single encoded module, possibly with submodules
+    Module(PathBuf),
+    /// A package archive
+    Package(PathBuf)
+}
+
+/// A module file found while scanning a search directory, described by its file name
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub file: ModuleFile,
+    /// The path of the module in the file, or the package's root module
+    pub name: ir::Path,
+    pub version: ir::Version
+}
+
+impl SearchPath {
+    /// The default search path, which contains each directory listed in `OXLR_MODULE_PATH`
+    /// followed by the current directory. If the variable is not set, only the current directory
+    /// is searched.
+    pub fn from_env() -> SearchPath {
+        let mut dirs: Vec<PathBuf> = match std::env::var_os(MODULE_PATH_VAR) {
+            Some(v) => std::env::split_paths(&v).filter(|d| !d.as_os_str().is_empty()).collect(),
+            None => {
+                log::info!("{} is not set, only searching the current directory for modules", MODULE_PATH_VAR);
+                Vec::new()
+            }
+        };
+        match std::env::current_dir() {
+            Ok(d) => dirs.push(d),
+            Err(e) => log::warn!("could not get current directory to search for modules: {}", e)
+        }
+        SearchPath { dirs }
+    }
+
+    /// The directories in this search path, in order of preference
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Add a directory to search after all the others
+    pub fn push(&mut self, dir: impl Into<PathBuf>) {
+        self.dirs.push(dir.into());
+    }
+
+    /// Add all the directories of another search path after the ones in this one
+    pub fn extend(&mut self, other: SearchPath) {
+        self.dirs.extend(other.dirs);
+    }
+
+    /// Each directory that could contain files for the module at `path` in order of preference,
+    /// along with the module path prefix that the directory represents
+    pub fn candidate_dirs(&self, path: &ir::Path) -> Vec<(PathBuf, ir::Path)> {
+        let mut out = Vec::new();
+        for dir in self.dirs.iter() {
+            out.push((dir.clone(), ir::Path(Vec::new())));
+            let mut sub = dir.clone();
+            for k in 1..path.len() {
+                sub.push(&path[k-1].0);
+                if !sub.is_dir() {
+                    break;
+                }
+                out.push((sub.clone(), path.subpath(path.len() - k)));
+            }
+        }
+        out
+    }
+}
+
+/// find all module files in a directory. Files in a nested directory are named relative to the
+/// module path `prefix` that the directory represents, unless their name already includes it
+pub fn scan_dir(dir: &std::path::Path, prefix: &ir::Path) -> Vec<FileEntry> {
+    match std::fs::read_dir(dir) {
+        Ok(rd) => rd.filter_map(|e| match e {
+            Ok(e) => parse_module_file_name(&e, prefix),
+            Err(e) => {
+                log::warn!("error reading module directory {}: {}", dir.display(), e);
+                None
+            }
+        }).collect(),
+        Err(e) => {
+            log::warn!("could not read module directory {}: {}", dir.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+fn parse_module_file_name(dir_entry: &std::fs::DirEntry, prefix: &ir::Path) -> Option<FileEntry> {
+    let filename = dir_entry.file_name().into_string().ok()?;
+    let (filename, is_package) = if let Some(f) = filename.strip_suffix(".om") {
+        (f, false)
+    } else if let Some(f) = filename.strip_suffix(ir::package::PACKAGE_EXTENSION).and_then(|f| f.strip_suffix('.')) {
+        (f, true)
+    } else {
+        return None;
+    };
+    let (fpath, fver) = filename.split_once('#')?;
+    let mut name = ir::Path::from(fpath);
+    // modules in a nested directory are always below the module path it represents, so a name
+    // that isn't is relative to the directory, and `a/a#1.0.0.om` contains `a::a`
+    if name.len() <= prefix.len() || !name.starts_with(prefix) {
+        name = ir::Path(prefix.iter().chain(name.iter()).cloned().collect());
+    }
+    let version = ir::Version::parse(fver).ok()?;
+    log::trace!("found module file {} for {} {}", dir_entry.path().display(), name, version);
+    Some(FileEntry {
+        file: if is_package { ModuleFile::Package(dir_entry.path()) } else { ModuleFile::Module(dir_entry.path()) },
+        name, version
+    })
+}
+
+impl ModuleFile {
+    /// The location of this file in the filesystem
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            ModuleFile::Module(p) | ModuleFile::Package(p) => p
+        }
+    }
+}
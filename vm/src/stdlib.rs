@@ -0,0 +1,62 @@
+//! The standard library of built-in modules under `std`, which are implemented natively in the VM.
+//! They are added to a world like any other native module and all share the version of the VM, so
+//! programs import them with a requirement like `^0.1`.
+use anyhow::*;
+use crate::world::World;
+use crate::value::Value;
+use crate::native::NativeModule;
+
+pub mod io;
+pub mod env;
+pub mod time;
+pub mod math;
+pub mod string;
+pub mod collections;
+
+const UNIT: ir::Type = ir::Type::Unit;
+const BOOL: ir::Type = ir::Type::Bool;
+const U64: ir::Type = ir::Type::Int { signed: false, width: 64 };
+const I64: ir::Type = ir::Type::Int { signed: true, width: 64 };
+const F64: ir::Type = ir::Type::Float { width: 64 };
+
+/// the version of every module in the standard library
+pub fn version() -> ir::Version {
+    ir::Version::parse(env!("CARGO_PKG_VERSION")).expect("crate version is a valid version")
+}
+
+/// all of the standard library modules. `args` are the program arguments available from `std::env`
+pub fn modules(args: Vec<String>) -> Vec<NativeModule> {
+    vec![
+        io::module(),
+        env::module(args),
+        time::module(),
+        math::module(),
+        string::module(),
+        collections::module()
+    ]
+}
+
+/// add the standard library to a world
+pub fn install(world: &World, args: Vec<String>) -> Result<()> {
+    for m in modules(args) {
+        world.add_native_module(m)?;
+    }
+    Ok(())
+}
+
+/// a type parameter, which native functions accept any value for
+fn any(name: &str) -> ir::Type {
+    ir::Type::Var(ir::Symbol(name.into()))
+}
+
+fn sig(args: &[(&str, ir::Type)], return_type: ir::Type) -> ir::FunctionSignature {
+    ir::FunctionSignature {
+        args: args.iter().map(|(n, t)| (t.clone(), ir::Symbol(n.to_string()))).collect(),
+        return_type
+    }
+}
+
+/// convert argument `i` to a Rust value. The number of arguments has already been checked
+fn arg<T: TryFrom<Value, Error = Error>>(args: &[Value], i: usize) -> Result<T> {
+    args[i].clone().try_into()
+}
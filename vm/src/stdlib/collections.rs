@@ -0,0 +1,114 @@
+//! `std::collections`: growable lists and maps with string keys, which can hold values of any type.
+//! Collections are stored by the VM and referred to by a `u64` handle. A collection stays alive
+//! until it is freed with `list_free` or `map_free`, which also drops the values it holds. Handles
+//! are never reused, so using one after it is freed is an error
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use anyhow::*;
+use crate::value::Value;
+use crate::native::{NativeModule, NativeContext, string_type};
+use super::{sig, arg, any, version, U64, UNIT, BOOL};
+
+#[derive(Default)]
+struct Collections {
+    lists: HashMap<u64, Vec<Value>>,
+    maps: HashMap<u64, HashMap<String, Value>>,
+    /// the handle of the next collection that is made
+    next_handle: u64
+}
+
+impl Collections {
+    /// a handle that no collection has had before
+    fn handle(&mut self) -> u64 {
+        self.next_handle += 1;
+        self.next_handle - 1
+    }
+
+    /// the list whose handle is the first argument
+    fn list(&mut self, a: &[Value]) -> Result<&mut Vec<Value>> {
+        let h: u64 = arg(a, 0)?;
+        self.lists.get_mut(&h).ok_or_else(|| anyhow!("invalid list handle {}", h))
+    }
+
+    /// the map whose handle is the first argument
+    fn map(&mut self, a: &[Value]) -> Result<&mut HashMap<String, Value>> {
+        let h: u64 = arg(a, 0)?;
+        self.maps.get_mut(&h).ok_or_else(|| anyhow!("invalid map handle {}", h))
+    }
+}
+
+type CollectionFn = fn(&mut Collections, &mut NativeContext, &[Value]) -> Result<Value>;
+
+/// make a native function that operates on the shared collections
+fn with(c: &Rc<RefCell<Collections>>, f: CollectionFn) -> impl Fn(&mut NativeContext, Vec<Value>) -> Result<Value> {
+    let c = c.clone();
+    move |cx, a| f(&mut c.borrow_mut(), cx, &a)
+}
+
+fn index_error(i: u64, len: usize) -> Error {
+    anyhow!("index {} out of range for list of length {}", i, len)
+}
+
+pub fn module() -> NativeModule {
+    let s = string_type;
+    let c = Rc::new(RefCell::new(Collections::default()));
+    NativeModule::new(ir::Path::from("std::collections"), version())
+        .function("list_new", sig(&[], U64), with(&c, |c, _, _| {
+            let h = c.handle();
+            c.lists.insert(h, Vec::new());
+            Ok(Value::from(h))
+        }))
+        .function("list_free", sig(&[("list", U64)], UNIT), with(&c, |c, _, a| {
+            let h: u64 = arg(a, 0)?;
+            c.lists.remove(&h).ok_or_else(|| anyhow!("invalid list handle {}", h))?;
+            Ok(Value::Nil)
+        }))
+        .function("list_len", sig(&[("list", U64)], U64), with(&c, |c, _, a| Ok(Value::from(c.list(a)?.len() as u64))))
+        .function("list_push", sig(&[("list", U64), ("value", any("T"))], UNIT), with(&c, |c, _, a| {
+            c.list(a)?.push(a[1].clone());
+            Ok(Value::Nil)
+        }))
+        .function("list_pop", sig(&[("list", U64)], any("T")), with(&c, |c, _, a| {
+            c.list(a)?.pop().ok_or_else(|| anyhow!("pop from empty list"))
+        }))
+        .function("list_get", sig(&[("list", U64), ("index", U64)], any("T")), with(&c, |c, _, a| {
+            let i: u64 = arg(a, 1)?;
+            let l = c.list(a)?;
+            l.get(i as usize).cloned().ok_or_else(|| index_error(i, l.len()))
+        }))
+        .function("list_set", sig(&[("list", U64), ("index", U64), ("value", any("T"))], UNIT), with(&c, |c, _, a| {
+            let i: u64 = arg(a, 1)?;
+            let l = c.list(a)?;
+            let len = l.len();
+            *l.get_mut(i as usize).ok_or_else(|| index_error(i, len))? = a[2].clone();
+            Ok(Value::Nil)
+        }))
+        .function("map_new", sig(&[], U64), with(&c, |c, _, _| {
+            let h = c.handle();
+            c.maps.insert(h, HashMap::new());
+            Ok(Value::from(h))
+        }))
+        .function("map_free", sig(&[("map", U64)], UNIT), with(&c, |c, _, a| {
+            let h: u64 = arg(a, 0)?;
+            c.maps.remove(&h).ok_or_else(|| anyhow!("invalid map handle {}", h))?;
+            Ok(Value::Nil)
+        }))
+        .function("map_len", sig(&[("map", U64)], U64), with(&c, |c, _, a| Ok(Value::from(c.map(a)?.len() as u64))))
+        .function("map_insert", sig(&[("map", U64), ("key", s()), ("value", any("T"))], UNIT), with(&c, |c, cx, a| {
+            let key = cx.string(&a[1])?;
+            c.map(a)?.insert(key, a[2].clone());
+            Ok(Value::Nil)
+        }))
+        .function("map_get", sig(&[("map", U64), ("key", s())], any("T")), with(&c, |c, cx, a| {
+            let key = cx.string(&a[1])?;
+            c.map(a)?.get(&key).cloned().ok_or_else(|| anyhow!("key {:?} is not in map", key))
+        }))
+        .function("map_contains", sig(&[("map", U64), ("key", s())], BOOL), with(&c, |c, cx, a| {
+            let key = cx.string(&a[1])?;
+            Ok(Value::Bool(c.map(a)?.contains_key(&key)))
+        }))
+        .function("map_remove", sig(&[("map", U64), ("key", s())], UNIT), with(&c, |c, cx, a| {
+            let key = cx.string(&a[1])?;
+            c.map(a)?.remove(&key);
+            Ok(Value::Nil)
+        }))
+}
@@ -0,0 +1,28 @@
+//! `std::env`: program arguments and environment variables
+use std::rc::Rc;
+use anyhow::*;
+use crate::value::Value;
+use crate::native::{NativeModule, string_type};
+use super::{sig, arg, version, U64, BOOL};
+
+pub fn module(args: Vec<String>) -> NativeModule {
+    let s = string_type;
+    let args = Rc::new(args);
+    let count_args = args.clone();
+    NativeModule::new(ir::Path::from("std::env"), version())
+        .function("arg_count", sig(&[], U64), move |_, _| Ok(Value::from(count_args.len() as u64)))
+        .function("arg", sig(&[("index", U64)], s()), move |cx, a| {
+            let i: u64 = arg(&a, 0)?;
+            let v = args.get(i as usize)
+                .ok_or_else(|| anyhow!("argument index {} is out of range, there are {} arguments", i, args.len()))?;
+            cx.alloc_string(v)
+        })
+        // the value of an environment variable, or an empty string if it is not set
+        .function("var", sig(&[("name", s())], s()), |cx, a| {
+            let v = std::env::var(cx.string(&a[0])?).unwrap_or_default();
+            cx.alloc_string(&v)
+        })
+        .function("has_var", sig(&[("name", s())], BOOL), |cx, a| {
+            Ok(Value::Bool(std::env::var_os(cx.string(&a[0])?).is_some()))
+        })
+}
@@ -0,0 +1,46 @@
+//! `std::io`: the standard streams and files
+use std::io::Write;
+use anyhow::*;
+use crate::value::Value;
+use crate::native::{NativeModule, NativeContext, string_type};
+use super::{sig, version, UNIT, BOOL};
+
+fn write_to(mut out: impl Write, cx: &NativeContext, args: &[Value], newline: bool) -> Result<Value> {
+    let s = cx.string(&args[0])?;
+    if newline {
+        writeln!(out, "{}", s)?;
+    } else {
+        write!(out, "{}", s)?;
+        out.flush()?;
+    }
+    Ok(Value::Nil)
+}
+
+pub fn module() -> NativeModule {
+    let s = string_type;
+    NativeModule::new(ir::Path::from("std::io"), version())
+        .function("print", sig(&[("s", s())], UNIT), |cx, args| write_to(std::io::stdout(), cx, &args, false))
+        .function("println", sig(&[("s", s())], UNIT), |cx, args| write_to(std::io::stdout(), cx, &args, true))
+        .function("eprint", sig(&[("s", s())], UNIT), |cx, args| write_to(std::io::stderr(), cx, &args, false))
+        .function("eprintln", sig(&[("s", s())], UNIT), |cx, args| write_to(std::io::stderr(), cx, &args, true))
+        // reads a line from stdin without the line ending, or an empty string at the end of input
+        .function("read_line", sig(&[], s()), |cx, _| {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).context("reading from stdin")?;
+            let line = line.strip_suffix('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)).unwrap_or(&line);
+            cx.alloc_string(line)
+        })
+        .function("read_file", sig(&[("path", s())], s()), |cx, args| {
+            let path = cx.string(&args[0])?;
+            let contents = std::fs::read_to_string(&path).with_context(|| format!("reading file {}", path))?;
+            cx.alloc_string(&contents)
+        })
+        .function("write_file", sig(&[("path", s()), ("contents", s())], UNIT), |cx, args| {
+            let path = cx.string(&args[0])?;
+            std::fs::write(&path, cx.string(&args[1])?).with_context(|| format!("writing file {}", path))?;
+            Ok(Value::Nil)
+        })
+        .function("file_exists", sig(&[("path", s())], BOOL), |cx, args| {
+            Ok(Value::Bool(std::path::Path::new(&cx.string(&args[0])?).exists()))
+        })
+}
@@ -0,0 +1,25 @@
+//! `std::math`: floating point functions and integer helpers
+use crate::value::Value;
+use crate::native::NativeModule;
+use super::{sig, arg, version, F64, I64};
+
+type FloatFn = fn(f64) -> f64;
+
+pub fn module() -> NativeModule {
+    let float_fns: [(&str, FloatFn); 9] = [
+        ("sqrt", f64::sqrt), ("sin", f64::sin), ("cos", f64::cos), ("tan", f64::tan),
+        ("exp", f64::exp), ("ln", f64::ln), ("floor", f64::floor), ("ceil", f64::ceil), ("round", f64::round)
+    ];
+    let mut m = NativeModule::new(ir::Path::from("std::math"), version())
+        .function("pi", sig(&[], F64), |_, _| Ok(Value::from(std::f64::consts::PI)))
+        .function("pow", sig(&[("x", F64), ("y", F64)], F64), |_, a| {
+            Ok(Value::from(arg::<f64>(&a, 0)?.powf(arg(&a, 1)?)))
+        })
+        .function("abs", sig(&[("x", I64)], I64), |_, a| Ok(Value::from(arg::<i64>(&a, 0)?.wrapping_abs())))
+        .function("min", sig(&[("a", I64), ("b", I64)], I64), |_, a| Ok(Value::from(arg::<i64>(&a, 0)?.min(arg(&a, 1)?))))
+        .function("max", sig(&[("a", I64), ("b", I64)], I64), |_, a| Ok(Value::from(arg::<i64>(&a, 0)?.max(arg(&a, 1)?))));
+    for (name, f) in float_fns {
+        m = m.function(name, sig(&[("x", F64)], F64), move |_, a| Ok(Value::from(f(arg(&a, 0)?))));
+    }
+    m
+}
@@ -0,0 +1,54 @@
+//! `std::string`: operations on strings, and the `ToString` interface for converting values to
+//! strings. Strings are arrays of bytes containing UTF-8 text, and indices are in bytes
+use std::collections::HashMap;
+use anyhow::*;
+use crate::value::Value;
+use crate::native::{NativeModule, string_type};
+use super::{sig, arg, any, version, U64, I64, F64, BOOL};
+
+pub fn module() -> NativeModule {
+    let s = string_type;
+    let mut m = NativeModule::new(ir::Path::from("std::string"), version())
+        .function("len", sig(&[("s", s())], U64), |cx, a| Ok(Value::from(cx.string(&a[0])?.len() as u64)))
+        .function("concat", sig(&[("a", s()), ("b", s())], s()), |cx, a| {
+            let joined = cx.string(&a[0])? + &cx.string(&a[1])?;
+            cx.alloc_string(&joined)
+        })
+        .function("eq", sig(&[("a", s()), ("b", s())], BOOL), |cx, a| Ok(Value::Bool(cx.string(&a[0])? == cx.string(&a[1])?)))
+        .function("substring", sig(&[("s", s()), ("start", U64), ("end", U64)], s()), |cx, a| {
+            let st = cx.string(&a[0])?;
+            let (start, end) = (arg::<u64>(&a, 1)? as usize, arg::<u64>(&a, 2)? as usize);
+            let sub = st.get(start..end)
+                .ok_or_else(|| anyhow!("invalid substring range {}..{} of string with length {}", start, end, st.len()))?;
+            cx.alloc_string(sub)
+        })
+        // the index of the first occurrence of `needle`, or -1 if it does not occur
+        .function("find", sig(&[("s", s()), ("needle", s())], I64), |cx, a| {
+            Ok(Value::from(cx.string(&a[0])?.find(&cx.string(&a[1])?).map_or(-1, |i| i as i64)))
+        })
+        .function("parse_int", sig(&[("s", s())], I64), |cx, a| {
+            let st = cx.string(&a[0])?;
+            Ok(Value::from(st.trim().parse::<i64>().with_context(|| format!("parsing integer from {:?}", st))?))
+        })
+        .function("parse_float", sig(&[("s", s())], F64), |cx, a| {
+            let st = cx.string(&a[0])?;
+            Ok(Value::from(st.trim().parse::<f64>().with_context(|| format!("parsing float from {:?}", st))?))
+        })
+        .function("from_u64", sig(&[("x", U64)], s()), |cx, a| cx.alloc_string(&arg::<u64>(&a, 0)?.to_string()))
+        .function("from_i64", sig(&[("x", I64)], s()), |cx, a| cx.alloc_string(&arg::<i64>(&a, 0)?.to_string()))
+        .function("from_f64", sig(&[("x", F64)], s()), |cx, a| cx.alloc_string(&arg::<f64>(&a, 0)?.to_string()))
+        .function("from_bool", sig(&[("x", BOOL)], s()), |cx, a| cx.alloc_string(&arg::<bool>(&a, 0)?.to_string()));
+
+    let to_string = ir::Symbol("to_string".into());
+    let mut functions = HashMap::new();
+    functions.insert(to_string.clone(), sig(&[("self", any("Self"))], s()));
+    m.module.interfaces.insert(ir::Symbol("ToString".into()), ir::Interface {
+        name: ir::Symbol("ToString".into()),
+        functions
+    });
+    for (ty, f) in [(U64, "from_u64"), (I64, "from_i64"), (F64, "from_f64"), (BOOL, "from_bool")] {
+        m.module.implementations.insert((ty, ir::Path::from("std::string::ToString")),
+            HashMap::from([(to_string.clone(), ir::Symbol(f.into()))]));
+    }
+    m
+}
@@ -0,0 +1,21 @@
+//! `std::time`: clocks and sleeping
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::value::Value;
+use crate::native::NativeModule;
+use super::{sig, arg, version, U64, UNIT};
+
+pub fn module() -> NativeModule {
+    let start = Instant::now();
+    NativeModule::new(ir::Path::from("std::time"), version())
+        // milliseconds since the Unix epoch
+        .function("now_millis", sig(&[], U64), |_, _| {
+            let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Ok(Value::from(since_epoch.as_millis() as u64))
+        })
+        // nanoseconds since the standard library was loaded, from a clock that never goes backwards
+        .function("elapsed_nanos", sig(&[], U64), move |_, _| Ok(Value::from(start.elapsed().as_nanos() as u64)))
+        .function("sleep_millis", sig(&[("millis", U64)], UNIT), |_, a| {
+            std::thread::sleep(Duration::from_millis(arg(&a, 0)?));
+            Ok(Value::Nil)
+        })
+}
@@ -11,14 +11,110 @@ pub enum Value {
 }
 
 impl Value {
-    pub fn type_of(&self, mem: &crate::memory::Memory) -> ir::Type {
+    pub fn type_of(&self, _mem: &crate::memory::Memory) -> ir::Type {
         match self {
             Value::Nil => ir::Type::Unit,
             Value::Bool(_) => ir::Type::Bool,
             Value::Int(i) => ir::Type::Int { signed: i.signed, width: i.width },
             Value::Float(f) => ir::Type::Float { width: f.width() },
             Value::Ref(v) => v.type_of().clone(),
-            Value::Fn => ir::Type::FnRef(todo!()),
+            Value::Fn => todo!("type of function values"),
         }
     }
 }
+
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::Nil
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(f: f32) -> Value {
+        Value::Float(Float::F32(f))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Value {
+        Value::Float(Float::F64(f))
+    }
+}
+
+impl TryFrom<Value> for () {
+    type Error = anyhow::Error;
+
+    fn try_from(v: Value) -> anyhow::Result<()> {
+        match v {
+            Value::Nil => Ok(()),
+            v => Err(anyhow::anyhow!("expected unit, got {:?}", v))
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = anyhow::Error;
+
+    fn try_from(v: Value) -> anyhow::Result<bool> {
+        match v {
+            Value::Bool(b) => Ok(b),
+            v => Err(anyhow::anyhow!("expected bool, got {:?}", v))
+        }
+    }
+}
+
+impl TryFrom<Value> for f32 {
+    type Error = anyhow::Error;
+
+    fn try_from(v: Value) -> anyhow::Result<f32> {
+        match v {
+            Value::Float(Float::F32(f)) => Ok(f),
+            v => Err(anyhow::anyhow!("expected f32, got {:?}", v))
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = anyhow::Error;
+
+    fn try_from(v: Value) -> anyhow::Result<f64> {
+        match v {
+            Value::Float(Float::F64(f)) => Ok(f),
+            v => Err(anyhow::anyhow!("expected f64, got {:?}", v))
+        }
+    }
+}
+
+/// conversions between Rust integers and integer values of the same width and signedness. Signed
+/// values are stored sign extended, like the result of negating an integer
+macro_rules! int_conversions {
+    ($($t:ty: $width:expr, $signed:expr;)*) => {$(
+        impl From<$t> for Value {
+            fn from(i: $t) -> Value {
+                Value::Int(Integer::new($width, $signed, i as i64 as u64))
+            }
+        }
+
+        impl TryFrom<Value> for $t {
+            type Error = anyhow::Error;
+
+            fn try_from(v: Value) -> anyhow::Result<$t> {
+                match v {
+                    Value::Int(Integer { width: $width, signed: $signed, data }) => Ok(data as $t),
+                    v => Err(anyhow::anyhow!("expected {}, got {:?}", stringify!($t), v))
+                }
+            }
+        }
+    )*};
+}
+
+int_conversions! {
+    u8: 8, false; u16: 16, false; u32: 32, false; u64: 64, false;
+    i8: 8, true; i16: 16, true; i32: 32, true; i64: 64, true;
+}
@@ -1,120 +1,446 @@
-use std::{collections::HashMap};
+use std::{collections::{HashMap, HashSet}, cell::{Cell, RefCell}, rc::Rc};
 use itertools::Itertools;
-use anyhow::*;
+use anyhow::{anyhow, bail, Context, Result};
+use crate::resolve::{Resolver, ResolveOptions, ResolveError, unversioned_path};
+use crate::search::SearchPath;
+use crate::lockfile::{Lockfile, LockEntry};
+use crate::deps::ImportGraph;
+use crate::native::{NativeModule, NativeFunction};
+use crate::error::ErrorKind;
+
+/// The file that a module was loaded from
+#[derive(Debug, Clone)]
+pub struct ModuleOrigin {
+    pub file: std::path::PathBuf,
+    /// The exact version and hash of the file, as recorded in a lockfile
+    pub lock: LockEntry
+}
+
+/// A function in a loaded module
+#[derive(Debug, Clone)]
+pub enum Function {
+    /// A function with an IR body. The module stays alive while the function is in use, even if it
+    /// is unloaded from the world
+    Ir { module: Rc<ir::Module>, name: ir::Symbol },
+    /// A function implemented by the host
+    Native { path: ir::Path, f: Rc<NativeFunction> }
+}
+
+impl Function {
+    pub fn signature(&self) -> &ir::FunctionSignature {
+        match self {
+            Function::Ir { module, name } => &module.functions[name].0,
+            Function::Native { f, .. } => &f.signature
+        }
+    }
+
+    /// the full path of the function
+    pub fn path(&self) -> ir::Path {
+        match self {
+            Function::Ir { module, name } => {
+                let mut p = module.path.clone();
+                p.0.push(name.clone());
+                p
+            },
+            Function::Native { path, .. } => path.clone()
+        }
+    }
+
+    /// the body of the function, or `None` if it is a native function
+    pub fn body(&self) -> Option<&ir::FnBody> {
+        match self {
+            Function::Ir { module, name } => Some(&module.functions[name].1),
+            Function::Native { .. } => None
+        }
+    }
+}
 
 pub struct World {
-    global_module_path: std::path::PathBuf,
-    local_module_path: std::path::PathBuf,
-    modules: HashMap<ir::Path, ir::Module>,
+    /// The directories that modules are loaded from
+    pub search_path: SearchPath,
+    pub resolve_options: ResolveOptions,
+    /// Only load imported modules when they are first used, instead of when the importing module
+    /// is loaded
+    pub lazy_loading: bool,
+    modules: RefCell<HashMap<ir::Path, Rc<ir::Module>>>,
+    /// Where each module loaded from the search path came from, by the path it is loaded under
+    origins: RefCell<HashMap<ir::Path, ModuleOrigin>>,
+    /// Modules that were loaded directly rather than imported, which keep their imports loaded
+    roots: RefCell<HashSet<ir::Path>>,
+    /// Imports of loaded modules that have not been loaded yet, with every requirement on them
+    deferred: RefCell<HashMap<ir::Path, ir::VersionReq>>,
+    /// Functions provided by the host, by path
+    natives: RefCell<HashMap<ir::Path, Rc<NativeFunction>>>,
+    /// Counts changes to the loaded modules, so that anything cached from them can be discarded
+    generation: Cell<u64>,
+    #[allow(dead_code)]
     instantiated_types: HashMap<(ir::Path, Vec<ir::Type>), ir::TypeDefinition>
 }
 
-fn test_module_file_candidate(dir_entry: &std::fs::DirEntry, path: &ir::Path, version_req: &ir::VersionReq) -> Option<std::path::PathBuf> {
-    let mut filename = dir_entry.file_name().into_string().ok()?;
-    if !filename.ends_with(".om") {
-        return None;
-    } else {
-        filename.truncate(filename.len() - 3)
-    }
-    log::trace!("testing {} as candidate for module", dir_entry.path().display());
-    let (fpath, fver) = filename.split_once('#')?;
-    let fpath = ir::Path::from(fpath);
-    let fver = ir::Version::parse(fver).ok()?;
-    log::trace!("candidate yielded: {} {}", fpath, fver);
-    if fpath == *path && version_req.matches(&fver) {
-        log::trace!("matched");
-        Some(dir_entry.path())
-    } else {
-        None
+/// move a module and all of its nested submodules into a flat list, checking that each submodule is
+/// directly contained in its parent
+pub fn flatten_module_tree(mut m: ir::Module, out: &mut Vec<ir::Module>) -> Result<()> {
+    for sm in std::mem::take(&mut m.submodules) {
+        if sm.path.len() != m.path.len() + 1 || !sm.path.starts_with(&m.path) {
+            bail!("submodule {} is not directly contained in module {}", sm.path, m.path);
+        }
+        flatten_module_tree(sm, out)?;
     }
+    out.push(m);
+    Ok(())
 }
 
 impl World {
-    pub fn new() -> Result<World> {
-        Ok(World {
-            global_module_path: std::env::var("OXLR_MODULE_PATH").map(std::path::PathBuf::from)?,
-            local_module_path: std::env::current_dir()?,
-            modules: HashMap::new(),
+    pub fn new(search_path: SearchPath) -> World {
+        World {
+            search_path,
+            resolve_options: ResolveOptions::default(),
+            lazy_loading: false,
+            modules: RefCell::new(HashMap::new()),
+            origins: RefCell::new(HashMap::new()),
+            roots: RefCell::new(HashSet::new()),
+            deferred: RefCell::new(HashMap::new()),
+            natives: RefCell::new(HashMap::new()),
+            generation: Cell::new(0),
             instantiated_types: HashMap::new()
-        })
+        }
+    }
+
+    /// a number that changes whenever modules are loaded, unloaded or reloaded, which changes what
+    /// paths refer to
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    fn changed(&self) {
+        self.generation.set(self.generation.get() + 1);
     }
 
-    /// get a module, loading it from the filesystem if necessary by searching the module search paths
-    pub fn load_module<'s>(&mut self, path: &'s ir::Path, version: &ir::VersionReq) -> Result<()> {
-        assert!(path.len() > 0);
-        if let Some(m) = self.modules.get(path) {
-            if version.matches(&m.version) {
-                Ok(())
-            } else {
-                Err(anyhow!("mismatched versions of module {} required. version loaded: {}, version required: {}", path, m.version, version))
+    /// get a module, loading it from the filesystem if necessary by searching the module search
+    /// paths. All of the modules it imports are resolved together, see [`Resolver`]. The module
+    /// stays loaded until it is unloaded with [`World::unload_module`]
+    pub fn load_module(&self, path: &ir::Path, version: &ir::VersionReq) -> Result<()> {
+        self.load(path, version)?;
+        self.roots.borrow_mut().insert(path.clone());
+        Ok(())
+    }
+
+    fn load(&self, path: &ir::Path, version: &ir::VersionReq) -> Result<()> {
+        assert!(!path.is_empty());
+        log::trace!("resolving module {} {} in {:?}", path, version, self.search_path.dirs());
+        let options = ResolveOptions { shallow: self.lazy_loading, ..self.resolve_options.clone() };
+        let mut resolver = Resolver::new(self.search_path.clone(), options);
+        let resolution = resolver.resolve(self.modules.borrow().iter().map(|(p, m)| (p, m.as_ref())), path, version)?;
+        let mut modules = Vec::new();
+        for rm in resolution.modules {
+            self.origins.borrow_mut().insert(rm.module.path.clone(), ModuleOrigin { file: rm.file, lock: rm.lock });
+            modules.push(rm.module);
+        }
+        self.add_modules(modules)
+    }
+
+    /// a lockfile recording every module that has been loaded from the module search path. Modules
+    /// added directly to the world are not included
+    pub fn lockfile(&self) -> Lockfile {
+        Lockfile { entries: self.origins.borrow().values().map(|o| o.lock.clone()).collect() }
+    }
+
+    /// the graph of imports between all loaded modules
+    pub fn import_graph(&self) -> ImportGraph {
+        let mut g = ImportGraph::default();
+        let origins = self.origins.borrow();
+        for (p, m) in self.modules.borrow().iter() {
+            g.add(m, None, origins.get(p).map(|o| o.file.clone()));
+        }
+        g
+    }
+
+    /// add a module to the world along with all of its submodules, loading any modules they import
+    /// that are not part of the same module tree
+    pub fn add_module(&self, m: ir::Module) -> Result<()> {
+        let path = m.path.clone();
+        self.add_modules(vec![m])?;
+        self.roots.borrow_mut().insert(path);
+        Ok(())
+    }
+
+    /// add a module of functions provided by the host. It is loaded like any other module, so
+    /// other modules can import it
+    pub fn add_native_module(&self, nm: NativeModule) -> Result<()> {
+        for name in nm.functions.keys() {
+            if nm.module.functions.contains_key(name) {
+                bail!("native module {} defines function {} twice", nm.module.path, name.0);
             }
-        } else {
-            for mod_file in
-                std::fs::read_dir(&self.global_module_path)?
-                    .filter_map(|re| re.map(|e| test_module_file_candidate(&e, path, version)).transpose())
-                    .chain(std::fs::read_dir(&self.local_module_path)?
-                                .filter_map(|re| re.map(|e| test_module_file_candidate(&e, path, version)).transpose()))
-                    .map(|rfp| rfp.and_then(|fp| Ok((std::fs::File::open(&fp)?, fp))))
-            {
-                match mod_file {
-                    Ok((f, fp)) => {
-                        let mp: Result<ir::Module, _> = rmp_serde::from_read(f);
-                        match mp {
-                            Ok(m) => {
-                                if m.path == *path && version.matches(&m.version) {
-                                    for (import_path, import_version) in m.imports.iter() {
-                                        self.load_module(import_path, import_version)?;
-                                    }
-                                    self.modules.insert(path.clone(), m);
-                                    return Ok(());
-                                }
-                            },
-                            Err(e) => log::warn!("tried to search for module {} v{}, got error decoding file {}: {}",
-                                path, version, fp.display(), e)
-                        }
-                    },
-                    Err(e) => log::error!("tried to search for module {} v{}, got error in process: {}", path, version, e)
+        }
+        let path = nm.module.path.clone();
+        self.add_module(nm.module)?;
+        self.natives.borrow_mut().extend(nm.functions.into_iter().map(|(name, f)| {
+            let mut fp = path.clone();
+            fp.0.push(name);
+            (fp, f)
+        }));
+        self.changed();
+        Ok(())
+    }
+
+    /// add a set of modules and their submodules to the world together, so that they may import
+    /// each other, loading any other modules they import or deferring them if loading lazily
+    fn add_modules(&self, ms: Vec<ir::Module>) -> Result<()> {
+        let mut tree = Vec::new();
+        for mut m in ms {
+            m.resolve_relative_paths()?;
+            flatten_module_tree(m, &mut tree)?;
+        }
+        self.load_imports(&tree)?;
+        let mut modules = self.modules.borrow_mut();
+        for m in tree {
+            if modules.contains_key(&m.path) {
+                bail!("module {} is already loaded", m.path);
+            }
+            self.deferred.borrow_mut().remove(&m.path);
+            modules.insert(m.path.clone(), Rc::new(m));
+        }
+        self.changed();
+        Ok(())
+    }
+
+    /// load or defer every module imported by a tree of modules that is not part of the tree
+    fn load_imports(&self, tree: &[ir::Module]) -> Result<()> {
+        for (import_path, import_version) in tree.iter().flat_map(|m| m.imports.iter()) {
+            if let Some(im) = tree.iter().find(|m| m.path == *import_path) {
+                if !import_version.matches(&im.version) {
+                    bail!("mismatched versions of module {} required. version contained: {}, version required: {}",
+                        import_path, im.version, import_version);
+                }
+                continue;
+            }
+            let loaded = self.modules.borrow().get(import_path).map(|m| m.version.clone());
+            match loaded {
+                Some(v) if import_version.matches(&v) => {},
+                None if self.lazy_loading => self.defer(import_path, import_version),
+                _ => self.load(import_path, import_version)?
+            }
+        }
+        Ok(())
+    }
+
+    /// replace a loaded module with the newest version in the search path that has the same major
+    /// version, along with the other modules from the same file. Calls made after the reload use
+    /// the new function bodies, while functions that are already running finish with the old ones.
+    /// Every type in the old modules must be unchanged in the new ones, so that existing values
+    /// stay valid. Returns the new version, or `None` if there is no newer version
+    pub fn reload_module(&self, path: &ir::Path) -> Result<Option<ir::Version>> {
+        if unversioned_path(path) != *path {
+            bail!("cannot reload module {}, which is loaded alongside a different major version", path);
+        }
+        let old_version = self.modules.borrow().get(path)
+            .map(|m| m.version.clone())
+            .ok_or_else(|| anyhow!("cannot reload module {}, which is not loaded", path))?;
+        // modules from the same file are replaced together
+        let old_paths: Vec<ir::Path> = {
+            let origins = self.origins.borrow();
+            let file = origins.get(path).map(|o| &o.file);
+            self.modules.borrow().keys()
+                .filter(|p| match file {
+                    Some(f) => origins.get(*p).is_some_and(|o| o.file == *f),
+                    None => p.starts_with(path)
+                })
+                .cloned().collect()
+        };
+        let req = ir::VersionReq::parse(&format!(">{}, <{}.0.0", old_version, old_version.major + 1))?;
+        log::trace!("looking for a newer version of {} matching {}", path, req);
+        let options = ResolveOptions { shallow: true, ..self.resolve_options.clone() };
+        let mut resolver = Resolver::new(self.search_path.clone(), options);
+        let resolution = resolver.resolve(self.modules.borrow().iter()
+            .filter(|(p, _)| !old_paths.contains(p))
+            .map(|(p, m)| (p, m.as_ref())), path, &req);
+        let resolution = match resolution {
+            Ok(r) => r,
+            Err(e) if matches!(e.downcast_ref(), Some(ResolveError::Unsatisfiable { .. })) => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        let mut tree = Vec::new();
+        let mut origins = Vec::new();
+        for rm in resolution.modules {
+            let mut m = rm.module;
+            m.resolve_relative_paths()?;
+            origins.push((m.path.clone(), ModuleOrigin { file: rm.file, lock: rm.lock }));
+            flatten_module_tree(m, &mut tree)?;
+        }
+        let new_version = tree.iter().find(|m| m.path == *path).expect("reloaded module is in resolution").version.clone();
+        self.check_reload(&old_paths, &tree)
+            .with_context(|| format!("reloading module {} v{} as v{}", path, old_version, new_version))?;
+        self.load_imports(&tree)?;
+        let mut modules = self.modules.borrow_mut();
+        let mut all_origins = self.origins.borrow_mut();
+        for p in old_paths.iter() {
+            modules.remove(p);
+            all_origins.remove(p);
+        }
+        all_origins.extend(origins);
+        for m in tree {
+            log::info!("reloaded module {} v{}", m.path, m.version);
+            self.deferred.borrow_mut().remove(&m.path);
+            modules.insert(m.path.clone(), Rc::new(m));
+        }
+        self.changed();
+        Ok(Some(new_version))
+    }
+
+    /// check that a set of modules can replace the loaded modules at `old_paths`
+    fn check_reload(&self, old_paths: &[ir::Path], tree: &[ir::Module]) -> Result<()> {
+        let modules = self.modules.borrow();
+        for p in old_paths {
+            let old = &modules[p];
+            let new = tree.iter().find(|m| m.path == *p)
+                .ok_or_else(|| anyhow!("module {} is missing from the new version", p))?;
+            if new.version.major != old.version.major {
+                bail!("module {} changed major version from v{} to v{}", p, old.version, new.version);
+            }
+            for (name, td) in old.types.iter() {
+                match new.types.get(name) {
+                    Some(ntd) if ntd == td => {},
+                    Some(_) => bail!("type {}::{} has changed", p, name.0),
+                    None => bail!("type {}::{} has been removed", p, name.0)
+                }
+            }
+        }
+        for (p, m) in modules.iter().filter(|(p, _)| !old_paths.contains(p)) {
+            for (ip, req) in m.imports.iter() {
+                if let Some(new) = tree.iter().find(|n| n.path == *ip) {
+                    if !req.matches(&new.version) {
+                        bail!("module {} requires {} {}, which does not match v{}", p, ip, req, new.version);
+                    }
                 }
             }
-            Err(anyhow!("could not find module {} v{}", path, version))
         }
+        Ok(())
     }
 
-    pub fn get_module(&self, path: &ir::Path) -> Option<&ir::Module> {
-        self.modules.get(path)
+    /// record an import to load when the module is first used, combining it with any other
+    /// requirements on the same module
+    fn defer(&self, path: &ir::Path, version: &ir::VersionReq) {
+        log::trace!("deferring load of {} {}", path, version);
+        self.deferred.borrow_mut().entry(path.clone())
+            .and_modify(|r| r.comparators.extend(version.comparators.iter().cloned()))
+            .or_insert_with(|| version.clone());
+    }
+
+    /// stop keeping a module loaded, then unload every module that is no longer reachable by
+    /// imports from the remaining modules that were loaded directly. Functions from unloaded
+    /// modules that are still running continue until they return. Returns the paths of the
+    /// modules that were unloaded
+    pub fn unload_module(&self, path: &ir::Path) -> Vec<ir::Path> {
+        self.roots.borrow_mut().remove(path);
+        self.unload_unreachable()
+    }
+
+    /// unload every module that is not reachable from a module that was loaded directly, by
+    /// imports, by the paths in its code or by being an ancestor of a reachable module, returning
+    /// their paths
+    pub fn unload_unreachable(&self) -> Vec<ir::Path> {
+        let mut modules = self.modules.borrow_mut();
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<ir::Path> = self.roots.borrow().iter().cloned().collect();
+        while let Some(p) = stack.pop() {
+            if !reachable.insert(p.clone()) {
+                continue;
+            }
+            if let Some(m) = modules.get(&p) {
+                stack.extend(m.imports.iter().map(|(ip, _)| ip.clone()));
+                // a module can use the other modules of its tree without importing them, so the
+                // module of every path its code refers to is reachable too
+                let mut used = Vec::new();
+                (**m).clone().for_each_path_mut(&mut |up| {
+                    used.push(up.subpath(1));
+                    Ok(())
+                }).expect("collecting paths cannot fail");
+                stack.extend(used.into_iter().filter(|up| !up.is_empty()));
+            }
+            // a submodule is loaded from the same file as its ancestors, which stay loaded with it.
+            // Its siblings and their submodules are only kept if something else reaches them
+            if p.len() > 1 {
+                stack.push(p.subpath(1));
+            }
+        }
+        let unloaded: Vec<ir::Path> = modules.keys().filter(|p| !reachable.contains(*p)).cloned().collect();
+        for p in unloaded.iter() {
+            log::debug!("unloading module {}", p);
+            modules.remove(p);
+            self.origins.borrow_mut().remove(p);
+            self.natives.borrow_mut().retain(|fp, _| fp.subpath(1) != *p);
+        }
+        let mut deferred = self.deferred.borrow_mut();
+        deferred.retain(|p, _| modules.values().any(|m| m.imports.iter().any(|(ip, _)| ip == p)));
+        if !unloaded.is_empty() {
+            self.changed();
+        }
+        unloaded
+    }
+
+    /// get a loaded module. If it has not been loaded yet but is imported by a loaded module, it
+    /// is loaded now
+    pub fn get_module(&self, path: &ir::Path) -> Result<Option<Rc<ir::Module>>> {
+        if let Some(m) = self.modules.borrow().get(path) {
+            return Ok(Some(m.clone()));
+        }
+        let req = self.deferred.borrow().get(path).cloned();
+        match req {
+            Some(req) => {
+                log::debug!("loading module {} {} on first use", path, req);
+                self.load(path, &req).with_context(|| format!("loading imported module {}", path))?;
+                Ok(self.modules.borrow().get(path).cloned())
+            },
+            None => Ok(None)
+        }
     }
 
     /// look up a type definition by path
-    pub fn get_type(&self, path: &ir::Path) -> Option<&ir::TypeDefinition> {
-        let m = self.get_module(&path.subpath(1))?;
-        m.types.get(path.last())
+    pub fn get_type(&self, path: &ir::Path) -> Result<Option<ir::TypeDefinition>> {
+        Ok(self.get_module(&path.subpath(1))?
+            .and_then(|m| m.types.get(path.last()).cloned()))
     }
 
     /// look up an interface by path
-    pub fn get_interface(&self, path: &ir::Path) -> Option<&ir::Interface> {
-        let m = self.get_module(&path.subpath(1))?;
-        m.interfaces.get(path.last())
+    pub fn get_interface(&self, path: &ir::Path) -> Result<Option<ir::Interface>> {
+        Ok(self.get_module(&path.subpath(1))?
+            .and_then(|m| m.interfaces.get(path.last()).cloned()))
     }
 
     /// look up a function by path
-    pub fn get_function(&self, path: &ir::Path) -> Option<&(ir::FunctionSignature, ir::FnBody)> {
-        let m = self.get_module(&path.subpath(1))?;
-        m.functions.get(path.last())
+    pub fn get_function(&self, path: &ir::Path) -> Result<Option<Function>> {
+        Ok(self.get_module(&path.subpath(1))?
+            .and_then(|module| self.function_in(module, path.last())))
+    }
+
+    /// look up a function by name in a loaded module
+    fn function_in(&self, module: Rc<ir::Module>, name: &ir::Symbol) -> Option<Function> {
+        if module.functions.contains_key(name) {
+            return Some(Function::Ir { module, name: name.clone() });
+        }
+        let mut path = module.path.clone();
+        path.0.push(name.clone());
+        let f = self.natives.borrow().get(&path).cloned()?;
+        Some(Function::Native { path, f })
     }
 
     /// look up the implementation function specific to type `ty` for the interface function `interface_fn`
-    pub fn find_impl(&self, interface_fn: &ir::Path, ty: &ir::Type) -> Option<&(ir::FunctionSignature, ir::FnBody)> {
+    pub fn find_impl(&self, interface_fn: &ir::Path, ty: &ir::Type) -> Result<Option<Function>> {
         let if_path = interface_fn.subpath(1);
         let fn_name = interface_fn.last();
-        let m = self.get_module(&interface_fn.subpath(2))?;
+        let m = match self.get_module(&interface_fn.subpath(2))? {
+            Some(m) => m,
+            None => return Ok(None)
+        };
         let fn_sym = m.implementations.get(&(ty.clone(), if_path))
-            .and_then(|m| m.get(fn_name))?;
-        m.functions.get(fn_sym)
+            .and_then(|m| m.get(fn_name))
+            .cloned();
+        Ok(fn_sym.and_then(|name| self.function_in(m, &name)))
     }
 
     pub fn size_of_user_type(&self, td: &ir::TypeDefinition, params: &Option<Vec<ir::Type>>) -> Result<usize> {
-        if let Some(_) = params {
-            todo!("compute the size of a specialized generic type");
+        if params.is_some() {
+            bail!(ErrorKind::trap(ir::Trap::Unsupported, "computing the size of a specialized generic type"));
         } else {
             match td {
                 ir::TypeDefinition::Sum { variants, .. } => {
@@ -153,16 +479,46 @@ impl World {
                 }
                 size
             },
-            Type::User(def_path, params) => self.get_type(def_path)
+            Type::User(def_path, params) => self.get_type(def_path)?
                 .ok_or_else(|| anyhow!("unknown type ty={:?}", ty))
-                .and_then(|t| self.size_of_user_type(t, params))?,
+                .and_then(|t| self.size_of_user_type(&t, params))?,
             Type::FnRef(_) => 0, //for now not sure what we'll actually store here
-            Type::Var(_) => panic!(),
+            Type::Var(_) => bail!(unsized_type(ty)),
         })
     }
 
+    /// get the offset in bytes of a field within a value of type `ty`, and the type of the field
+    pub fn field_offset(&self, ty: &ir::Type, field: &ir::Symbol) -> Result<(usize, ir::Type)> {
+        let path = match ty {
+            ir::Type::User(path, None) => path,
+            ir::Type::User(_, Some(_)) => bail!(ErrorKind::trap(ir::Trap::Unsupported, "field lookup in generic types")),
+            t => bail!(ErrorKind::trap(ir::Trap::TypeMismatch, format!("cannot look up field {} in type {:?}", field.0, t)))
+        };
+        match self.get_type(path)? {
+            // should probably check what the field name is?
+            Some(ir::TypeDefinition::NewType(ty)) => Ok((0, ty)),
+            Some(ir::TypeDefinition::Sum { .. }) =>
+                bail!(ErrorKind::trap(ir::Trap::TypeMismatch, format!("cannot look up field {} in sum type {}", field.0, path))),
+            Some(ir::TypeDefinition::Product { fields, .. }) => {
+                let mut offset = 0;
+                for (name, t) in fields.iter() {
+                    let ralign = self.required_alignment(t)?;
+                    while offset % ralign != 0 { offset += 1; }
+                    if name == field {
+                        return Ok((offset, t.clone()));
+                    }
+                    offset += self.size_of_type(t)?;
+                }
+                bail!(ErrorKind::trap(ir::Trap::TypeMismatch, format!("field {} not defined on type {}", field.0, path)))
+            },
+            None => bail!("unknown type, path = {}, field = {:?}", path, field)
+        }
+    }
+
     pub fn array_size(&self, el_ty: &ir::Type, count: usize) -> Result<usize> {
-        Ok(std::mem::size_of::<usize>() + self.size_of_type(el_ty)?*count)
+        self.size_of_type(el_ty)?.checked_mul(count)
+            .and_then(|s| s.checked_add(std::mem::size_of::<usize>()))
+            .ok_or_else(|| ErrorKind::trap(ir::Trap::OutOfMemory, format!("array of {} x {:?} is too large", count, el_ty)).into())
     }
 
     pub fn required_alignment(&self, ty: &ir::Type) -> Result<usize> {
@@ -176,7 +532,11 @@ impl World {
             // guess, but is it the correct one? I'm not sure
             Type::Ref(_) | Type::AbstractRef(_) | Type::Array(_)
                 | Type::User(_,_) | Type::Tuple(_) | Type::FnRef(_) => std::mem::align_of::<*mut u8>(),
-            Type::Var(_) => panic!(),
+            Type::Var(_) => bail!(unsized_type(ty)),
         })
     }
 }
+
+fn unsized_type(ty: &ir::Type) -> ErrorKind {
+    ErrorKind::trap(ir::Trap::InvalidCode, format!("type parameter {:?} has no size", ty))
+}
@@ -0,0 +1,102 @@
+Module(
+    path: Path([Symbol("aot")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // fills an array with the numbers below n and adds them up
+        Symbol("sum"): (
+            FunctionSignature(args: [(Int(width: 64, signed: false), Symbol("n"))], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 9,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            AllocArray(Register(1), Int(width: 64, signed: false), Reg(Register(0)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(2), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(5))) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(2)), Reg(Register(0))),
+                            Br(cond: Reg(Register(3)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            StoreIndex(Register(1), Reg(Register(2)), Reg(Register(2))),
+                            BinaryOp(Add, Register(5), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [],
+                        next_block: 4
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(6), [ (3, LiteralInt(Integer(width: 64, signed: false, data: 0))), (5, Reg(Register(8))) ]),
+                            Phi(Register(7), [ (3, LiteralInt(Integer(width: 64, signed: false, data: 0))), (5, Reg(Register(4))) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(6)), Reg(Register(0))),
+                            Br(cond: Reg(Register(3)), if_true: 6, if_false: 5)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            LoadIndex(Register(4), Register(1), Reg(Register(6))),
+                            BinaryOp(Add, Register(4), Reg(Register(7)), Reg(Register(4))),
+                            BinaryOp(Add, Register(8), Reg(Register(6)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 4
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(7)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            // also compiled to an executable by run.sh, which must behave the same
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 6,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("std"), Symbol("string"), Symbol("concat")]), [LiteralString("hello "), LiteralString("from native code")]),
+                            Call(Register(1), Path([Symbol("std"), Symbol("io"), Symbol("println")]), [Reg(Register(0))]),
+                            Call(Register(2), Path([Symbol("aot"), Symbol("sum")]), [LiteralInt(Integer(width: 64, signed: false, data: 10))]),
+                            Call(Register(3), Path([Symbol("std"), Symbol("string"), Symbol("from_u64")]), [Reg(Register(2))]),
+                            Call(Register(4), Path([Symbol("std"), Symbol("string"), Symbol("eq")]), [Reg(Register(3)), LiteralString("45")]),
+                            Br(cond: Reg(Register(4)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("std"), Symbol("io")]), "^0.1"),
+        (Path([Symbol("std"), Symbol("string")]), "^0.1")
+    ]
+)
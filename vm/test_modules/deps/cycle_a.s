@@ -0,0 +1,29 @@
+Module(
+    path: Path([Symbol("cycle_a")]),
+    version: "1.0.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // cycle_b imports this module back, so it only loads with --allow-import-cycles
+                            Call(Register(0), Path([Symbol("cycle_b"), Symbol("value")]), []),
+                            BinaryOp(Sub, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("cycle_b")]), "^1.0")
+    ]
+)
@@ -0,0 +1,44 @@
+Module(
+    path: Path([Symbol("inline_dep")]),
+    version: "1.0.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("square"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Mul, Register(1), Reg(Register(0)), Reg(Register(0))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // calls square through a relative path, which still names it once cube is inlined elsewhere
+        Symbol("cube"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(1), Path([Symbol("self"), Symbol("square")]), [ Reg(Register(0)) ]),
+                            BinaryOp(Mul, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [],
+    // the functions will always compute the same results, so other packages may inline them
+    inlinable: true
+)
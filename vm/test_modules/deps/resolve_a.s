@@ -0,0 +1,26 @@
+Module(
+    path: Path([Symbol("resolve_a")]),
+    version: "1.0.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("value"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 0,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("resolve_shared")]), "^1.0")
+    ]
+)
@@ -0,0 +1,81 @@
+Module(
+    path: Path([Symbol("escape")]),
+    version: "0.0.1",
+    types: {
+        Symbol("pair"): Product(
+            parameters: [],
+            fields: [
+                (Symbol("a"), Int(signed: false, width: 64)),
+                (Symbol("b"), Int(signed: false, width: 64)),
+            ]
+        )
+    },
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // only reads through its argument, so references passed to it don't escape
+        Symbol("sum"): (
+            FunctionSignature(args: [ (Ref(User(Path([Symbol("escape"), Symbol("pair")]), None)), Symbol("p")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 4,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            LoadField(Register(1), Register(0), Symbol("a")),
+                            LoadField(Register(2), Register(0), Symbol("b")),
+                            BinaryOp(Add, Register(3), Reg(Register(1)), Reg(Register(2))),
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // the pair is returned, so it has to stay on the heap
+        Symbol("make"): (
+            FunctionSignature(args: [], return_type: Ref(User(Path([Symbol("escape"), Symbol("pair")]), None))),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Alloc(Register(0), User(Path([Symbol("escape"), Symbol("pair")]), None)),
+                            StoreField(LiteralInt(Integer(width: 64, signed: false, data: 1)), Register(0), Symbol("a")),
+                            StoreField(LiteralInt(Integer(width: 64, signed: false, data: 1)), Register(0), Symbol("b")),
+                            Return(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // the pair and the array in register 5 never leave start, so they can be moved onto the
+        // stack, but the pair from make can't
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 9,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Alloc(Register(0), User(Path([Symbol("escape"), Symbol("pair")]), None)),
+                            StoreField(LiteralInt(Integer(width: 64, signed: false, data: 2)), Register(0), Symbol("a")),
+                            StoreField(LiteralInt(Integer(width: 64, signed: false, data: 3)), Register(0), Symbol("b")),
+                            Call(Register(1), Path([Symbol("escape"), Symbol("sum")]), [ Reg(Register(0)) ]),
+                            Call(Register(2), Path([Symbol("escape"), Symbol("make")]), []),
+                            Call(Register(3), Path([Symbol("escape"), Symbol("sum")]), [ Reg(Register(2)) ]),
+                            BinaryOp(Add, Register(4), Reg(Register(1)), Reg(Register(3))),
+                            AllocArray(Register(5), Int(width: 64, signed: false), LiteralInt(Integer(width: 64, signed: false, data: 2))),
+                            StoreIndex(Register(5), LiteralInt(Integer(width: 64, signed: false, data: 1)), Reg(Register(4))),
+                            LoadIndex(Register(6), Register(5), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            BinaryOp(Sub, Register(7), Reg(Register(6)), LiteralInt(Integer(width: 64, signed: false, data: 7))),
+                            Return(Reg(Register(7)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
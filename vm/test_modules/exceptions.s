@@ -0,0 +1,100 @@
+Module(
+    path: Path([Symbol("exceptions")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("throw_it"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")) ], return_type: Unit),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Throw(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("fails"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // the stack allocation is released when the exception passes through this function
+                            StackAlloc(Register(1), Int(width: 64, signed: false)),
+                            Call(Register(2), Path([Symbol("exceptions"), Symbol("throw_it")]), [ Reg(Register(0)) ]),
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("succeeds"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 0,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 5,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(0), func: Path([Symbol("exceptions"), Symbol("fails")]),
+                                args: [ LiteralInt(Integer(width: 64, signed: false, data: 7)) ],
+                                normal: 1, unwind: 2, exception: Register(1))
+                        ],
+                        next_block: 0
+                    ),
+                    // not reached, since fails always throws
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(2), Reg(Register(1)), LiteralInt(Integer(width: 64, signed: false, data: 7))),
+                            Br(cond: Reg(Register(2)), if_true: 3, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // a function that returns normally continues in the normal block
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(3), func: Path([Symbol("exceptions"), Symbol("succeeds")]),
+                                args: [], normal: 4, unwind: 1, exception: Register(4))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [],
+    submodules: []
+)
@@ -0,0 +1,44 @@
+Module(
+    path: Path([Symbol("inlining")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // inline_dep is marked inlinable, so oxlr-opt inlines cube (and square inside it) and
+        // folds its result, but resolve_a is not, so value is still called
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 4,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("inline_dep"), Symbol("cube")]), [ LiteralInt(Integer(width: 64, signed: false, data: 3)) ]),
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 27))),
+                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(2), Path([Symbol("resolve_a"), Symbol("value")]), []),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("inline_dep")]), "^1.0"),
+        (Path([Symbol("resolve_a")]), "^1.0")
+    ]
+)
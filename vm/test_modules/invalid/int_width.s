@@ -0,0 +1,27 @@
+Module(
+    path: Path([Symbol("int_width")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // a zero bit integer has no values, so none of the arithmetic on it can be done
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            UnaryOp(Neg, Register(0), LiteralInt(Integer(width: 0, signed: true, data: 0))),
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [],
+    submodules: []
+)
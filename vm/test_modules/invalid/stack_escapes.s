@@ -0,0 +1,113 @@
+Module(
+    path: Path([Symbol("stack_escapes")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // returns its argument, which is fine on its own
+        Symbol("id"): (
+            FunctionSignature(args: [ (Ref(Int(width: 64, signed: false)), Symbol("x")) ], return_type: Ref(Int(width: 64, signed: false))),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // the stack allocation outlives the function inside the array that is returned
+        Symbol("in_array"): (
+            FunctionSignature(args: [], return_type: Ref(Array(Ref(Int(width: 64, signed: false))))),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            StackAlloc(Register(0), Int(width: 64, signed: false)),
+                            AllocArray(Register(1), Ref(Int(width: 64, signed: false)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            StoreIndex(Register(1), LiteralInt(Integer(width: 64, signed: false, data: 0)), Reg(Register(0))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // stores its second argument in memory from the first, so it outlives the call
+        Symbol("keep"): (
+            FunctionSignature(args: [ (Ref(Ref(Int(width: 64, signed: false))), Symbol("into")), (Ref(Int(width: 64, signed: false)), Symbol("x")) ], return_type: Unit),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            StoreRef(Register(0), Reg(Register(1))),
+                            Return(LiteralUnit)
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // the stack allocation is kept by the function it is passed to
+        Symbol("kept"): (
+            FunctionSignature(args: [], return_type: Ref(Ref(Int(width: 64, signed: false)))),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Alloc(Register(0), Ref(Int(width: 64, signed: false))),
+                            StackAlloc(Register(1), Int(width: 64, signed: false)),
+                            Call(Register(2), Path([Symbol("stack_escapes"), Symbol("keep")]), [ Reg(Register(0)), Reg(Register(1)) ]),
+                            Return(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // the stack allocation is returned by the function it is passed to, and then from this one
+        Symbol("leak"): (
+            FunctionSignature(args: [], return_type: Ref(Int(width: 64, signed: false))),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            StackAlloc(Register(0), Int(width: 64, signed: false)),
+                            Call(Register(1), Path([Symbol("stack_escapes"), Symbol("id")]), [ Reg(Register(0)) ]),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // the stack allocation is returned after going through another one
+        Symbol("loaded_back"): (
+            FunctionSignature(args: [], return_type: Ref(Int(width: 64, signed: false))),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            StackAlloc(Register(0), Int(width: 64, signed: false)),
+                            StackAllocArray(Register(1), Ref(Int(width: 64, signed: false)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            StoreIndex(Register(1), LiteralInt(Integer(width: 64, signed: false, data: 0)), Reg(Register(0))),
+                            LoadIndex(Register(2), Register(1), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
@@ -0,0 +1,378 @@
+Module(
+    path: Path([Symbol("jit")]),
+    version: "0.0.1",
+    types: {
+        Symbol("point"): Product(
+            parameters: [],
+            fields: [
+                (Symbol("x"), Int(width: 64, signed: false)),
+                (Symbol("y"), Int(width: 64, signed: false)),
+            ]
+        )
+    },
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // adds up the numbers below n in a loop with phis
+        Symbol("sum"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 6,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(1), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(4))) ]),
+                            Phi(Register(2), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(5))) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(1)), Reg(Register(0))),
+                            Br(cond: Reg(Register(3)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(5), Reg(Register(2)), Reg(Register(1))),
+                            BinaryOp(Add, Register(4), Reg(Register(1)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // moves a point n times, adding 2 to x and 1 to y each time, and returns x + y
+        Symbol("walk"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 12,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Alloc(Register(1), User(Path([Symbol("jit"), Symbol("point")]), None)),
+                            StoreField(LiteralInt(Integer(width: 64, signed: false, data: 0)), Register(1), Symbol("x")),
+                            StoreField(LiteralInt(Integer(width: 64, signed: false, data: 0)), Register(1), Symbol("y"))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(2), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(8))) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(2)), Reg(Register(0))),
+                            Br(cond: Reg(Register(3)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            LoadField(Register(4), Register(1), Symbol("x")),
+                            BinaryOp(Add, Register(5), Reg(Register(4)), LiteralInt(Integer(width: 64, signed: false, data: 2))),
+                            StoreField(Reg(Register(5)), Register(1), Symbol("x")),
+                            LoadField(Register(6), Register(1), Symbol("y")),
+                            BinaryOp(Add, Register(7), Reg(Register(6)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            StoreField(Reg(Register(7)), Register(1), Symbol("y")),
+                            BinaryOp(Add, Register(8), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            LoadField(Register(9), Register(1), Symbol("x")),
+                            LoadField(Register(10), Register(1), Symbol("y")),
+                            BinaryOp(Add, Register(11), Reg(Register(9)), Reg(Register(10))),
+                            Return(Reg(Register(11)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // fills an array with the squares of the numbers below n and adds them up, reading them
+        // back with get
+        Symbol("squares"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 9,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            AllocArray(Register(1), Int(width: 64, signed: false), Reg(Register(0)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(2), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(8))) ]),
+                            Phi(Register(3), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(7))) ]),
+                            BinaryOp(Eq, Register(4), Reg(Register(2)), Reg(Register(0))),
+                            Br(cond: Reg(Register(4)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Mul, Register(5), Reg(Register(2)), Reg(Register(2))),
+                            StoreIndex(Register(1), Reg(Register(2)), Reg(Register(5))),
+                            Call(Register(6), Path([Symbol("jit"), Symbol("get")]), [ Reg(Register(1)), Reg(Register(2)) ]),
+                            BinaryOp(Add, Register(7), Reg(Register(3)), Reg(Register(6))),
+                            BinaryOp(Add, Register(8), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("get"): (
+            FunctionSignature(args: [
+                    (Array(Int(width: 64, signed: false)), Symbol("a")),
+                    (Int(width: 64, signed: false), Symbol("i"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            LoadIndex(Register(2), Register(0), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("fib"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 8,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Br(cond: Reg(Register(1)), if_true: 3, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(2), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Br(cond: Reg(Register(2)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Sub, Register(3), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Call(Register(4), Path([Symbol("jit"), Symbol("fib")]), [ Reg(Register(3)) ]),
+                            BinaryOp(Sub, Register(5), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 2))),
+                            Call(Register(6), Path([Symbol("jit"), Symbol("fib")]), [ Reg(Register(5)) ]),
+                            BinaryOp(Add, Register(7), Reg(Register(4)), Reg(Register(6))),
+                            Return(Reg(Register(7)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("divide"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("a")),
+                    (Int(width: 64, signed: false), Symbol("b"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Div, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // adds up i / d for the numbers i below n
+        Symbol("divs"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n")),
+                    (Int(width: 64, signed: false), Symbol("d"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 8,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(2), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(7))) ]),
+                            Phi(Register(3), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(6))) ]),
+                            BinaryOp(Eq, Register(4), Reg(Register(2)), Reg(Register(0))),
+                            Br(cond: Reg(Register(4)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(5), Path([Symbol("jit"), Symbol("divide")]), [ Reg(Register(2)), Reg(Register(1)) ]),
+                            BinaryOp(Add, Register(6), Reg(Register(3)), Reg(Register(5))),
+                            BinaryOp(Add, Register(7), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // each function is called often enough to be compiled, and the later calls run the compiled
+        // code, which must give the same results and traps as the interpreter
+        Symbol("start"): (
+            FunctionSignature(args: [],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 18,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("jit"), Symbol("sum")]), [ LiteralInt(Integer(width: 64, signed: false, data: 3000)) ]),
+                            Call(Register(1), Path([Symbol("jit"), Symbol("sum")]), [ LiteralInt(Integer(width: 64, signed: false, data: 3000)) ]),
+                            BinaryOp(Eq, Register(2), Reg(Register(1)), LiteralInt(Integer(width: 64, signed: false, data: 4498500))),
+                            Br(cond: Reg(Register(2)), if_true: 1, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(3), Path([Symbol("jit"), Symbol("walk")]), [ LiteralInt(Integer(width: 64, signed: false, data: 2000)) ]),
+                            Call(Register(3), Path([Symbol("jit"), Symbol("walk")]), [ LiteralInt(Integer(width: 64, signed: false, data: 2000)) ]),
+                            BinaryOp(Eq, Register(4), Reg(Register(3)), LiteralInt(Integer(width: 64, signed: false, data: 6000))),
+                            Br(cond: Reg(Register(4)), if_true: 2, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(5), Path([Symbol("jit"), Symbol("squares")]), [ LiteralInt(Integer(width: 64, signed: false, data: 1500)) ]),
+                            Call(Register(5), Path([Symbol("jit"), Symbol("squares")]), [ LiteralInt(Integer(width: 64, signed: false, data: 1500)) ]),
+                            BinaryOp(Eq, Register(6), Reg(Register(5)), LiteralInt(Integer(width: 64, signed: false, data: 1123875250))),
+                            Br(cond: Reg(Register(6)), if_true: 3, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(7), Path([Symbol("jit"), Symbol("fib")]), [ LiteralInt(Integer(width: 64, signed: false, data: 20)) ]),
+                            BinaryOp(Eq, Register(8), Reg(Register(7)), LiteralInt(Integer(width: 64, signed: false, data: 6765))),
+                            Br(cond: Reg(Register(8)), if_true: 4, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(9), Path([Symbol("jit"), Symbol("divs")]), [ LiteralInt(Integer(width: 64, signed: false, data: 1500)), LiteralInt(Integer(width: 64, signed: false, data: 1)) ]),
+                            Call(Register(9), Path([Symbol("jit"), Symbol("divs")]), [ LiteralInt(Integer(width: 64, signed: false, data: 1500)), LiteralInt(Integer(width: 64, signed: false, data: 1)) ]),
+                            BinaryOp(Eq, Register(10), Reg(Register(9)), LiteralInt(Integer(width: 64, signed: false, data: 1124250))),
+                            Br(cond: Reg(Register(10)), if_true: 5, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            // a trap in compiled code unwinds through compiled callers like any other
+                            Invoke(dest: Register(11), func: Path([Symbol("jit"), Symbol("divs")]), args: [ LiteralInt(Integer(width: 64, signed: false, data: 5)), LiteralInt(Integer(width: 64, signed: false, data: 0)) ],
+                                normal: 10, unwind: 6, exception: Register(12))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(13), Reg(Register(12)), LiteralInt(Integer(width: 32, signed: false, data: 1))),
+                            Br(cond: Reg(Register(13)), if_true: 7, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            AllocArray(Register(14), Int(width: 64, signed: false), LiteralInt(Integer(width: 64, signed: false, data: 3))),
+                            Invoke(dest: Register(15), func: Path([Symbol("jit"), Symbol("get")]), args: [ Reg(Register(14)), LiteralInt(Integer(width: 64, signed: false, data: 3)) ],
+                                normal: 10, unwind: 8, exception: Register(16))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(17), Reg(Register(16)), LiteralInt(Integer(width: 32, signed: false, data: 3))),
+                            Br(cond: Reg(Register(17)), if_true: 9, if_false: 10)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
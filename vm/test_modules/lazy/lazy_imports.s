@@ -0,0 +1,32 @@
+Module(
+    path: Path([Symbol("lazy_imports")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // resolve_shared is only loaded here, when it is first called, so the
+                            // newest version is chosen
+                            Call(Register(0), Path([Symbol("resolve_shared"), Symbol("minor")]), []),
+                            BinaryOp(Sub, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 2))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("resolve_shared")]), "^1.0"),
+        // there is no module file for this import, but it is never used so it is never loaded
+        (Path([Symbol("lazy_missing")]), "^1.0")
+    ]
+)
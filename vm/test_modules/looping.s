@@ -18,10 +18,10 @@ Module(
                     ),
                     BasicBlock(
                         instrs: [
-                            Phi(Register(1), {
-                                0: Reg(Register(0)),
-                                1: Reg(Register(2))
-                            }),
+                            Phi(Register(1), [
+                                (0, Reg(Register(0))),
+                                (1, Reg(Register(2)))
+                            ]),
                             BinaryOp(Mul, Register(2), Reg(Register(1)),
                                 LiteralInt(Integer(width: 64, signed: false, data: 3))),
                             BinaryOp(Eq, Register(3), Reg(Register(2)),
@@ -0,0 +1,24 @@
+Module(
+    path: Path([Symbol("layout"), Symbol("layout")]),
+    version: "0.1.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("offset"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 0,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 7)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
@@ -0,0 +1,34 @@
+Module(
+    path: Path([Symbol("nested_dirs")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 4,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // layout::inner::leaf is stored in the layout/inner/ subdirectory of the search path
+                            Call(Register(0), Path([Symbol("layout"), Symbol("inner"), Symbol("leaf"), Symbol("minor")]), []),
+                            // layout::layout is stored under its short name as layout/layout#0.1.0.om,
+                            // which is not the module layout
+                            Call(Register(1), Path([Symbol("layout"), Symbol("layout"), Symbol("offset")]), []),
+                            BinaryOp(Add, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            BinaryOp(Sub, Register(3), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 49))),
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("layout"), Symbol("inner"), Symbol("leaf")]), "^0.3"),
+        (Path([Symbol("layout"), Symbol("layout")]), "^0.1")
+    ]
+)
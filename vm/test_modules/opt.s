@@ -0,0 +1,128 @@
+Module(
+    path: Path([Symbol("opt")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // the branch is on a constant, so after folding only block 1 is left, merged into block 0
+        // along with block 3, whose phi becomes a load of its value for block 1
+        Symbol("constant_branch"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Mul, Register(0), LiteralInt(Integer(width: 64, signed: false, data: 2)), LiteralInt(Integer(width: 64, signed: false, data: 3))),
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 6))),
+                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [],
+                        next_block: 3
+                    ),
+                    BasicBlock(
+                        instrs: [],
+                        next_block: 3
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(2), [ (1, Reg(Register(0))), (2, LiteralInt(Integer(width: 64, signed: false, data: 7))) ]),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // reads of the copies become reads of the argument
+        Symbol("copies"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("n")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 4,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            LoadImm(Register(1), Reg(Register(0))),
+                            LoadImm(Register(2), Reg(Register(1))),
+                            BinaryOp(Add, Register(3), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // dividing by a literal zero is not folded, so it still traps when it runs
+        Symbol("divide_by_zero"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            LoadImm(Register(0), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            BinaryOp(Div, Register(1), LiteralInt(Integer(width: 64, signed: false, data: 1)), Reg(Register(0))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 7,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("opt"), Symbol("constant_branch")]), []),
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 6))),
+                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 4)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(2), Path([Symbol("opt"), Symbol("copies")]), [ LiteralInt(Integer(width: 64, signed: false, data: 41)) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 42))),
+                            Br(cond: Reg(Register(3)), if_true: 2, if_false: 4)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(4), func: Path([Symbol("opt"), Symbol("divide_by_zero")]),
+                                args: [], normal: 4, unwind: 3, exception: Register(5))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(6), Reg(Register(5)), LiteralInt(Integer(width: 32, signed: false, data: 1))),
+                            Br(cond: Reg(Register(6)), if_true: 5, if_false: 4)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
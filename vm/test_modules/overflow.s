@@ -0,0 +1,128 @@
+Module(
+    path: Path([Symbol("overflow")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("add_i64"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: true), Symbol("x")), (Int(width: 64, signed: true), Symbol("y")) ], return_type: Int(width: 64, signed: true)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("div_i64"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: true), Symbol("x")), (Int(width: 64, signed: true), Symbol("y")) ], return_type: Int(width: 64, signed: true)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Div, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("mul_i32"): (
+            FunctionSignature(args: [ (Int(width: 32, signed: true), Symbol("x")), (Int(width: 32, signed: true), Symbol("y")) ], return_type: Int(width: 32, signed: true)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Mul, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("add_u8"): (
+            FunctionSignature(args: [ (Int(width: 8, signed: false), Symbol("x")), (Int(width: 8, signed: false), Symbol("y")) ], return_type: Int(width: 8, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            // arithmetic is checked at the width and signedness of its operands, both in the VM
+            // and in compiled code
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 8,
+                blocks: [
+                    // -1 + 1 is 0 in signed integers
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("overflow"), Symbol("add_i64")]), [ LiteralInt(Integer(width: 64, signed: true, data: 18446744073709551615)), LiteralInt(Integer(width: 64, signed: true, data: 1)) ]),
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: true, data: 0))),
+                            Br(cond: Reg(Register(1)), if_true: 2, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    // signed division rounds towards zero
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(2), Path([Symbol("overflow"), Symbol("div_i64")]), [ LiteralInt(Integer(width: 64, signed: true, data: 18446744073709551609)), LiteralInt(Integer(width: 64, signed: true, data: 2)) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: true, data: 18446744073709551613))),
+                            Br(cond: Reg(Register(3)), if_true: 3, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // the product of negative and positive 32 bit integers is negative
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(4), Path([Symbol("overflow"), Symbol("mul_i32")]), [ LiteralInt(Integer(width: 32, signed: true, data: 18446744073709551613)), LiteralInt(Integer(width: 32, signed: true, data: 2)) ]),
+                            BinaryOp(Eq, Register(5), Reg(Register(4)), LiteralInt(Integer(width: 32, signed: true, data: 18446744073709551610))),
+                            Br(cond: Reg(Register(5)), if_true: 4, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // 200 + 55 is the largest 8 bit unsigned integer
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(6), Path([Symbol("overflow"), Symbol("add_u8")]), [ LiteralInt(Integer(width: 8, signed: false, data: 200)), LiteralInt(Integer(width: 8, signed: false, data: 55)) ]),
+                            BinaryOp(Eq, Register(7), Reg(Register(6)), LiteralInt(Integer(width: 8, signed: false, data: 255))),
+                            Br(cond: Reg(Register(7)), if_true: 5, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
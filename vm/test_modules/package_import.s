@@ -0,0 +1,33 @@
+Module(
+    path: Path([Symbol("package_import")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 4,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // both functions come from the same package archive
+                            Call(Register(0), Path([Symbol("geometry"), Symbol("area")]),
+                                [ LiteralInt(Integer(width: 64, signed: false, data: 4)), LiteralInt(Integer(width: 64, signed: false, data: 5)) ]),
+                            Call(Register(1), Path([Symbol("geometry"), Symbol("util"), Symbol("mul")]),
+                                [ LiteralInt(Integer(width: 64, signed: false, data: 2)), LiteralInt(Integer(width: 64, signed: false, data: 10)) ]),
+                            BinaryOp(Sub, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("geometry"), Symbol("util")]), "^1.0"),
+        (Path([Symbol("geometry")]), "^1.0")
+    ]
+)
@@ -0,0 +1,32 @@
+Module(
+    path: Path([Symbol("geometry")]),
+    version: "1.2.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("area"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("w")),
+                    (Int(width: 64, signed: false), Symbol("h"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(2), Path([Symbol("self"), Symbol("util"), Symbol("mul")]), [ Reg(Register(0)), Reg(Register(1)) ]),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("self"), Symbol("util")]), "^1.2")
+    ]
+)
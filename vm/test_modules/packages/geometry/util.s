@@ -0,0 +1,30 @@
+Module(
+    path: Path([Symbol("geometry"), Symbol("util")]),
+    version: "1.2.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("mul"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("a")),
+                    (Int(width: 64, signed: false), Symbol("b"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Mul, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
@@ -0,0 +1,127 @@
+Module(
+    path: Path([Symbol("peval")]),
+    version: "0.1.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // x to the power of n, by multiplying n times in a loop
+        Symbol("power"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")), (Int(width: 64, signed: false), Symbol("n")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 7,
+                blocks: [
+                    BasicBlock(
+                        instrs: [],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(2), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 0))), (2, Reg(Register(5))) ]),
+                            Phi(Register(3), [ (0, LiteralInt(Integer(width: 64, signed: false, data: 1))), (2, Reg(Register(6))) ]),
+                            BinaryOp(Eq, Register(4), Reg(Register(2)), Reg(Register(1))),
+                            Br(cond: Reg(Register(4)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(5), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            BinaryOp(Mul, Register(6), Reg(Register(3)), Reg(Register(0)))
+                        ],
+                        next_block: 1
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("fib"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("n")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 8,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(2), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Br(cond: Reg(Register(2)), if_true: 3, if_false: 4)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Sub, Register(3), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            BinaryOp(Sub, Register(4), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 2))),
+                            Call(Register(5), Path([Symbol("peval"), Symbol("fib")]), [ Reg(Register(3)) ]),
+                            Call(Register(6), Path([Symbol("peval"), Symbol("fib")]), [ Reg(Register(4)) ]),
+                            BinaryOp(Add, Register(7), Reg(Register(5)), Reg(Register(6))),
+                            Return(Reg(Register(7)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // x to the power of n plus the (n + 7)th fibonacci number. oxlr-peval specializes this
+        // for n = 3 as peval::poly3 in the next version of this module, which
+        // specialized/peval_use.s calls
+        Symbol("poly"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")), (Int(width: 64, signed: false), Symbol("n")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 6,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(2), Path([Symbol("peval"), Symbol("power")]), [ Reg(Register(0)), Reg(Register(1)) ]),
+                            BinaryOp(Add, Register(3), Reg(Register(1)), LiteralInt(Integer(width: 64, signed: false, data: 7))),
+                            Call(Register(4), Path([Symbol("peval"), Symbol("fib")]), [ Reg(Register(3)) ]),
+                            BinaryOp(Add, Register(5), Reg(Register(2)), Reg(Register(4))),
+                            Return(Reg(Register(5)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("peval"), Symbol("poly")]), [ LiteralInt(Integer(width: 64, signed: false, data: 4)), LiteralInt(Integer(width: 64, signed: false, data: 3)) ]),
+                            BinaryOp(Sub, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 119))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
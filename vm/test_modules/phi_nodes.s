@@ -35,10 +35,10 @@ Module(
 
                     BasicBlock(
                         instrs: [
-                            Phi(Register(4), {
-                                1: Reg(Register(2)),
-                                2: Reg(Register(3))
-                            }),
+                            Phi(Register(4), [
+                                (1, Reg(Register(2))),
+                                (2, Reg(Register(3)))
+                            ]),
                             Return(Reg(Register(4)))
                         ],
                         next_block: 3
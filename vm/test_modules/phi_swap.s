@@ -0,0 +1,92 @@
+Module(
+    path: Path([Symbol("phi_swap")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // swaps a and b n times, then returns a. The phis are in the entry block, so on entry the
+        // registers keep the arguments, and each time round the loop they swap a and b together
+        Symbol("swap"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n")),
+                    (Int(width: 64, signed: false), Symbol("a")),
+                    (Int(width: 64, signed: false), Symbol("b"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 5,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Phi(Register(0), [ (1, Reg(Register(3))) ]),
+                            Phi(Register(1), [ (1, Reg(Register(2))) ]),
+                            Phi(Register(2), [ (1, Reg(Register(1))) ]),
+                            BinaryOp(Eq, Register(4), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Br(cond: Reg(Register(4)), if_true: 2, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Sub, Register(3), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 4,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("phi_swap"), Symbol("swap")]), [
+                                LiteralInt(Integer(width: 64, signed: false, data: 3)),
+                                LiteralInt(Integer(width: 64, signed: false, data: 10)),
+                                LiteralInt(Integer(width: 64, signed: false, data: 20))
+                            ]),
+                            BinaryOp(Eq, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 20))),
+                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 3)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(2), Path([Symbol("phi_swap"), Symbol("swap")]), [
+                                LiteralInt(Integer(width: 64, signed: false, data: 4)),
+                                LiteralInt(Integer(width: 64, signed: false, data: 10)),
+                                LiteralInt(Integer(width: 64, signed: false, data: 20))
+                            ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 10))),
+                            Br(cond: Reg(Register(3)), if_true: 2, if_false: 3)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
@@ -14,12 +14,12 @@ Module(
             ),
             // this code is sloppy and actually computes fib(n+2), oh well
             FnBody(
-                max_registers: 7,
+                max_registers: 8,
                 blocks: [
                     BasicBlock(
                         instrs: [
                             BinaryOp(Eq,  Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 0))),
-                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 2)
+                            Br(cond: Reg(Register(1)), if_true: 1, if_false: 3)
                         ],
                         next_block: 999
                     ),
@@ -41,6 +41,22 @@ Module(
                             Return(Reg(Register(6)))
                         ],
                         next_block: 999
+                    ),
+
+                    // n - 2 would go below zero
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq,  Register(7), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Br(cond: Reg(Register(7)), if_true: 4, if_false: 2)
+                        ],
+                        next_block: 999
+                    ),
+
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 2)))
+                        ],
+                        next_block: 999
                     )
                 ]
             )
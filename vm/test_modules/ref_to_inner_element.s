@@ -47,7 +47,7 @@ Module(
                             LoadIndex(Register(3), Register(0), LiteralInt(Integer(width: 64,
                                 signed: false, data: 1))),
                             BinaryOp(Sub, Register(4), Reg(Register(3)),
-                                LiteralInt(Integer(width: 64, signed: false, data: 10))),
+                                LiteralInt(Integer(width: 64, signed: false, data: 7))),
                             Return(Reg(Register(4)))
                        ],
                         next_block: 0
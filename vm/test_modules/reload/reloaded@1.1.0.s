@@ -0,0 +1,32 @@
+Module(
+    path: Path([Symbol("reloaded")]),
+    version: "1.1.0",
+    types: {
+        Symbol("point"): Product(
+            parameters: [],
+            fields: [
+                (Symbol("x"), Int(signed: false, width: 64)),
+            ]
+        )
+    },
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("value"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 0,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // a new body with the same types, which can replace 1.0.0
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
@@ -0,0 +1,33 @@
+Module(
+    path: Path([Symbol("reloaded")]),
+    version: "1.2.0",
+    types: {
+        Symbol("point"): Product(
+            parameters: [],
+            fields: [
+                (Symbol("x"), Int(signed: false, width: 64)),
+                (Symbol("y"), Int(signed: false, width: 64)),
+            ]
+        )
+    },
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("value"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 0,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // point has a new field, so this can't replace an older version
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 3)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: []
+)
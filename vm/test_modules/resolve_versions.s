@@ -0,0 +1,31 @@
+Module(
+    path: Path([Symbol("resolve_versions")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // resolve_a accepts any 1.x version of resolve_shared, but resolve_b
+                            // needs one older than 1.2, so 1.1.0 must be chosen over 1.2.0
+                            Call(Register(0), Path([Symbol("resolve_shared"), Symbol("minor")]), []),
+                            BinaryOp(Sub, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("resolve_a")]), "^1.0"),
+        (Path([Symbol("resolve_b")]), "^1.0")
+    ]
+)
@@ -1,18 +1,175 @@
 #!/bin/bash
+# stop at the first command that fails
+set -e
 
 cargo build --workspace
 ASM=../../target/debug/asm
 VM=../../target/debug/vm
+AOT=../../target/debug/oxlr-aot
+OPT=../../target/debug/oxlr-opt
+PEVAL=../../target/debug/oxlr-peval
+
+# run a command that must fail with an error containing a message
+expect_error() {
+    local message="$1"
+    shift
+    if ! "$@" 2>&1 | grep -F -- "$message" >/dev/null; then
+        echo "expected $* to fail with: $message"
+        exit 1
+    fi
+}
+
+# run the VM on a program that it must refuse, which prints an error line containing a message and
+# exits with code 1
+expect_vm_error() {
+    local message="$1"
+    shift
+    local code=0
+    local output
+    output=$($VM "$@" 2>&1) || code=$?
+    if [ "$code" -ne 1 ] || ! grep "^error: " <<< "$output" | grep -F -- "$message" >/dev/null; then
+        echo "expected vm $* to exit with 1 and the error: $message"
+        exit 1
+    fi
+}
+
+# run a command that must exit with a code
+expect_exit() {
+    local expected="$1"
+    shift
+    local code=0
+    "$@" || code=$?
+    if [ "$code" -ne "$expected" ]; then
+        echo "expected $* to exit with $expected, but it exited with $code"
+        exit 1
+    fi
+}
 
 # assemble test modules
 echo "==== Assembling test modules ===="
 mkdir -p /tmp/oxlr_test_modules
-find -type f -name "*.s" | xargs -I {} -- $ASM {} /tmp/oxlr_test_modules
+find -maxdepth 1 -type f -name "*.s" | xargs -I {} -- $ASM {} /tmp/oxlr_test_modules
+# modules under deps/ are only imported by other test modules, so they are assembled but not run
+find deps -type f -name "*.s" | xargs -I {} -- $ASM {} /tmp/oxlr_test_modules
+find lazy -type f -name "*.s" | xargs -I {} -- $ASM {} /tmp/oxlr_test_modules
+# modules under nested/ are assembled into the matching subdirectory of the module directory
+(cd nested && find -type f -name "*.s") | while read -r mod; do
+    mkdir -p "/tmp/oxlr_test_modules/$(dirname "$mod")"
+    $ASM "nested/$mod" "/tmp/oxlr_test_modules/$(dirname "$mod")"
+done
+# files in nested directories can also be named relative to the directory
+mv "/tmp/oxlr_test_modules/layout/layout::layout#0.1.0.om" "/tmp/oxlr_test_modules/layout/layout#0.1.0.om"
+# each directory under packages/ is assembled into a single package archive
+for pkg in packages/*/; do
+    $ASM --package /tmp/oxlr_test_modules "$pkg"*.s
+done
 echo
 
 # run test modules
 echo "==== Running test modules ======="
 export OXLR_MODULE_PATH=/tmp/oxlr_test_modules
 export RUST_LOG=info
-find -type f -name "*.s" -printf "%f\n" | cut -d '.' -f -1 \
-    | xargs -n 1 -- $VM
+# the exit code of each module is kept, and its optimized and compiled versions must exit with the
+# same code
+declare -A exit_codes
+for mod in $(find -maxdepth 1 -type f -name "*.s" -printf "%f\n" | cut -d '.' -f -1); do
+    code=0
+    $VM "$mod" || code=$?
+    exit_codes[$mod]=$code
+done
+# modules under lazy/ are run with imports loaded only when they are first used
+find lazy -type f -name "*.s" -printf "%f\n" | cut -d '.' -f -1 \
+    | xargs -n 1 -- $VM --lazy
+# start_args returns its first argument minus 42, after checking that std::env has the same
+# arguments
+expect_exit 0 $VM start_args 42 and more
+expect_exit 3 $VM start_args 45
+# bad command lines are reported like errors in the program
+expect_vm_error "unknown flag --no-such-flag" --no-such-flag start_args
+expect_vm_error "parse JIT threshold" --jit-threshold=many start_args
+
+# a lockfile records the modules a program loaded, and runs with it must load exactly the same
+# files. A changed copy of a locked module is skipped if there is an unchanged one elsewhere in the
+# search path, and rejected otherwise
+echo "==== Checking lockfiles ===="
+$VM --write-lockfile=/tmp/oxlr_test.lock resolve_versions
+$VM --locked=/tmp/oxlr_test.lock resolve_versions
+rm -rf /tmp/oxlr_tampered && mkdir -p /tmp/oxlr_tampered
+cp /tmp/oxlr_test_modules/resolve_*.om /tmp/oxlr_tampered
+echo >> "/tmp/oxlr_tampered/resolve_shared#1.1.0.om"
+$VM -L /tmp/oxlr_tampered --locked=/tmp/oxlr_test.lock resolve_versions
+OXLR_MODULE_PATH=/tmp/oxlr_tampered expect_vm_error "does not match the lockfile" \
+    --locked=/tmp/oxlr_test.lock resolve_versions
+
+# cycle_a and cycle_b import each other, which is only allowed with --allow-import-cycles, even
+# when the cycle is only found once cycle_b is loaded on first use
+echo "==== Checking import graphs ===="
+expect_vm_error "circular import" cycle_a
+expect_vm_error "circular import" --lazy cycle_a
+expect_error "note: use --allow-import-cycles to load it anyway" $VM cycle_a
+$VM --allow-import-cycles cycle_a
+$VM --lazy --allow-import-cycles cycle_a
+$VM --print-deps resolve_versions | grep -F "resolve_shared >=1.0, <1.2 -> resolve_shared v1.1.0" >/dev/null \
+    || { echo "--print-deps does not show resolve_shared v1.1.0 loaded for resolve_b"; exit 1; }
+
+# the modules under invalid/ have code that the verifier must refuse, which is checked for each
+# function that has it
+echo "==== Checking invalid modules ===="
+mkdir -p /tmp/oxlr_invalid
+expect_error "stack_escapes::in_array block 0 instruction 3: reference to the stack allocation in register 0" \
+    $ASM invalid/stack_escapes.s /tmp/oxlr_invalid
+expect_error "stack_escapes::loaded_back block 0 instruction 4: reference to the stack allocation in register 0" \
+    $ASM invalid/stack_escapes.s /tmp/oxlr_invalid
+expect_error "stack_escapes::leak block 0 instruction 2: reference to the stack allocation in register 0 (made at block 0 instruction 0) is returned" \
+    $ASM invalid/stack_escapes.s /tmp/oxlr_invalid
+expect_error "stack_escapes::kept block 0 instruction 2: reference to the stack allocation in register 1 (made at block 0 instruction 1) is passed to stack_escapes::keep, which lets it escape" \
+    $ASM invalid/stack_escapes.s /tmp/oxlr_invalid
+expect_error "int_width::start block 0 instruction 0: integer literal has unsupported width 0" \
+    $ASM invalid/int_width.s /tmp/oxlr_invalid
+
+# the modules under reload/ are newer versions of the same module, which the reload example adds
+# to the search path of a running world one at a time
+echo "==== Reloading modules ===="
+rm -rf /tmp/oxlr_reload && mkdir -p /tmp/oxlr_reload/newer
+$ASM reload/reloaded@1.0.0.s /tmp/oxlr_reload
+$ASM reload/reloaded@1.1.0.s /tmp/oxlr_reload/newer
+$ASM reload/reloaded@1.2.0.s /tmp/oxlr_reload/newer
+RUST_LOG=warn cargo run -q -p vm --example reload -- /tmp/oxlr_reload \
+    "/tmp/oxlr_reload/newer/reloaded#1.1.0.om" "/tmp/oxlr_reload/newer/reloaded#1.2.0.om"
+
+# unload_user imports one submodule of unload_tree, so the unload example must unload its sibling
+# and keep the rest of the tree
+echo "==== Unloading modules ===="
+rm -rf /tmp/oxlr_unload && mkdir -p /tmp/oxlr_unload
+find unload -type f -name "*.s" | xargs -I {} -- $ASM {} /tmp/oxlr_unload
+RUST_LOG=warn cargo run -q -p vm --example unload -- /tmp/oxlr_unload
+
+# every module and package is also optimized and run again, and must exit with the same code. The
+# optimized modules are found first, and everything else they import is the same as before
+echo "==== Running optimized test modules ===="
+mkdir -p /tmp/oxlr_opt_modules
+find -maxdepth 1 -type f -name "*.s" | xargs -I {} -- $OPT {} /tmp/oxlr_opt_modules
+for pkg in /tmp/oxlr_test_modules/*.opk; do
+    $OPT "$pkg" /tmp/oxlr_opt_modules
+done
+for mod in "${!exit_codes[@]}"; do
+    expect_exit "${exit_codes[$mod]}" $VM -L /tmp/oxlr_opt_modules "$mod"
+done
+
+# functions are specialized into new versions of their modules, and the modules under
+# specialized/ call the specializations
+echo "==== Specializing test modules ===="
+mkdir -p /tmp/oxlr_peval_modules
+$PEVAL --as=poly3 peval.s poly n=3 /tmp/oxlr_peval_modules
+find specialized -type f -name "*.s" | xargs -I {} -- $ASM {} /tmp/oxlr_peval_modules
+find specialized -type f -name "*.s" -printf "%f\n" | cut -d '.' -f -1 \
+    | xargs -n 1 -- $VM -L /tmp/oxlr_peval_modules
+
+# modules that only use what compiled code supports are also compiled to executables, which must
+# exit with the value that start returns in the VM
+echo "==== Compiling test modules ahead of time ===="
+mkdir -p /tmp/oxlr_aot
+for mod in aot array_alloc basic_struct looping overflow phi_swap rec_call ref_to_inner_element; do
+    $AOT -o "/tmp/oxlr_aot/$mod" "$mod"
+    expect_exit "${exit_codes[$mod]}" "/tmp/oxlr_aot/$mod"
+done
@@ -0,0 +1,27 @@
+Module(
+    path: Path([Symbol("peval_use")]),
+    version: "0.1.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // peval::poly3 is peval::poly specialized for n = 3 by oxlr-peval, so it computes x^3 + 55
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("peval"), Symbol("poly3")]), [ LiteralInt(Integer(width: 64, signed: false, data: 4)) ]),
+                            BinaryOp(Sub, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 119))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [ (Path([Symbol("peval")]), "^0.2") ]
+)
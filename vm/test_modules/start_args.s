@@ -0,0 +1,61 @@
+Module(
+    path: Path([Symbol("start_args")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            // start can take the program arguments, which are also available from std::env. It
+            // returns its first argument minus 42, or 0 if there are no arguments
+            FunctionSignature(args: [(Array(Array(Int(width: 8, signed: false))), Symbol("args"))], return_type: Int(width: 64, signed: true)),
+            FnBody(
+                max_registers: 8,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(1), Path([Symbol("std"), Symbol("env"), Symbol("arg_count")]), []),
+                            BinaryOp(Eq, Register(2), Reg(Register(1)), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Br(cond: Reg(Register(2)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: true, data: 0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            LoadIndex(Register(3), Register(0), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Call(Register(4), Path([Symbol("std"), Symbol("env"), Symbol("arg")]), [LiteralInt(Integer(width: 64, signed: false, data: 0))]),
+                            Call(Register(5), Path([Symbol("std"), Symbol("string"), Symbol("eq")]), [Reg(Register(3)), Reg(Register(4))]),
+                            Br(cond: Reg(Register(5)), if_true: 3, if_false: 4)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(6), Path([Symbol("std"), Symbol("string"), Symbol("parse_int")]), [Reg(Register(3))]),
+                            BinaryOp(Sub, Register(7), Reg(Register(6)), LiteralInt(Integer(width: 64, signed: true, data: 42))),
+                            Return(Reg(Register(7)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            // the array passed to start has different arguments from std::env
+                            Return(LiteralInt(Integer(width: 64, signed: true, data: 18446744073709551615)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("std"), Symbol("env")]), "^0.1"),
+        (Path([Symbol("std"), Symbol("string")]), "^0.1")
+    ]
+)
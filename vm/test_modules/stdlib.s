@@ -0,0 +1,73 @@
+Module(
+    path: Path([Symbol("stdlib")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 15,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("std"), Symbol("string"), Symbol("concat")]), [LiteralString("hello "), LiteralString("from the standard library")]),
+                            Call(Register(1), Path([Symbol("std"), Symbol("io"), Symbol("println")]), [Reg(Register(0))]),
+                            // 42 is converted with the ToString implementation for u64, giving a string of length 2
+                            CallImpl(Register(2), Path([Symbol("std"), Symbol("string"), Symbol("ToString"), Symbol("to_string")]), [LiteralInt(Integer(width: 64, signed: false, data: 42))]),
+                            Call(Register(3), Path([Symbol("std"), Symbol("string"), Symbol("len")]), [Reg(Register(2))]),
+                            Call(Register(4), Path([Symbol("std"), Symbol("collections"), Symbol("list_new")]), []),
+                            Call(Register(5), Path([Symbol("std"), Symbol("collections"), Symbol("list_push")]), [Reg(Register(4)), Reg(Register(3))]),
+                            Call(Register(5), Path([Symbol("std"), Symbol("collections"), Symbol("list_push")]), [Reg(Register(4)), LiteralInt(Integer(width: 64, signed: false, data: 40))]),
+                            Call(Register(6), Path([Symbol("std"), Symbol("collections"), Symbol("list_get")]), [Reg(Register(4)), LiteralInt(Integer(width: 64, signed: false, data: 1))]),
+                            BinaryOp(Add, Register(7), Reg(Register(6)), Reg(Register(3))),
+                            BinaryOp(Sub, Register(8), Reg(Register(7)), LiteralInt(Integer(width: 64, signed: false, data: 42))),
+                            Call(Register(9), Path([Symbol("std"), Symbol("math"), Symbol("sqrt")]), [LiteralFloat(F64(16.0))]),
+                            BinaryOp(Eq, Register(10), Reg(Register(9)), LiteralFloat(F64(4.0))),
+                            Call(Register(5), Path([Symbol("std"), Symbol("collections"), Symbol("list_free")]), [Reg(Register(4))]),
+                            Call(Register(11), Path([Symbol("std"), Symbol("collections"), Symbol("map_new")]), []),
+                            Call(Register(5), Path([Symbol("std"), Symbol("collections"), Symbol("map_free")]), [Reg(Register(11))]),
+                            Br(cond: Reg(Register(10)), if_true: 3, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(8)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    // the list was freed, so its handle is no longer valid and using it fails with
+                    // the native error trap
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(12), func: Path([Symbol("std"), Symbol("collections"), Symbol("list_len")]), args: [Reg(Register(4))],
+                                normal: 2, unwind: 4, exception: Register(13))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(14), Reg(Register(13)), LiteralInt(Integer(width: 32, signed: false, data: 9))),
+                            Br(cond: Reg(Register(14)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("std"), Symbol("io")]), "^0.1"),
+        (Path([Symbol("std"), Symbol("string")]), "^0.1"),
+        (Path([Symbol("std"), Symbol("math")]), "^0.1"),
+        (Path([Symbol("std"), Symbol("collections")]), "^0.1")
+    ]
+)
@@ -0,0 +1,76 @@
+Module(
+    path: Path([Symbol("submodules")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("offset"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 0,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 7)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            // calls into the submodule using a path relative to this module
+                            Call(Register(0), Path([Symbol("self"), Symbol("math"), Symbol("square_plus_offset")]),
+                                [ LiteralInt(Integer(width: 64, signed: false, data: 3)) ]),
+                            BinaryOp(Sub, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 16))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [],
+    submodules: [
+        Module(
+            path: Path([Symbol("self"), Symbol("math")]),
+            version: "0.0.1",
+            types: {},
+            interfaces: {},
+            implementations: {},
+            functions: {
+                Symbol("square_plus_offset"): (
+                    FunctionSignature(args: [
+                            (Int(width: 64, signed: false), Symbol("x"))
+                        ],
+                        return_type: Int(width: 64, signed: false)
+                    ),
+                    FnBody(
+                        max_registers: 4,
+                        blocks: [
+                            BasicBlock(
+                                instrs: [
+                                    BinaryOp(Mul, Register(1), Reg(Register(0)), Reg(Register(0))),
+                                    // calls back up into the parent module
+                                    Call(Register(2), Path([Symbol("super"), Symbol("offset")]), []),
+                                    BinaryOp(Add, Register(3), Reg(Register(1)), Reg(Register(2))),
+                                    Return(Reg(Register(3)))
+                                ],
+                                next_block: 0
+                            )
+                        ]
+                    )
+                )
+            },
+            imports: []
+        )
+    ]
+)
@@ -0,0 +1,98 @@
+Module(
+    path: Path([Symbol("tail_calls")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        // counts n down to zero with tail calls, much deeper than the call stack could hold.
+        // each call makes a stack allocation, which is released by the next tail call
+        Symbol("count"): (
+            FunctionSignature(args: [
+                    (Int(width: 64, signed: false), Symbol("n")),
+                    (Int(width: 64, signed: false), Symbol("acc"))
+                ],
+                return_type: Int(width: 64, signed: false)
+            ),
+            FnBody(
+                max_registers: 6,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(2), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 0))),
+                            Br(cond: Reg(Register(2)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            StackAllocArray(Register(3), Int(width: 64, signed: false), LiteralInt(Integer(width: 64, signed: false, data: 4))),
+                            BinaryOp(Sub, Register(4), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            BinaryOp(Add, Register(5), Reg(Register(1)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            TailCall(Path([Symbol("tail_calls"), Symbol("count")]), [ Reg(Register(4)), Reg(Register(5)) ])
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        // a tail call to an interface function, which is implemented natively
+        Symbol("describe"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")) ], return_type: Array(Int(width: 8, signed: false))),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            TailCallImpl(Path([Symbol("std"), Symbol("string"), Symbol("ToString"), Symbol("to_string")]), [ Reg(Register(0)) ])
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 5,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("tail_calls"), Symbol("count")]), [
+                                LiteralInt(Integer(width: 64, signed: false, data: 100000)),
+                                LiteralInt(Integer(width: 64, signed: false, data: 0))
+                            ]),
+                            Call(Register(1), Path([Symbol("tail_calls"), Symbol("describe")]), [ Reg(Register(0)) ]),
+                            Call(Register(2), Path([Symbol("std"), Symbol("string"), Symbol("len")]), [ Reg(Register(1)) ]),
+                            BinaryOp(Eq, Register(3), Reg(Register(2)), LiteralInt(Integer(width: 64, signed: false, data: 6))),
+                            Br(cond: Reg(Register(3)), if_true: 1, if_false: 2)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("std"), Symbol("string")]), "^0.1")
+    ],
+    submodules: []
+)
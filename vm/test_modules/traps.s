@@ -0,0 +1,303 @@
+Module(
+    path: Path([Symbol("traps")]),
+    version: "0.0.1",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("add_u8"): (
+            FunctionSignature(args: [ (Int(width: 8, signed: false), Symbol("x")), (Int(width: 8, signed: false), Symbol("y")) ], return_type: Int(width: 8, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("add_mixed"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")), (Int(width: 32, signed: false), Symbol("y")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("divide"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Div, Register(1), LiteralInt(Integer(width: 64, signed: false, data: 10)), Reg(Register(0))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("index"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("i")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            AllocArray(Register(1), Int(width: 64, signed: false), LiteralInt(Integer(width: 64, signed: false, data: 3))),
+                            LoadIndex(Register(2), Register(1), Reg(Register(0))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("invoke_deeper"): (
+            // invokes itself with n + 1 until the call stack is full, and returns the n of the
+            // call that could not make another one
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("n")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 5,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Add, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                            Invoke(dest: Register(2), func: Path([Symbol("traps"), Symbol("invoke_deeper")]),
+                                args: [ Reg(Register(1)) ], normal: 1, unwind: 2, exception: Register(3))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(4), Reg(Register(3)), LiteralInt(Integer(width: 32, signed: false, data: 5))),
+                            Br(cond: Reg(Register(4)), if_true: 3, if_false: 4)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("neg_i8"): (
+            FunctionSignature(args: [ (Int(width: 8, signed: true), Symbol("x")) ], return_type: Int(width: 8, signed: true)),
+            FnBody(
+                max_registers: 2,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            UnaryOp(Neg, Register(1), Reg(Register(0))),
+                            Return(Reg(Register(1)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("recurse"): (
+            FunctionSignature(args: [], return_type: Unit),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("traps"), Symbol("recurse")]), []),
+                            Return(LiteralUnit)
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("sub"): (
+            FunctionSignature(args: [ (Int(width: 64, signed: false), Symbol("x")), (Int(width: 64, signed: false), Symbol("y")) ], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 3,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Sub, Register(2), Reg(Register(0)), Reg(Register(1))),
+                            Return(Reg(Register(2)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        ),
+        Symbol("start"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 23,
+                blocks: [
+                    // dividing by zero traps with code 1
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(0), func: Path([Symbol("traps"), Symbol("divide")]),
+                                args: [ LiteralInt(Integer(width: 64, signed: false, data: 0)) ],
+                                normal: 1, unwind: 2, exception: Register(1))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 1)))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(2), Reg(Register(1)), LiteralInt(Integer(width: 32, signed: false, data: 1))),
+                            Br(cond: Reg(Register(2)), if_true: 3, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // indexing past the end of an array traps with code 3
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(3), func: Path([Symbol("traps"), Symbol("index")]),
+                                args: [ LiteralInt(Integer(width: 64, signed: false, data: 3)) ],
+                                normal: 1, unwind: 4, exception: Register(4))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(5), Reg(Register(4)), LiteralInt(Integer(width: 32, signed: false, data: 3))),
+                            Br(cond: Reg(Register(5)), if_true: 5, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // unbounded recursion traps with code 5 when the call stack is full
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(6), func: Path([Symbol("traps"), Symbol("recurse")]),
+                                args: [], normal: 1, unwind: 6, exception: Register(7))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(8), Reg(Register(7)), LiteralInt(Integer(width: 32, signed: false, data: 5))),
+                            Br(cond: Reg(Register(8)), if_true: 7, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // adding past the largest 8 bit integer traps with code 2, even though the sum
+                    // fits in 64 bits
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(9), func: Path([Symbol("traps"), Symbol("add_u8")]),
+                                args: [ LiteralInt(Integer(width: 8, signed: false, data: 200)), LiteralInt(Integer(width: 8, signed: false, data: 100)) ],
+                                normal: 1, unwind: 8, exception: Register(10))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(11), Reg(Register(10)), LiteralInt(Integer(width: 32, signed: false, data: 2))),
+                            Br(cond: Reg(Register(11)), if_true: 9, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // subtracting below zero traps with code 2
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(12), func: Path([Symbol("traps"), Symbol("sub")]),
+                                args: [ LiteralInt(Integer(width: 64, signed: false, data: 1)), LiteralInt(Integer(width: 64, signed: false, data: 2)) ],
+                                normal: 1, unwind: 10, exception: Register(13))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(14), Reg(Register(13)), LiteralInt(Integer(width: 32, signed: false, data: 2))),
+                            Br(cond: Reg(Register(14)), if_true: 11, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // an Invoke that cannot push a frame because the call stack is full handles the
+                    // trap itself. start and the calls to invoke_deeper with n up to 99999 fill the
+                    // default maximum depth of 100000, so the last of them catches it
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(15), Path([Symbol("traps"), Symbol("invoke_deeper")]), [ LiteralInt(Integer(width: 64, signed: false, data: 1)) ]),
+                            BinaryOp(Eq, Register(16), Reg(Register(15)), LiteralInt(Integer(width: 64, signed: false, data: 99999))),
+                            Br(cond: Reg(Register(16)), if_true: 12, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // negating the smallest 8 bit integer traps with code 2, since 128 does not fit
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(17), func: Path([Symbol("traps"), Symbol("neg_i8")]),
+                                args: [ LiteralInt(Integer(width: 8, signed: true, data: 18446744073709551488)) ],
+                                normal: 1, unwind: 13, exception: Register(18))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(19), Reg(Register(18)), LiteralInt(Integer(width: 32, signed: false, data: 2))),
+                            Br(cond: Reg(Register(19)), if_true: 14, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    // adding integers of different widths is invalid code, which traps with code 10
+                    BasicBlock(
+                        instrs: [
+                            Invoke(dest: Register(20), func: Path([Symbol("traps"), Symbol("add_mixed")]),
+                                args: [ LiteralInt(Integer(width: 64, signed: false, data: 1)), LiteralInt(Integer(width: 32, signed: false, data: 2)) ],
+                                normal: 1, unwind: 15, exception: Register(21))
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            BinaryOp(Eq, Register(22), Reg(Register(21)), LiteralInt(Integer(width: 32, signed: false, data: 10))),
+                            Br(cond: Reg(Register(22)), if_true: 16, if_false: 1)
+                        ],
+                        next_block: 0
+                    ),
+                    BasicBlock(
+                        instrs: [
+                            Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [],
+    submodules: []
+)
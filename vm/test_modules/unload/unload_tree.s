@@ -0,0 +1,102 @@
+Module(
+    path: Path([Symbol("unload_tree")]),
+    version: "1.0.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {},
+    imports: [],
+    submodules: [
+        // imported by unload_user, so it stays loaded along with its parent, and with its own
+        // submodule that it calls without importing
+        Module(
+            path: Path([Symbol("self"), Symbol("used")]),
+            version: "1.0.0",
+            types: {},
+            interfaces: {},
+            implementations: {},
+            functions: {
+                Symbol("value"): (
+                    FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+                    FnBody(
+                        max_registers: 2,
+                        blocks: [
+                            BasicBlock(
+                                instrs: [
+                                    Call(Register(0), Path([Symbol("self"), Symbol("inner"), Symbol("value")]), []),
+                                    BinaryOp(Add, Register(1), Reg(Register(0)), LiteralInt(Integer(width: 64, signed: false, data: 1))),
+                                    Return(Reg(Register(1)))
+                                ],
+                                next_block: 0
+                            )
+                        ]
+                    )
+                )
+            },
+            imports: [],
+            submodules: [
+                Module(
+                    path: Path([Symbol("self"), Symbol("inner")]),
+                    version: "1.0.0",
+                    types: {},
+                    interfaces: {},
+                    implementations: {},
+                    functions: {
+                        Symbol("value"): (
+                            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+                            FnBody(
+                                max_registers: 0,
+                                blocks: [
+                                    BasicBlock(
+                                        instrs: [
+                                            Return(LiteralInt(Integer(width: 64, signed: false, data: 41)))
+                                        ],
+                                        next_block: 0
+                                    )
+                                ]
+                            )
+                        )
+                    },
+                    imports: []
+                )
+            ]
+        ),
+        // nothing imports or calls this sibling of used, so it is unloaded along with its own
+        // submodule
+        Module(
+            path: Path([Symbol("self"), Symbol("unused")]),
+            version: "1.0.0",
+            types: {},
+            interfaces: {},
+            implementations: {},
+            functions: {
+                Symbol("value"): (
+                    FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+                    FnBody(
+                        max_registers: 0,
+                        blocks: [
+                            BasicBlock(
+                                instrs: [
+                                    Return(LiteralInt(Integer(width: 64, signed: false, data: 0)))
+                                ],
+                                next_block: 0
+                            )
+                        ]
+                    )
+                )
+            },
+            imports: [],
+            submodules: [
+                Module(
+                    path: Path([Symbol("self"), Symbol("inner")]),
+                    version: "1.0.0",
+                    types: {},
+                    interfaces: {},
+                    implementations: {},
+                    functions: {},
+                    imports: []
+                )
+            ]
+        )
+    ]
+)
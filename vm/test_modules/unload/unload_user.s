@@ -0,0 +1,28 @@
+Module(
+    path: Path([Symbol("unload_user")]),
+    version: "1.0.0",
+    types: {},
+    interfaces: {},
+    implementations: {},
+    functions: {
+        Symbol("value"): (
+            FunctionSignature(args: [], return_type: Int(width: 64, signed: false)),
+            FnBody(
+                max_registers: 1,
+                blocks: [
+                    BasicBlock(
+                        instrs: [
+                            Call(Register(0), Path([Symbol("unload_tree"), Symbol("used"), Symbol("value")]), []),
+                            Return(Reg(Register(0)))
+                        ],
+                        next_block: 0
+                    )
+                ]
+            )
+        )
+    },
+    imports: [
+        (Path([Symbol("unload_tree"), Symbol("used")]), "^1.0")
+    ],
+    submodules: []
+)